@@ -10,6 +10,9 @@ class InteractiveTerminal {
         this.currentSessionId = null;
         this.api = null;
         this.pollInterval = null;
+        this.unlistenOutput = null;
+        this.unlistenExit = null;
+        this.lastSeq = -1;
         this.lastTimestamp = 0;
         this.isOpen = false;
         this.pendingClose = false;
@@ -193,18 +196,23 @@ class InteractiveTerminal {
             this.lastTimestamp = 0;
 
             const payload = {
+                session_id: this._generateSessionId(),
                 shell: undefined,
                 cwd: cwd && cwd !== '~' ? cwd : undefined,
                 cols: 120,
                 rows: 32,
             };
 
+            this.setupTerminal();
+
+            // Il listener va registrato prima della creazione per non perdere il prompt iniziale
+            this.currentSessionId = payload.session_id;
+            const streaming = await this.startStreaming();
+
             const sessionId = await api.invoke('pty_create_session', { payload });
             console.log('Interactive PTY session created:', sessionId);
             this.currentSessionId = sessionId;
 
-            this.setupTerminal();
-
             await api.invoke('pty_write', {
                 payload: {
                     session_id: sessionId,
@@ -212,7 +220,9 @@ class InteractiveTerminal {
                 },
             });
 
-            this.startPolling();
+            if (!streaming) {
+                this.startPolling();
+            }
         } catch (error) {
             console.error('Error opening interactive terminal:', error);
             this.showError('Errore nell\'apertura del terminale interattivo: ' + error.message);
@@ -429,6 +439,67 @@ class InteractiveTerminal {
         console.log('Terminal setup complete');
     }
 
+    _generateSessionId() {
+        if (window.crypto && typeof window.crypto.randomUUID === 'function') {
+            return window.crypto.randomUUID();
+        }
+        return `interactive-${Date.now()}-${Math.random().toString(16).slice(2)}`;
+    }
+
+    async startStreaming() {
+        this.stopStreaming();
+        const listen = this.api?.event?.listen;
+        if (typeof listen !== 'function') {
+            console.warn('Tauri event API unavailable, falling back to polling');
+            return false;
+        }
+
+        this.lastSeq = -1;
+        this.terminal.write('\x1b[2J\x1b[H');
+        try {
+            this.unlistenOutput = await listen('pty-output', (event) => {
+                const chunk = event?.payload;
+                if (!chunk || chunk.session_id !== this.currentSessionId || !this.terminal) {
+                    return;
+                }
+                if (chunk.seq <= this.lastSeq) {
+                    return;
+                }
+                this.lastSeq = chunk.seq;
+                this.terminal.write(chunk.data);
+            });
+            this.unlistenExit = await listen('pty-exited', (event) => {
+                const payload = event?.payload;
+                if (!payload || payload.session_id !== this.currentSessionId || !this.terminal) {
+                    return;
+                }
+                this.terminal.write(`\r\n[${this._describeExit(payload.exit_status)}]\r\n`);
+            });
+            return true;
+        } catch (error) {
+            console.warn('Failed to subscribe to PTY output, falling back to polling:', error);
+            return false;
+        }
+    }
+
+    stopStreaming() {
+        if (this.unlistenOutput) {
+            try { this.unlistenOutput(); } catch (_) {}
+            this.unlistenOutput = null;
+        }
+        if (this.unlistenExit) {
+            try { this.unlistenExit(); } catch (_) {}
+            this.unlistenExit = null;
+        }
+    }
+
+    _describeExit(status) {
+        if (status?.signal) {
+            return `process killed by ${status.signal}`;
+        }
+        return `process exited ${status?.exit_code ?? '?'}`;
+    }
+
     startPolling() {
         if (!this.currentSessionId || !this.api) {
             console.warn('Cannot start polling: session or API missing');
@@ -489,6 +560,7 @@ class InteractiveTerminal {
 
         this.pendingClose = true;
         this.stopPolling();
+        this.stopStreaming();
 
         const sessionId = this.currentSessionId;
         this.currentSessionId = null;
@@ -3,6 +3,8 @@ class PTYTerminal {
         console.log('PTYTerminal: Constructor called');
         this.terminal = terminalInstance;
         this.sessionId = null;
+        // 'local' per le shell su questa macchina (anche tramite termina-muxd)
+        this.backend = null;
         this.isActive = false;
         this.outputBuffer = '';
         this.lastOutputIndex = 0;
@@ -39,6 +41,7 @@ class PTYTerminal {
                 payload: {},
             });
             this.sessionId = sessionId;
+            this.backend = 'local';
             this.isActive = true;
             this.startDataPolling();
             console.log(`PTY session started: ${this.sessionId}, isActive: ${this.isActive}`);
@@ -62,6 +65,7 @@ class PTYTerminal {
                 this.stopDataPolling();
                 this.isActive = false;
                 this.sessionId = null;
+                this.backend = null;
                 console.log('PTY session stopped');
             }
         } catch (error) {
@@ -134,6 +138,7 @@ class PTYTerminal {
                     },
                 });
                 this.sessionId = null;
+                this.backend = null;
                 this.isActive = false;
                 console.log('PTYTerminal: Session closed');
             } catch (error) {
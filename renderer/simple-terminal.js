@@ -1500,8 +1500,37 @@ class SimpleTerminal {
         this.setupConfigListeners();
 
         // Listener aggiornamento cwd
-            // CWD updates are handled through events in Tauri v2
-            // This will be implemented through the backend event system
+        this.setupCwdListener();
+    }
+
+    setupCwdListener() {
+        // Le sessioni PTY segnalano la directory reale (OSC 7 o /proc) con `pty-cwd-changed`:
+        // prompt, contesto AI e nuovi terminali seguono l'ultima directory visitata.
+        // Conta solo la sessione di questa vista, e solo se la shell è locale: la
+        // directory di un host SSH non esiste su questa macchina
+        const attach = async () => {
+            try {
+                const tauriAPI = await this._getApi();
+                if (typeof tauriAPI?.event?.listen !== 'function') {
+                    return;
+                }
+                await tauriAPI.event.listen('pty-cwd-changed', (evt) => {
+                    const pty = this.ptyTerminal;
+                    const payload = evt?.payload;
+                    if (!pty?.sessionId || pty.backend !== 'local' || payload?.session_id !== pty.sessionId) {
+                        return;
+                    }
+                    const cwd = payload.cwd;
+                    if (typeof cwd === 'string' && cwd && cwd !== this.cwd) {
+                        this.cwd = cwd;
+                        this.renderPrompt();
+                    }
+                });
+            } catch (error) {
+                console.warn('Failed to listen for PTY cwd changes:', error);
+            }
+        };
+        attach();
     }
 
     setupConfigListeners() {
@@ -0,0 +1,74 @@
+#!/usr/bin/env node
+
+import { fileURLToPath } from 'node:url';
+import { dirname, resolve, join } from 'node:path';
+import { execFileSync } from 'node:child_process';
+import { copyFile, mkdir, stat, writeFile } from 'node:fs/promises';
+
+const __dirname = dirname(fileURLToPath(import.meta.url));
+const projectRoot = resolve(__dirname, '..');
+const tauriDir = join(projectRoot, 'src-tauri');
+const binariesDir = join(tauriDir, 'binaries');
+
+// Tauri cerca i sidecar come `binaries/termina-muxd-<target triple>`
+function targetTriple() {
+  const index = process.argv.indexOf('--target');
+  if (index !== -1 && process.argv[index + 1]) {
+    return { triple: process.argv[index + 1], explicit: true };
+  }
+  const host = execFileSync('rustc', ['-vV'], { encoding: 'utf8' })
+    .split('\n')
+    .find((line) => line.startsWith('host:'));
+  if (!host) {
+    throw new Error('rustc -vV non riporta il target host');
+  }
+  return { triple: host.slice('host:'.length).trim(), explicit: false };
+}
+
+async function exists(path) {
+  try {
+    await stat(path);
+    return true;
+  } catch (err) {
+    if (err.code === 'ENOENT') {
+      return false;
+    }
+    throw err;
+  }
+}
+
+async function main() {
+  const { triple, explicit } = targetTriple();
+  // Il demone usa socket Unix: su Windows le sessioni restano nell'applicazione
+  if (triple.includes('windows')) {
+    console.log(`⏭️  termina-muxd is not supported on ${triple}, skipping.`);
+    return;
+  }
+  const sidecar = join(binariesDir, `termina-muxd-${triple}`);
+  console.log(`🛠️  Building termina-muxd sidecar for ${triple} ...`);
+
+  // tauri-build rifiuta di compilare il crate se il sidecar non esiste
+  // ancora: un segnaposto vuoto basta per il primo build del demone
+  await mkdir(binariesDir, { recursive: true });
+  if (!(await exists(sidecar))) {
+    await writeFile(sidecar, '');
+  }
+
+  const args = ['build', '--release', '--bin', 'termina-muxd'];
+  if (explicit) {
+    args.push('--target', triple);
+  }
+  execFileSync('cargo', args, { cwd: tauriDir, stdio: 'inherit' });
+
+  const built = explicit
+    ? join(tauriDir, 'target', triple, 'release', 'termina-muxd')
+    : join(tauriDir, 'target', 'release', 'termina-muxd');
+  await copyFile(built, sidecar);
+
+  console.log(`✅ termina-muxd copied to ${sidecar}.`);
+}
+
+main().catch((error) => {
+  console.error('❌ Failed to build termina-muxd sidecar:', error);
+  process.exitCode = 1;
+});
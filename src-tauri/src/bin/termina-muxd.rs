@@ -0,0 +1,43 @@
+//! `termina-muxd`: demone che possiede le sessioni PTY
+//!
+//! L'applicazione vi si collega tramite socket Unix (`terminal.use_muxd`);
+//! le shell sopravvivono alla chiusura o al crash dell'interfaccia e possono
+//! essere ricollegate con tutto lo scrollback.
+
+// Il demone usa solo una parte dei moduli PTY condivisi con l'applicazione
+#![allow(dead_code)]
+
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[path = "../pty/mod.rs"]
+mod pty;
+
+#[cfg(unix)]
+fn main() {
+    env_logger::init();
+
+    let mut socket_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => socket_path = args.next().map(PathBuf::from),
+            _ => {
+                eprintln!("Usage: termina-muxd [--socket <path>]");
+                std::process::exit(2);
+            }
+        }
+    }
+    let socket_path = socket_path.unwrap_or_else(pty::mux::default_socket_path);
+
+    if let Err(e) = pty::mux::server::run(&socket_path) {
+        log::error!("termina-muxd failed: {e:#}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("termina-muxd is only supported on Unix");
+    std::process::exit(1);
+}
@@ -67,6 +67,16 @@ impl ConfigManager {
                 "cursor_style": "block",
                 "cursor_blink": true,
                 "scrollback": 4000,
+                "shell_integration": true,
+                "close_grace_period_ms": 2000,
+                "exited_session_grace_secs": 300,
+                "idle_timeout_secs": 0,
+                "close_confirm_ignore": ["less", "more", "man", "top", "htop"],
+                "restore_sessions": false,
+                "use_muxd": false,
+                "default_profile": "",
+                "profiles": {},
+                "ssh_profiles": {},
                 "bell_sound": false,
                 "auto_scroll": true,
                 "smooth_scroll": true
@@ -98,6 +108,13 @@ impl ConfigManager {
         self.data.clone()
     }
 
+    /// Legge un valore tramite un percorso puntato (es. `terminal.scrollback`)
+    pub fn get_value(&self, key_path: &str) -> Option<&Value> {
+        key_path
+            .split('.')
+            .try_fold(&self.data, |current, key| current.get(key))
+    }
+
     pub fn set_full_config(&mut self, value: Value) -> Result<()> {
         self.data = value;
         self.persist()
@@ -1,31 +1,60 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use dirs::{audio_dir, desktop_dir, document_dir, download_dir, home_dir, picture_dir, public_dir, video_dir};
 use reqwest::{Client, Url};
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tauri::{AppHandle, Emitter, Manager, State};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
 
 mod config_manager;
 mod pty;
 
 use crate::config_manager::ConfigManager;
-use crate::pty::pty_manager::PtyManager;
+use crate::pty::broadcast::BroadcastResult;
+use crate::pty::close_check::{CloseCheck, DEFAULT_IGNORED_PROCESSES};
+use crate::pty::events::SessionEvent;
+use crate::pty::export::ExportFormat;
+#[cfg(unix)]
+use crate::pty::mux::{self, client::MuxClient};
+use crate::pty::output::{OutputChunk, OutputEncoding};
+use crate::pty::persistence;
+use crate::pty::playback::PlaybackOptions;
+use crate::pty::process::ProcessInfo;
+use crate::pty::profiles::ShellProfile;
+use crate::pty::pty_manager::{self, ManagedSession, PtyManager};
+use crate::pty::reaper::{self, ReaperPolicy};
+use crate::pty::recording;
+use crate::pty::screen::ScreenSnapshot;
+use crate::pty::search::{SearchOptions, SearchResults};
+use crate::pty::serial::{self, SerialOptions, SerialPortEntry, SerialSession};
+use crate::pty::session::SessionStatus;
+use crate::pty::shell_integration::CommandRecord;
+use crate::pty::shutdown::ShutdownReport;
+use crate::pty::signals::{SessionSignal, SignalTarget};
+use crate::pty::ssh::{self, SshConnect, SshHost, SshOptions, SshPrompt};
 use crate::pty::PtyConfig;
 
 #[derive(Default, Deserialize)]
 struct PtyCreateSessionPayload {
     session_id: Option<String>,
+    /// Profilo di `terminal.profiles`; i campi espliciti hanno la precedenza
+    profile: Option<String>,
     cwd: Option<String>,
     cols: Option<u16>,
     rows: Option<u16>,
     shell: Option<String>,
+    args: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize)]
@@ -34,6 +63,58 @@ struct PtyWritePayload {
     input: String,
 }
 
+#[derive(Deserialize)]
+struct PtyDuplicatePayload {
+    source_id: String,
+    #[serde(default)]
+    session_id: Option<String>,
+    /// Eredita anche l'ambiente della shell da `/proc/<pid>/environ`
+    #[serde(default)]
+    inherit_env: bool,
+}
+
+#[derive(Deserialize)]
+struct PtyBroadcastWritePayload {
+    /// Gruppo di broadcast di destinazione
+    #[serde(default)]
+    group: Option<String>,
+    /// Sessioni aggiunte a quelle del gruppo
+    #[serde(default)]
+    session_ids: Vec<String>,
+    input: String,
+}
+
+#[derive(Deserialize)]
+struct PtyBroadcastGroupPayload {
+    name: String,
+    /// Nuovo elenco completo delle sessioni; sostituisce `add` e `remove`
+    #[serde(default)]
+    session_ids: Option<Vec<String>>,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PtyDeleteBroadcastGroupPayload {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct PtyWriteBytesPayload {
+    session_id: String,
+    /// Byte da scrivere, codificati in base64
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct PtyReadBytesPayload {
+    session_id: String,
+    #[serde(default)]
+    offset: u64,
+}
+
 #[derive(Deserialize)]
 struct PtyResizePayload {
     session_id: String,
@@ -49,8 +130,124 @@ struct PtyClosePayload {
 #[derive(Deserialize)]
 struct PtyImmediateOutputPayload {
     session_id: String,
+}
+
+#[derive(Deserialize)]
+struct PtyScreenPayload {
+    session_id: String,
+    #[serde(default)]
+    include_history: bool,
+}
+
+#[derive(Deserialize)]
+struct PtyCommandOutputPayload {
+    session_id: String,
+    command_id: u64,
+}
+
+#[derive(Deserialize)]
+struct PtySubscribePayload {
+    session_id: String,
+    #[serde(default)]
+    encoding: OutputEncoding,
+}
+
+#[derive(Deserialize)]
+struct PtyUnsubscribePayload {
+    session_id: String,
+    subscription_id: u64,
+}
+
+#[derive(Deserialize, Default)]
+struct PtyCloseCheckPayload {
+    /// Tutte le sessioni se assente
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PtySignalPayload {
+    session_id: String,
+    signal: SessionSignal,
+    /// Il gruppo in primo piano se assente
+    #[serde(default)]
+    target: SignalTarget,
+}
+
+#[derive(Deserialize)]
+struct PtyExportPayload {
+    session_id: String,
+    #[serde(default)]
+    format: ExportFormat,
+    /// Solo gli ultimi N comandi; tutto lo scrollback se assente
+    #[serde(default)]
+    last_commands: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct PtySearchPayload {
+    /// Sessione in cui cercare; tutte le sessioni aperte se assente
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(flatten)]
+    options: SearchOptions,
+}
+
+#[derive(Deserialize)]
+struct PtyRecordPayload {
+    session_id: String,
+    /// File `.cast` di destinazione; se assente viene scelto un nome
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    record_input: bool,
+}
+
+#[derive(Deserialize)]
+struct PtyPlaybackPayload {
+    path: String,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(flatten)]
+    options: PlaybackOptions,
+}
+
+#[derive(Deserialize)]
+struct PtyPlaybackSpeedPayload {
+    session_id: String,
+    speed: f64,
+}
+
+#[derive(Deserialize)]
+struct PtySshConnectPayload {
+    #[serde(default)]
+    session_id: Option<String>,
+    /// Profilo di `terminal.profiles`; se assente vale `terminal.ssh_profiles`
     #[serde(default)]
-    timestamp: Option<u64>,
+    profile: Option<String>,
+    #[serde(flatten)]
+    options: SshOptions,
+}
+
+/// Sessione aperta oppure richiesta da mostrare prima di riprovare
+#[derive(Serialize)]
+struct PtySshConnectResponse {
+    session_id: Option<String>,
+    prompt: Option<SshPrompt>,
+}
+
+#[derive(Default, Deserialize)]
+struct PtySshHostsPayload {
+    config_path: Option<PathBuf>,
+    known_hosts_path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct PtySerialOpenPayload {
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(flatten)]
+    options: SerialOptions,
 }
 
 #[derive(Deserialize)]
@@ -110,6 +307,40 @@ fn pty_create_session(
     let options = payload.unwrap_or_default();
 
     let mut config = PtyConfig::default();
+    {
+        let config_manager = state.config_manager.lock().unwrap();
+        if let Some(scrollback) = config_manager
+            .get_value("terminal.scrollback")
+            .and_then(Value::as_u64)
+        {
+            config.scrollback = scrollback as usize;
+        }
+        if let Some(enabled) = config_manager
+            .get_value("terminal.shell_integration")
+            .and_then(Value::as_bool)
+        {
+            config.shell_integration = enabled;
+        }
+        if let Some(grace_period) = config_manager
+            .get_value("terminal.close_grace_period_ms")
+            .and_then(Value::as_u64)
+        {
+            config.close_grace_period_ms = grace_period;
+        }
+
+        let profile = options.profile.clone().or_else(|| {
+            config_manager
+                .get_value("terminal.default_profile")
+                .and_then(Value::as_str)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+        });
+        if let Some(name) = profile {
+            ShellProfile::from_config(config_manager.get_value("terminal.profiles"), &name)
+                .map_err(|e| e.to_string())?
+                .apply(&mut config);
+        }
+    }
     if let Some(cwd) = options.cwd {
         config.cwd = cwd;
     }
@@ -122,6 +353,12 @@ fn pty_create_session(
     if let Some(shell) = options.shell {
         config.shell = shell;
     }
+    if let Some(args) = options.args {
+        config.args = args;
+    }
+    if let Some(env) = options.env {
+        config.env_vars.extend(env);
+    }
 
     let session_id = options
         .session_id
@@ -135,35 +372,161 @@ fn pty_create_session(
     Ok(session_id)
 }
 
+/// Sessione presa dal registro. Il lock del manager va rilasciato prima di
+/// usarla: con `termina-muxd` ogni chiamata è una richiesta al demone.
+fn pty_session(state: &AppState, session_id: &str) -> Result<Arc<ManagedSession>, String> {
+    state
+        .pty_manager
+        .lock()
+        .unwrap()
+        .session(session_id)
+        .map_err(|e| e.to_string())
+}
+
+/// La sessione indicata o, senza `session_id`, tutte
+fn pty_sessions(state: &AppState, session_id: Option<&str>) -> Result<Vec<Arc<ManagedSession>>, String> {
+    match session_id {
+        Some(session_id) => Ok(vec![pty_session(state, session_id)?]),
+        None => {
+            let session_ids = pty_session_ids(state);
+            Ok(state.pty_manager.lock().unwrap().sessions(&session_ids))
+        }
+    }
+}
+
+/// Id delle sessioni; con `termina-muxd` chiede al demone anche quelle
+/// create da altri client, senza tenere il lock del manager
+fn pty_session_ids(state: &AppState) -> Vec<String> {
+    #[cfg(unix)]
+    {
+        let client = state.pty_manager.lock().unwrap().mux_client();
+        if let Some(client) = client {
+            let remote_sessions = client.list_sessions().unwrap_or_else(|e| {
+                log::warn!("Failed to list muxd sessions: {e}");
+                Vec::new()
+            });
+            return state.pty_manager.lock().unwrap().merge_remote_sessions(remote_sessions);
+        }
+    }
+    state.pty_manager.lock().unwrap().list_sessions()
+}
+
+#[tauri::command]
+fn pty_duplicate_session(state: State<'_, AppState>, payload: PtyDuplicatePayload) -> Result<String, String> {
+    let session_id = payload
+        .session_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let config = pty_session(&state, &payload.source_id)?
+        .duplicate_config(payload.inherit_env)
+        .map_err(|e| e.to_string())?;
+    log::info!("Duplicating PTY session {} as {}", payload.source_id, session_id);
+    let mut manager = state.pty_manager.lock().unwrap();
+    manager
+        .create_session(session_id, config)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn pty_write(state: State<'_, AppState>, payload: PtyWritePayload) -> Result<(), String> {
+    pty_session(&state, &payload.session_id)?
+        .write(payload.input.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_broadcast_write(
+    state: State<'_, AppState>,
+    payload: PtyBroadcastWritePayload,
+) -> Result<Vec<BroadcastResult>, String> {
+    let targets = state
+        .pty_manager
+        .lock()
+        .unwrap()
+        .broadcast_targets(payload.group.as_deref(), &payload.session_ids)
+        .map_err(|e| e.to_string())?;
+    Ok(pty_manager::broadcast_write(targets, &payload.input))
+}
+
+#[tauri::command]
+fn pty_update_broadcast_group(
+    state: State<'_, AppState>,
+    payload: PtyBroadcastGroupPayload,
+) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let mut manager = state.pty_manager.lock().unwrap();
+    match &payload.session_ids {
+        Some(session_ids) => manager.set_broadcast_group(&payload.name, session_ids),
+        None => manager.edit_broadcast_group(&payload.name, &payload.add, &payload.remove),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(manager.broadcast_groups())
+}
+
+#[tauri::command]
+fn pty_delete_broadcast_group(
+    state: State<'_, AppState>,
+    payload: PtyDeleteBroadcastGroupPayload,
+) -> Result<bool, String> {
+    let mut manager = state.pty_manager.lock().unwrap();
+    Ok(manager.delete_broadcast_group(&payload.name))
+}
+
+#[tauri::command]
+fn pty_list_broadcast_groups(state: State<'_, AppState>) -> Result<BTreeMap<String, Vec<String>>, String> {
     let manager = state.pty_manager.lock().unwrap();
-    manager
-        .write_to_session(&payload.session_id, &payload.input)
+    Ok(manager.broadcast_groups())
+}
+
+#[tauri::command]
+fn pty_write_bytes(state: State<'_, AppState>, payload: PtyWriteBytesPayload) -> Result<(), String> {
+    let data = BASE64
+        .decode(payload.data.as_bytes())
+        .map_err(|e| format!("Invalid base64 input: {e}"))?;
+    pty_session(&state, &payload.session_id)?
+        .write(&data)
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn pty_read_bytes(state: State<'_, AppState>, payload: PtyReadBytesPayload) -> Result<Value, String> {
+    let read = pty_session(&state, &payload.session_id)?
+        .read_bytes(payload.offset)
+        .map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "data": BASE64.encode(&read.data),
+        "start": read.start,
+        "end": read.end,
+        "droppedBytes": read.dropped
+    }))
+}
+
 #[tauri::command]
 fn pty_resize(state: State<'_, AppState>, payload: PtyResizePayload) -> Result<(), String> {
-    let manager = state.pty_manager.lock().unwrap();
-    manager
-        .resize_session(&payload.session_id, payload.cols, payload.rows)
+    pty_session(&state, &payload.session_id)?
+        .resize(payload.cols, payload.rows)
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 fn pty_clear(state: State<'_, AppState>, payload: PtyClosePayload) -> Result<(), String> {
-    let mut manager = state.pty_manager.lock().unwrap();
-    manager
-        .clear_session(&payload.session_id)
+    pty_session(&state, &payload.session_id)?
+        .clear()
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn pty_close(state: State<'_, AppState>, payload: PtyClosePayload) -> Result<(), String> {
-    let mut manager = state.pty_manager.lock().unwrap();
-    manager
-        .close_session(&payload.session_id)
+async fn pty_close(state: State<'_, AppState>, payload: PtyClosePayload) -> Result<ShutdownReport, String> {
+    // La chiusura attende il periodo di grazia: il lock serve solo a togliere
+    // la sessione dal registro, il resto avviene fuori dal thread principale
+    let session = state
+        .pty_manager
+        .lock()
+        .unwrap()
+        .take_session(&payload.session_id)
+        .map_err(|e| e.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || session.close())
+        .await
+        .map_err(|e| e.to_string())?
         .map_err(|e| e.to_string())
 }
 
@@ -172,13 +535,15 @@ fn pty_get_immediate_output(
     state: State<'_, AppState>,
     payload: PtyImmediateOutputPayload,
 ) -> Result<Value, String> {
-    let mut manager = state.pty_manager.lock().unwrap();
-    match manager.get_incremental_output(&payload.session_id, payload.timestamp.unwrap_or(0)) {
-        Ok((output, last_timestamp, has_new_data)) => Ok(json!({
+    let session = state.pty_manager.lock().unwrap().session(&payload.session_id);
+    match session.and_then(|session| session.incremental_output()) {
+        Ok(incremental) => Ok(json!({
             "success": true,
-            "hasNewData": has_new_data,
-            "output": output,
-            "lastTimestamp": last_timestamp
+            "hasNewData": incremental.has_new_data,
+            "output": incremental.output,
+            "lastTimestamp": incremental.last_activity,
+            "offset": incremental.offset,
+            "droppedBytes": incremental.dropped_bytes
         })),
         Err(e) => Ok(json!({
             "success": false,
@@ -187,10 +552,46 @@ fn pty_get_immediate_output(
     }
 }
 
+#[tauri::command]
+fn pty_subscribe_output(
+    state: State<'_, AppState>,
+    payload: PtySubscribePayload,
+    on_output: Channel<OutputChunk>,
+) -> Result<Value, String> {
+    let encoding = payload.encoding;
+    let (subscription_id, offset) = pty_session(&state, &payload.session_id)?
+        .subscribe_output(
+            // Un canale chiuso (es. webview ricaricata) annulla la sottoscrizione
+            Arc::new(move |chunk: &OutputChunk| {
+                on_output.send(chunk.with_encoding(encoding)).is_ok()
+            }),
+        )
+        .map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "subscriptionId": subscription_id,
+        "offset": offset
+    }))
+}
+
+#[tauri::command]
+fn pty_unsubscribe_output(
+    state: State<'_, AppState>,
+    payload: PtyUnsubscribePayload,
+) -> Result<bool, String> {
+    Ok(pty_session(&state, &payload.session_id)?.unsubscribe_output(payload.subscription_id))
+}
+
 #[tauri::command]
 fn pty_list_sessions(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    Ok(pty_session_ids(&state))
+}
+
+/// Sessioni ripristinate all'avvio che nessun client ha ancora aperto
+#[tauri::command]
+fn pty_list_restored_sessions(state: State<'_, AppState>) -> Result<Vec<String>, String> {
     let manager = state.pty_manager.lock().unwrap();
-    Ok(manager.list_sessions())
+    Ok(manager.restored_sessions())
 }
 
 #[tauri::command]
@@ -198,12 +599,232 @@ fn pty_get_session_output(
     state: State<'_, AppState>,
     payload: PtyClosePayload,
 ) -> Result<String, String> {
+    pty_session(&state, &payload.session_id)?
+        .output()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_get_status(
+    state: State<'_, AppState>,
+    payload: PtyClosePayload,
+) -> Result<SessionStatus, String> {
+    pty_session(&state, &payload.session_id)?
+        .status()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_get_foreground_process(
+    state: State<'_, AppState>,
+    payload: PtyClosePayload,
+) -> Result<Option<ProcessInfo>, String> {
+    pty_session(&state, &payload.session_id)?
+        .foreground_process()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_get_screen(
+    state: State<'_, AppState>,
+    payload: PtyScreenPayload,
+) -> Result<ScreenSnapshot, String> {
+    pty_session(&state, &payload.session_id)?
+        .get_screen(payload.include_history)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_list_commands(
+    state: State<'_, AppState>,
+    payload: PtyClosePayload,
+) -> Result<Vec<CommandRecord>, String> {
+    pty_session(&state, &payload.session_id)?
+        .list_commands()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_get_command_output(
+    state: State<'_, AppState>,
+    payload: PtyCommandOutputPayload,
+) -> Result<Value, String> {
+    let (record, read) = pty_session(&state, &payload.session_id)?
+        .get_command_output(payload.command_id)
+        .map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "command": record,
+        "output": String::from_utf8_lossy(&read.data),
+        "droppedBytes": read.dropped
+    }))
+}
+
+#[tauri::command]
+fn pty_close_check(
+    state: State<'_, AppState>,
+    payload: Option<PtyCloseCheckPayload>,
+) -> Result<CloseCheck, String> {
+    let payload = payload.unwrap_or_default();
+    let ignore: Vec<String> = match state
+        .config_manager
+        .lock()
+        .unwrap()
+        .get_value("terminal.close_confirm_ignore")
+        .and_then(Value::as_array)
+    {
+        Some(names) => names.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        None => DEFAULT_IGNORED_PROCESSES.iter().map(|name| name.to_string()).collect(),
+    };
+    let sessions = pty_sessions(&state, payload.session_id.as_deref())?;
+    Ok(pty_manager::close_check_sessions(&sessions, &ignore))
+}
+
+#[tauri::command]
+fn pty_signal(state: State<'_, AppState>, payload: PtySignalPayload) -> Result<i32, String> {
+    pty_session(&state, &payload.session_id)?
+        .signal(payload.signal, payload.target)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_export_scrollback(state: State<'_, AppState>, payload: PtyExportPayload) -> Result<String, String> {
+    pty_session(&state, &payload.session_id)?
+        .export_scrollback(payload.format, payload.last_commands)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_search(state: State<'_, AppState>, payload: PtySearchPayload) -> Result<SearchResults, String> {
+    let sessions = pty_sessions(&state, payload.session_id.as_deref())?;
+    pty_manager::search_sessions(&sessions, payload.session_id.is_none(), &payload.options)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_start_recording(state: State<'_, AppState>, payload: PtyRecordPayload) -> Result<String, String> {
+    let path = payload
+        .path
+        .map(PathBuf::from)
+        .unwrap_or_else(|| recording::default_recording_path(&payload.session_id));
+    pty_session(&state, &payload.session_id)?
+        .start_recording(&path, payload.record_input)
+        .map_err(|e| e.to_string())?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+fn pty_stop_recording(state: State<'_, AppState>, payload: PtyClosePayload) -> Result<String, String> {
+    let path = pty_session(&state, &payload.session_id)?
+        .stop_recording()
+        .map_err(|e| e.to_string())?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+fn pty_play_recording(state: State<'_, AppState>, payload: PtyPlaybackPayload) -> Result<String, String> {
+    let session_id = payload
+        .session_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let mut manager = state.pty_manager.lock().unwrap();
+    manager
+        .play_recording(session_id, Path::new(&payload.path), payload.options)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_set_playback_speed(state: State<'_, AppState>, payload: PtyPlaybackSpeedPayload) -> Result<(), String> {
     let manager = state.pty_manager.lock().unwrap();
     manager
-        .get_session_output(&payload.session_id)
+        .set_playback_speed(&payload.session_id, payload.speed)
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn pty_ssh_connect(
+    state: State<'_, AppState>,
+    payload: PtySshConnectPayload,
+) -> Result<PtySshConnectResponse, String> {
+    let mut options = payload.options;
+    {
+        let config_manager = state.config_manager.lock().unwrap();
+        if options.scrollback.is_none() {
+            options.scrollback = config_manager
+                .get_value("terminal.scrollback")
+                .and_then(Value::as_u64)
+                .map(|scrollback| scrollback as usize);
+        }
+        let profile = payload
+            .profile
+            .or_else(|| ssh::host_profile(config_manager.get_value("terminal.ssh_profiles"), &options.host));
+        if let Some(name) = profile {
+            let profile = ShellProfile::from_config(config_manager.get_value("terminal.profiles"), &name)
+                .map_err(|e| e.to_string())?;
+            options.apply_profile(&profile);
+        }
+    }
+
+    let session_id = payload
+        .session_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    // Connessione e autenticazione bloccano fino al timeout
+    let connect_id = session_id.clone();
+    let connected = tauri::async_runtime::spawn_blocking(move || ssh::connect(connect_id, &options))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    match connected {
+        SshConnect::Connected(session) => {
+            let mut manager = state.pty_manager.lock().unwrap();
+            manager
+                .register_session(Arc::new(session))
+                .map_err(|e| e.to_string())?;
+            Ok(PtySshConnectResponse {
+                session_id: Some(session_id),
+                prompt: None,
+            })
+        }
+        SshConnect::Prompt(prompt) => Ok(PtySshConnectResponse {
+            session_id: None,
+            prompt: Some(prompt),
+        }),
+    }
+}
+
+#[tauri::command]
+fn pty_ssh_hosts(payload: Option<PtySshHostsPayload>) -> Result<Vec<SshHost>, String> {
+    let payload = payload.unwrap_or_default();
+    ssh::hosts(payload.config_path.as_deref(), payload.known_hosts_path.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pty_serial_open(state: State<'_, AppState>, payload: PtySerialOpenPayload) -> Result<String, String> {
+    let mut options = payload.options;
+    if options.scrollback.is_none() {
+        let config_manager = state.config_manager.lock().unwrap();
+        options.scrollback = config_manager
+            .get_value("terminal.scrollback")
+            .and_then(Value::as_u64)
+            .map(|scrollback| scrollback as usize);
+    }
+
+    let session_id = payload
+        .session_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let session = SerialSession::open(session_id.clone(), &options).map_err(|e| e.to_string())?;
+    let mut manager = state.pty_manager.lock().unwrap();
+    manager
+        .register_session(Arc::new(session))
+        .map_err(|e| e.to_string())?;
+    Ok(session_id)
+}
+
+#[tauri::command]
+fn pty_serial_ports() -> Result<Vec<SerialPortEntry>, String> {
+    serial::available_ports().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn run_command(payload: RunCommandPayload) -> Result<Value, String> {
     let mut command = if cfg!(target_os = "windows") {
@@ -345,7 +966,11 @@ fn test_ai_connection(_state: State<'_, AppState>, provider: String, ai_config:
 }
 
 #[tauri::command]
-fn get_cwd() -> Result<String, String> {
+fn get_cwd(state: State<'_, AppState>, session_id: Option<String>) -> Result<String, String> {
+    // Con una sessione si restituisce la directory in cui si trova l'utente
+    if let Some(session_id) = session_id {
+        return pty_session(&state, &session_id)?.cwd().map_err(|e| e.to_string());
+    }
     std::env::current_dir()
         .map(|path| path.display().to_string())
         .map_err(|e| e.to_string())
@@ -801,15 +1426,93 @@ fn main() {
             pty_manager,
             config_manager,
         })
+        .setup(|app| {
+            // Ogni sessione PTY inoltra il proprio output come evento `pty-output`
+            let handle = app.handle().clone();
+            let state = app.state::<AppState>();
+            state
+                .pty_manager
+                .lock()
+                .unwrap()
+                .set_output_listener(Arc::new(move |chunk: &OutputChunk| {
+                    let _ = handle.emit("pty-output", chunk);
+                    true
+                }));
+            // Gli eventi di ciclo di vita (es. `pty-exited`) hanno un nome per tipo
+            let handle = app.handle().clone();
+            state
+                .pty_manager
+                .lock()
+                .unwrap()
+                .set_event_listener(Arc::new(move |event: &SessionEvent| {
+                    let _ = handle.emit(event.name(), event);
+                }));
+            // Con `termina-muxd` le sessioni sopravvivono all'applicazione e
+            // vengono ricollegate invece che ripristinate (`restore_sessions`
+            // non fa nulla); il demone esiste solo sui sistemi Unix
+            #[cfg(unix)]
+            if terminal_flag(&state, "use_muxd", false) {
+                let socket = mux::default_socket_path();
+                if let Err(e) = MuxClient::connect_or_spawn(&socket, &mux::client::daemon_path())
+                    .and_then(|client| state.pty_manager.lock().unwrap().use_remote(client))
+                {
+                    log::warn!("termina-muxd unavailable, using local sessions: {e}");
+                }
+            }
+            if terminal_flag(&state, "restore_sessions", false) {
+                if let Err(e) = state
+                    .pty_manager
+                    .lock()
+                    .unwrap()
+                    .restore_sessions(&persistence::default_sessions_path())
+                {
+                    log::warn!("Failed to restore PTY sessions: {e}");
+                }
+            }
+            // Il reaper rimuove le sessioni terminate o abbandonate
+            let policy = reaper_policy(&state);
+            if let Err(e) = state.pty_manager.lock().unwrap().set_reaper_policy(policy) {
+                log::warn!("Failed to configure session reaper: {e}");
+            }
+            reaper::start(Arc::downgrade(&state.pty_manager));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             pty_create_session,
+            pty_duplicate_session,
             pty_write,
+            pty_write_bytes,
+            pty_broadcast_write,
+            pty_update_broadcast_group,
+            pty_delete_broadcast_group,
+            pty_list_broadcast_groups,
+            pty_read_bytes,
             pty_resize,
             pty_clear,
             pty_close,
+            pty_signal,
+            pty_close_check,
             pty_list_sessions,
+            pty_list_restored_sessions,
             pty_get_session_output,
+            pty_get_status,
+            pty_get_foreground_process,
+            pty_get_screen,
+            pty_list_commands,
+            pty_get_command_output,
+            pty_export_scrollback,
+            pty_search,
+            pty_start_recording,
+            pty_stop_recording,
+            pty_play_recording,
+            pty_set_playback_speed,
+            pty_ssh_connect,
+            pty_ssh_hosts,
+            pty_serial_open,
+            pty_serial_ports,
             pty_get_immediate_output,
+            pty_subscribe_output,
+            pty_unsubscribe_output,
             run_command,
             get_config,
             set_config,
@@ -821,6 +1524,41 @@ fn main() {
             close_current_window,
             open_settings_window
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            // Le sessioni vanno salvate prima che il manager le termini
+            if let RunEvent::Exit = event {
+                let state = app.state::<AppState>();
+                if terminal_flag(&state, "restore_sessions", false) {
+                    let manager = state.pty_manager.lock().unwrap();
+                    if let Err(e) = manager.save_sessions(&persistence::default_sessions_path()) {
+                        log::warn!("Failed to save PTY sessions: {e}");
+                    }
+                }
+            }
+        });
+}
+
+/// Criteri del reaper dalla sezione `terminal` della configurazione
+fn reaper_policy(state: &AppState) -> ReaperPolicy {
+    let config_manager = state.config_manager.lock().unwrap();
+    let value = |key: &str| config_manager.get_value(&format!("terminal.{key}")).and_then(Value::as_u64);
+    let mut policy = ReaperPolicy::default();
+    if let Some(grace) = value("exited_session_grace_secs") {
+        policy.exited_grace_secs = grace;
+    }
+    policy.idle_timeout_secs = value("idle_timeout_secs").filter(|timeout| *timeout > 0);
+    policy
+}
+
+/// Opzione booleana della sezione `terminal` della configurazione
+fn terminal_flag(state: &AppState, key: &str, default: bool) -> bool {
+    state
+        .config_manager
+        .lock()
+        .unwrap()
+        .get_value(&format!("terminal.{key}"))
+        .and_then(Value::as_bool)
+        .unwrap_or(default)
 }
@@ -0,0 +1,232 @@
+//! Backend delle sessioni
+//!
+//! Il `PtyManager` non conosce il tipo concreto delle sessioni: ogni sessione
+//! è un `SessionBackend` che riceve input, produce un flusso di output con
+//! offset assoluti, ha uno stato e si può terminare. I backend disponibili
+//! sono il PTY locale (`RealPtySession`), le sessioni del demone
+//! `termina-muxd` (`mux::remote::RemoteSession`), le riproduzioni di sola
+//! lettura (`PlaybackSession`), le connessioni SSH (`ssh::SshSession`) e le
+//! porte seriali (`serial::SerialSession`); altri (es. container) si
+//! aggiungono implementando il trait, senza toccare i comandi Tauri.
+//!
+//! Le funzioni che non tutti i backend possono offrire (segnali, comandi
+//! rilevati, registrazione, ...) hanno un'implementazione predefinita che
+//! restituisce un errore o un valore neutro.
+
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use super::close_check::SessionCloseInfo;
+use super::events::SessionEventListener;
+use super::output::OutputSubscriber;
+use super::process::ProcessInfo;
+use super::reaper::SessionActivity;
+use super::screen::ScreenSnapshot;
+use super::scrollback::ScrollbackRead;
+use super::session::SessionStatus;
+use super::shell_integration::CommandRecord;
+use super::shutdown::ShutdownReport;
+use super::signals::{SessionSignal, SignalTarget};
+use super::PtyConfig;
+
+/// Sessione gestita dal `PtyManager`
+pub trait SessionBackend: Send + Sync {
+    fn id(&self) -> &str;
+
+    /// Scrive byte arbitrari nell'input della sessione
+    fn write(&self, data: &[u8]) -> Result<()>;
+
+    fn resize(&self, cols: u16, rows: u16) -> Result<()>;
+
+    /// Legge l'output conservato a partire da un offset assoluto; oltre la
+    /// fine restituisce una lettura vuota con l'offset corrente
+    fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead>;
+
+    /// Sottoscrive l'output in streaming, restituendo l'id della
+    /// sottoscrizione e l'offset da cui partiranno i prossimi blocchi
+    fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)>;
+
+    fn unsubscribe_output(&self, subscription_id: u64) -> bool;
+
+    /// Svuota l'output conservato
+    fn clear(&self) -> Result<()>;
+
+    fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot>;
+
+    fn status(&self) -> Result<SessionStatus>;
+
+    /// Registra il listener degli eventi di ciclo di vita
+    fn set_event_listener(&self, listener: SessionEventListener);
+
+    /// Chiusura ordinata della sessione
+    fn close(&self) -> Result<ShutdownReport>;
+
+    /// Terminazione immediata della sessione
+    fn kill(&self) -> Result<()>;
+
+    /// Accesso al tipo concreto (es. per il salvataggio dei PTY locali)
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+
+    fn cwd(&self) -> Result<String> {
+        Err(self.unsupported("working directory"))
+    }
+
+    fn foreground_process(&self) -> Result<Option<ProcessInfo>> {
+        Ok(None)
+    }
+
+    fn list_commands(&self) -> Result<Vec<CommandRecord>> {
+        Ok(Vec::new())
+    }
+
+    fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        Err(anyhow!("Command {} not found in session {}", command_id, self.id()))
+    }
+
+    fn signal(&self, _signal: SessionSignal, _target: SignalTarget) -> Result<i32> {
+        Err(self.unsupported("signals"))
+    }
+
+    fn start_recording(&self, _path: &Path, _record_input: bool) -> Result<()> {
+        Err(self.unsupported("recording"))
+    }
+
+    fn stop_recording(&self) -> Result<PathBuf> {
+        Err(self.unsupported("recording"))
+    }
+
+    /// Configurazione per una nuova sessione nello stesso contesto
+    fn duplicate_config(&self, _inherit_env: bool) -> Result<PtyConfig> {
+        Err(self.unsupported("duplication"))
+    }
+
+    /// Programmi in esecuzione, per la conferma prima della chiusura
+    fn close_info(&self, _ignore: &[String]) -> Result<SessionCloseInfo> {
+        Ok(SessionCloseInfo::new(self.id(), true, Vec::new()))
+    }
+
+    /// Stato per il reaper; `None` se la sessione non va rimossa da qui
+    fn activity(&self) -> Option<SessionActivity> {
+        None
+    }
+
+    #[doc(hidden)]
+    fn unsupported(&self, feature: &str) -> anyhow::Error {
+        anyhow!("Session {} does not support {}", self.id(), feature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::pty::pty_manager::PtyManager;
+    use crate::pty::screen::TerminalScreen;
+    use crate::pty::scrollback::ScrollbackBuffer;
+
+    /// Backend minimo che rimanda l'input come output
+    struct Loopback {
+        id: String,
+        buffer: Mutex<ScrollbackBuffer>,
+    }
+
+    impl SessionBackend for Loopback {
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn write(&self, data: &[u8]) -> Result<()> {
+            self.buffer.lock().unwrap().append(data);
+            Ok(())
+        }
+
+        fn resize(&self, _cols: u16, _rows: u16) -> Result<()> {
+            Ok(())
+        }
+
+        fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead> {
+            Ok(self.buffer.lock().unwrap().read_from(from_offset))
+        }
+
+        fn subscribe_output(&self, _subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+            Ok((0, self.buffer.lock().unwrap().end_offset()))
+        }
+
+        fn unsubscribe_output(&self, _subscription_id: u64) -> bool {
+            false
+        }
+
+        fn clear(&self) -> Result<()> {
+            self.buffer.lock().unwrap().clear();
+            Ok(())
+        }
+
+        fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+            Ok(TerminalScreen::new(80, 24, 100).snapshot(include_history))
+        }
+
+        fn status(&self) -> Result<SessionStatus> {
+            Ok(SessionStatus {
+                id: self.id.clone(),
+                is_active: true,
+                is_executing: false,
+                current_command: String::new(),
+                last_activity: 0,
+                buffer_size: self.buffer.lock().unwrap().len(),
+                cwd: String::new(),
+                pid: None,
+                exit_status: None,
+                foreground_process: None,
+                restored_at: None,
+                recording: None,
+                read_only: false,
+            })
+        }
+
+        fn set_event_listener(&self, _listener: SessionEventListener) {}
+
+        fn close(&self) -> Result<ShutdownReport> {
+            Ok(ShutdownReport {
+                session_id: self.id.clone(),
+                ..Default::default()
+            })
+        }
+
+        fn kill(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+    }
+
+    #[test]
+    fn test_manager_drives_any_backend() {
+        let mut manager = PtyManager::new();
+        let backend = Loopback {
+            id: "loop".to_string(),
+            buffer: Mutex::new(ScrollbackBuffer::new(100)),
+        };
+        manager.register_session(Arc::new(backend)).unwrap();
+        assert_eq!(manager.list_sessions(), vec!["loop".to_string()]);
+
+        manager.write_to_session("loop", "hello").unwrap();
+        assert_eq!(manager.session("loop").unwrap().incremental_output().unwrap().output, "hello");
+        manager.clear_session("loop").unwrap();
+        manager.write_to_session("loop", "again").unwrap();
+        assert_eq!(manager.session("loop").unwrap().incremental_output().unwrap().output, "again");
+
+        // Le funzioni facoltative ricadono sulle implementazioni predefinite
+        assert!(manager.signal_session("loop", SessionSignal::Interrupt, SignalTarget::Foreground).is_err());
+        assert!(!manager.close_check(None, &[]).unwrap().needs_confirmation);
+        assert!(manager.get_session("loop").is_none());
+
+        manager.close_session("loop").unwrap();
+        assert!(manager.list_sessions().is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! Invio dello stesso input a più sessioni
+//!
+//! I gruppi di broadcast sono insiemi di sessioni con un nome, modificabili
+//! in qualsiasi momento: la stessa sequenza di tasti viene scritta in ogni
+//! sessione del gruppo (es. gli stessi comandi su più host SSH). L'esito è
+//! riportato per ogni sessione, così una sessione chiusa non blocca le altre.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Esito della scrittura in una sessione
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastResult {
+    pub session_id: String,
+    /// Errore della scrittura, assente se è andata a buon fine
+    pub error: Option<String>,
+}
+
+impl BroadcastResult {
+    pub fn new(session_id: &str, result: Result<()>) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Gruppi di broadcast per nome; le sessioni mantengono l'ordine di inserimento
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastGroups {
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+impl BroadcastGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sostituisce le sessioni di un gruppo; un elenco vuoto elimina il gruppo
+    pub fn set(&mut self, name: &str, session_ids: &[String]) {
+        self.groups.remove(name);
+        self.add(name, session_ids);
+    }
+
+    /// Aggiunge sessioni a un gruppo, creandolo se non esiste
+    pub fn add(&mut self, name: &str, session_ids: &[String]) {
+        if session_ids.is_empty() {
+            return;
+        }
+        let members = self.groups.entry(name.to_string()).or_default();
+        for session_id in session_ids {
+            if !members.contains(session_id) {
+                members.push(session_id.clone());
+            }
+        }
+    }
+
+    /// Toglie sessioni da un gruppo; il gruppo rimasto vuoto viene eliminato
+    pub fn remove(&mut self, name: &str, session_ids: &[String]) {
+        if let Some(members) = self.groups.get_mut(name) {
+            members.retain(|member| !session_ids.contains(member));
+            if members.is_empty() {
+                self.groups.remove(name);
+            }
+        }
+    }
+
+    pub fn delete(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Toglie una sessione chiusa da tutti i gruppi
+    pub fn forget_session(&mut self, session_id: &str) {
+        let names: Vec<String> = self.groups.keys().cloned().collect();
+        for name in names {
+            self.remove(&name, &[session_id.to_string()]);
+        }
+    }
+
+    pub fn members(&self, name: &str) -> Option<&[String]> {
+        self.groups.get(name).map(Vec::as_slice)
+    }
+
+    pub fn list(&self) -> BTreeMap<String, Vec<String>> {
+        self.groups.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_edit_groups() {
+        let mut groups = BroadcastGroups::new();
+        groups.set("web", &ids(&["a", "b"]));
+        groups.add("web", &ids(&["b", "c"]));
+        groups.add("db", &ids(&["c"]));
+        assert_eq!(groups.members("web"), Some(ids(&["a", "b", "c"]).as_slice()));
+
+        groups.remove("web", &ids(&["a"]));
+        groups.forget_session("c");
+        assert_eq!(groups.members("web"), Some(ids(&["b"]).as_slice()));
+        // Il gruppo rimasto vuoto sparisce
+        assert_eq!(groups.members("db"), None);
+
+        groups.set("web", &[]);
+        assert!(groups.list().is_empty());
+        assert!(!groups.delete("web"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Verifica prima della chiusura
+//!
+//! Prima di chiudere una scheda o l'applicazione l'interfaccia chiede quali
+//! programmi sono ancora in esecuzione nelle sessioni, per chiedere conferma
+//! solo quando si rischia di interrompere qualcosa. I programmi nella lista
+//! da ignorare (`terminal.close_confirm_ignore`, es. `less` o `top`) vengono
+//! riportati ma non richiedono conferma.
+
+use serde::{Deserialize, Serialize};
+
+use super::process;
+
+/// Programmi che per impostazione predefinita non richiedono conferma
+pub const DEFAULT_IGNORED_PROCESSES: &[&str] = &["less", "more", "man", "top", "htop"];
+
+/// Processo discendente della shell ancora in esecuzione
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunningProcess {
+    pub pid: i32,
+    pub name: String,
+    pub argv: Vec<String>,
+    /// Secondi dall'avvio del processo, se noti
+    pub runtime_secs: Option<u64>,
+    /// Il programma è nella lista da ignorare
+    pub ignored: bool,
+}
+
+/// Stato di una sessione ai fini della chiusura
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionCloseInfo {
+    pub session_id: String,
+    /// La shell è al prompt senza comandi in esecuzione
+    pub idle: bool,
+    pub processes: Vec<RunningProcess>,
+    /// Chiudere la sessione interromperebbe un programma non ignorato
+    pub needs_confirmation: bool,
+    /// Errore della verifica; nel dubbio la chiusura va confermata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SessionCloseInfo {
+    pub fn new(session_id: &str, idle: bool, processes: Vec<RunningProcess>) -> Self {
+        let needs_confirmation = processes.iter().any(|process| !process.ignored);
+        Self {
+            session_id: session_id.to_string(),
+            idle,
+            processes,
+            needs_confirmation,
+            error: None,
+        }
+    }
+
+    /// Sessione il cui stato non si è potuto verificare
+    pub fn failed(session_id: &str, error: String) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            needs_confirmation: true,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+}
+
+/// Esito della verifica su più sessioni
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CloseCheck {
+    pub sessions: Vec<SessionCloseInfo>,
+    pub needs_confirmation: bool,
+}
+
+impl CloseCheck {
+    pub fn new(sessions: Vec<SessionCloseInfo>) -> Self {
+        let needs_confirmation = sessions.iter().any(|session| session.needs_confirmation);
+        Self {
+            sessions,
+            needs_confirmation,
+        }
+    }
+}
+
+/// Discendenti della shell ancora in esecuzione, shell esclusa, dal più vecchio
+pub fn running_processes(shell_pid: i32, ignore: &[String]) -> Vec<RunningProcess> {
+    let mut processes: Vec<(u64, RunningProcess)> = process::session_processes(shell_pid)
+        .iter()
+        .filter(|stat| stat.pid != shell_pid)
+        .filter_map(|stat| {
+            let info = process::process_info(stat.pid, Some(shell_pid))?;
+            let ignored = is_ignored(&info.name, ignore);
+            let process = RunningProcess {
+                pid: info.pid,
+                name: info.name,
+                argv: info.argv,
+                runtime_secs: process::process_runtime(stat),
+                ignored,
+            };
+            Some((stat.start_time, process))
+        })
+        .collect();
+    processes.sort_by_key(|(start_time, process)| (*start_time, process.pid));
+    processes.into_iter().map(|(_, process)| process).collect()
+}
+
+fn is_ignored(name: &str, ignore: &[String]) -> bool {
+    ignore.iter().any(|ignored| ignored == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running(name: &str, ignore: &[String]) -> RunningProcess {
+        RunningProcess {
+            pid: 1,
+            name: name.to_string(),
+            argv: vec![name.to_string()],
+            runtime_secs: Some(3),
+            ignored: is_ignored(name, ignore),
+        }
+    }
+
+    #[test]
+    fn test_ignored_processes_do_not_need_confirmation() {
+        let ignore: Vec<String> = DEFAULT_IGNORED_PROCESSES.iter().map(|name| name.to_string()).collect();
+        let pager = SessionCloseInfo::new("a", false, vec![running("less", &ignore)]);
+        assert!(!pager.needs_confirmation);
+        let idle = SessionCloseInfo::new("b", true, Vec::new());
+        assert!(!CloseCheck::new(vec![pager.clone(), idle]).needs_confirmation);
+
+        let build = SessionCloseInfo::new("c", false, vec![running("cargo", &ignore)]);
+        assert!(build.needs_confirmation);
+        assert!(CloseCheck::new(vec![pager.clone(), build]).needs_confirmation);
+
+        let failed = SessionCloseInfo::failed("d", "muxd connection lost".to_string());
+        assert!(CloseCheck::new(vec![pager, failed]).needs_confirmation);
+    }
+}
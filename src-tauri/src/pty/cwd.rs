@@ -0,0 +1,41 @@
+//! Directory corrente comunicata dalla shell con OSC 7
+//!
+//! La sequenza ha la forma `OSC 7 ; file://host/percorso`, con il percorso
+//! codificato come URL. È emessa dagli hook di `shell_hooks` e da molte
+//! configurazioni di shell (vte, kitty, WezTerm).
+
+/// Estrae il percorso da una sequenza OSC 7, se `params` lo è
+pub fn parse_osc7(params: &[Vec<u8>]) -> Option<String> {
+    if params.first().map(Vec::as_slice) != Some(b"7".as_slice()) {
+        return None;
+    }
+    // Il parser divide i parametri sui `;`, che possono comparire nel percorso
+    let url = params[1..].join(&b';');
+    let url = String::from_utf8_lossy(&url);
+
+    let rest = url
+        .strip_prefix("file://")
+        .or_else(|| url.strip_prefix("kitty-shell-cwd://"))?;
+    // Dopo lo schema c'è l'host (eventualmente vuoto), poi il percorso assoluto
+    let path = &rest[rest.find('/')?..];
+    let decoded = urlencoding::decode_binary(path.as_bytes());
+    Some(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(raw: &str) -> Vec<Vec<u8>> {
+        raw.split(';').map(|part| part.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_parse_osc7() {
+        assert_eq!(parse_osc7(&params("7;file://host/home/user")), Some("/home/user".to_string()));
+        assert_eq!(parse_osc7(&params("7;file:///tmp/a%20b")), Some("/tmp/a b".to_string()));
+        assert_eq!(parse_osc7(&params("7;file://host/tmp/x;y")), Some("/tmp/x;y".to_string()));
+        assert_eq!(parse_osc7(&params("7;file://host")), None);
+        assert_eq!(parse_osc7(&params("133;A")), None);
+    }
+}
@@ -0,0 +1,55 @@
+//! Eventi del ciclo di vita delle sessioni
+//!
+//! Le sessioni notificano i cambiamenti di stato (es. la terminazione del
+//! processo, il cambio di directory o la fine di una riproduzione) a un
+//! listener registrato dal `PtyManager`; `main.rs` li inoltra al frontend
+//! come eventi Tauri.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::reaper::ReapReason;
+use super::session::ProcessExit;
+
+/// Evento emesso da una sessione
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// Il processo principale della sessione è terminato
+    Exited {
+        session_id: String,
+        exit_status: ProcessExit,
+    },
+    /// La directory corrente della sessione è cambiata
+    CwdChanged { session_id: String, cwd: String },
+    /// La riproduzione di una registrazione è terminata
+    PlaybackFinished { session_id: String },
+    /// La sessione è stata rimossa dal reaper
+    Reaped { session_id: String, reason: ReapReason },
+}
+
+impl SessionEvent {
+    /// Nome dell'evento Tauri corrispondente
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionEvent::Exited { .. } => "pty-exited",
+            SessionEvent::CwdChanged { .. } => "pty-cwd-changed",
+            SessionEvent::PlaybackFinished { .. } => "pty-playback-finished",
+            SessionEvent::Reaped { .. } => "pty-session-reaped",
+        }
+    }
+
+    /// Sessione a cui si riferisce l'evento
+    pub fn session_id(&self) -> &str {
+        match self {
+            SessionEvent::Exited { session_id, .. }
+            | SessionEvent::CwdChanged { session_id, .. }
+            | SessionEvent::PlaybackFinished { session_id }
+            | SessionEvent::Reaped { session_id, .. } => session_id,
+        }
+    }
+}
+
+/// Listener degli eventi di sessione
+pub type SessionEventListener = Arc<dyn Fn(&SessionEvent) + Send + Sync>;
@@ -0,0 +1,254 @@
+//! Esportazione dello scrollback
+//!
+//! Lo scrollback grezzo contiene le sequenze di escape così come le ha
+//! scritte il programma. Per il testo semplice e l'HTML i byte vengono
+//! reinterpretati da uno schermo dedicato, alto quanto serve: si ottiene il
+//! testo come appariva nel terminale (barre di avanzamento, correzioni con
+//! backspace, ecc.) con i colori di ogni cella.
+
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthChar;
+
+use super::screen::{CellAttributes, Color, ScreenLine, ScreenSnapshot, TerminalScreen};
+
+/// Uscita dallo schermo alternativo: l'esportazione mostra lo schermo principale
+const LEAVE_ALTERNATE_SCREEN: &[u8] = b"\x1b[?1049l";
+
+/// Colori della pagina HTML per le celle senza colore esplicito
+const DEFAULT_FG: &str = "#d4d4d4";
+const DEFAULT_BG: &str = "#1e1e1e";
+
+/// Palette dei 16 colori ANSI di base
+const ANSI_COLORS: [&str; 16] = [
+    "#000000", "#cd3131", "#0dbc79", "#e5e510", "#2472c8", "#bc3fbc", "#11a8cd", "#e5e5e5",
+    "#666666", "#f14c4c", "#23d18b", "#f5f543", "#3b8eea", "#d670d6", "#29b8db", "#ffffff",
+];
+
+/// Formato di esportazione
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Testo senza sequenze di escape
+    #[default]
+    Text,
+    /// Byte originali, sequenze di escape comprese
+    Ansi,
+    /// Pagina HTML autonoma con i colori
+    Html,
+}
+
+/// Esporta i byte di output di una sessione nel formato richiesto; `cols` e
+/// `rows` sono le dimensioni del terminale su cui l'output è stato prodotto
+pub fn export(data: &[u8], cols: u16, rows: u16, format: ExportFormat, title: &str) -> String {
+    match format {
+        ExportFormat::Ansi => String::from_utf8_lossy(data).to_string(),
+        ExportFormat::Text => to_text(&render(data, cols, rows)),
+        ExportFormat::Html => to_html(&render(data, cols, rows), title),
+    }
+}
+
+/// Reinterpreta l'output su uno schermo con storico illimitato
+fn render(data: &[u8], cols: u16, rows: u16) -> ScreenSnapshot {
+    let mut screen = TerminalScreen::new(cols, rows, usize::MAX);
+    screen.process(data);
+    if screen.is_alternate_screen() {
+        screen.process(LEAVE_ALTERNATE_SCREEN);
+    }
+    screen.snapshot(true)
+}
+
+/// Righe logiche: quelle spezzate dall'a capo automatico vengono riunite
+fn logical_lines(snapshot: &ScreenSnapshot) -> Vec<Vec<&ScreenLine>> {
+    let mut lines: Vec<Vec<&ScreenLine>> = Vec::new();
+    let mut continued = false;
+    for line in &snapshot.lines {
+        match lines.last_mut() {
+            Some(last) if continued => last.push(line),
+            _ => lines.push(vec![line]),
+        }
+        continued = line.wrapped;
+    }
+    while lines
+        .last()
+        .is_some_and(|last| last.iter().all(|line| line.text.is_empty()))
+    {
+        lines.pop();
+    }
+    lines
+}
+
+fn to_text(snapshot: &ScreenSnapshot) -> String {
+    let mut text = String::new();
+    for line in logical_lines(snapshot) {
+        for part in line {
+            text.push_str(&part.text);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn to_html(snapshot: &ScreenSnapshot, title: &str) -> String {
+    let mut body = String::new();
+    for line in logical_lines(snapshot) {
+        for part in line {
+            push_html_line(&mut body, part);
+        }
+        body.push('\n');
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ margin: 0; background: {bg}; color: {fg}; }}\n\
+         pre {{ margin: 0; padding: 1em; font-family: Menlo, Monaco, 'DejaVu Sans Mono', monospace; \
+         font-size: 13px; line-height: 1.2; white-space: pre-wrap; }}\n\
+         </style>\n</head>\n<body>\n<pre>{}</pre>\n</body>\n</html>\n",
+        escape_html(title),
+        body,
+        bg = DEFAULT_BG,
+        fg = DEFAULT_FG,
+    )
+}
+
+/// Scrive una riga raggruppando i caratteri consecutivi con gli stessi attributi
+fn push_html_line(html: &mut String, line: &ScreenLine) {
+    let mut run = String::new();
+    let mut run_attrs = CellAttributes::default();
+    let mut col = 0;
+    for ch in line.text.chars() {
+        let attrs = line
+            .spans
+            .iter()
+            .find(|span| span.start <= col && col < span.start + span.len)
+            .map(|span| span.attributes)
+            .unwrap_or_default();
+        if attrs != run_attrs && !run.is_empty() {
+            push_html_run(html, &run, &run_attrs);
+            run.clear();
+        }
+        run_attrs = attrs;
+        run.push(ch);
+        col += ch.width().unwrap_or(0).max(1);
+    }
+    if !run.is_empty() {
+        push_html_run(html, &run, &run_attrs);
+    }
+}
+
+fn push_html_run(html: &mut String, text: &str, attrs: &CellAttributes) {
+    let style = css_style(attrs);
+    if style.is_empty() {
+        html.push_str(&escape_html(text));
+    } else {
+        html.push_str(&format!("<span style=\"{}\">{}</span>", style, escape_html(text)));
+    }
+}
+
+fn css_style(attrs: &CellAttributes) -> String {
+    let (mut fg, mut bg) = (css_color(attrs.fg), css_color(attrs.bg));
+    if attrs.inverse {
+        let inverted_fg = bg.unwrap_or_else(|| DEFAULT_BG.to_string());
+        let inverted_bg = fg.unwrap_or_else(|| DEFAULT_FG.to_string());
+        fg = Some(inverted_fg);
+        bg = Some(inverted_bg);
+    }
+
+    let mut style = Vec::new();
+    if let Some(fg) = fg {
+        style.push(format!("color: {}", fg));
+    }
+    if let Some(bg) = bg {
+        style.push(format!("background: {}", bg));
+    }
+    if attrs.bold {
+        style.push("font-weight: bold".to_string());
+    }
+    if attrs.dim {
+        style.push("opacity: 0.6".to_string());
+    }
+    if attrs.italic {
+        style.push("font-style: italic".to_string());
+    }
+    let decorations: Vec<&str> = [
+        (attrs.underline, "underline"),
+        (attrs.strikethrough, "line-through"),
+    ]
+    .iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, decoration)| *decoration)
+    .collect();
+    if !decorations.is_empty() {
+        style.push(format!("text-decoration: {}", decorations.join(" ")));
+    }
+    if attrs.hidden {
+        style.push("visibility: hidden".to_string());
+    }
+    style.join("; ")
+}
+
+/// Colore CSS di un colore del terminale; `None` per il colore predefinito
+fn css_color(color: Color) -> Option<String> {
+    match color {
+        Color::Default => None,
+        Color::Rgb(r, g, b) => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        Color::Indexed(index) if index < 16 => Some(ANSI_COLORS[index as usize].to_string()),
+        Color::Indexed(index) if index < 232 => {
+            // Cubo 6x6x6
+            let index = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            Some(format!(
+                "#{:02x}{:02x}{:02x}",
+                level(index / 36),
+                level((index / 6) % 6),
+                level(index % 6)
+            ))
+        }
+        Color::Indexed(index) => {
+            // Scala di grigi
+            let gray = 8 + (index - 232) * 10;
+            Some(format!("#{:02x}{:02x}{:02x}", gray, gray, gray))
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &[u8] = b"$ make\r\n\x1b[1;31merror\x1b[0m: <missing>\r\n10%\r50%\r100%\r\n";
+
+    #[test]
+    fn test_text_strips_escapes_and_overwrites() {
+        let text = export(OUTPUT, 80, 24, ExportFormat::Text, "");
+        assert_eq!(text, "$ make\nerror: <missing>\n100%\n");
+    }
+
+    #[test]
+    fn test_text_joins_wrapped_lines() {
+        let text = export(b"abcdefgh\r\n", 4, 24, ExportFormat::Text, "");
+        assert_eq!(text, "abcdefgh\n");
+    }
+
+    #[test]
+    fn test_html_keeps_colors() {
+        let html = export(OUTPUT, 80, 24, ExportFormat::Html, "build & test");
+        assert!(html.contains("<title>build &amp; test</title>"));
+        assert!(html.contains("<span style=\"color: #cd3131; font-weight: bold\">error</span>: &lt;missing&gt;"));
+        assert_eq!(css_color(Color::Indexed(196)).as_deref(), Some("#ff0000"));
+        assert_eq!(css_color(Color::Indexed(244)).as_deref(), Some("#808080"));
+    }
+}
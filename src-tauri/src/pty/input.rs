@@ -0,0 +1,208 @@
+//! Coda di input verso il PTY
+//!
+//! Ogni sessione possiede un unico writer, ottenuto una sola volta alla
+//! creazione e usato da un thread dedicato. I comandi Tauri si limitano ad
+//! accodare i dati: se il processo figlio smette di leggere è il thread di
+//! scrittura a bloccarsi, non il chiamante. Quando la coda supera il limite
+//! l'accodamento fallisce subito, così il frontend può riprovare.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use log::{debug, error, info};
+
+/// Byte massimi in attesa di essere scritti
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+/// Dimensione dei blocchi scritti sul PTY
+const WRITE_CHUNK_SIZE: usize = 4096;
+
+#[derive(Default)]
+struct InputState {
+    queue: VecDeque<Vec<u8>>,
+    pending_bytes: usize,
+    closed: bool,
+    error: Option<String>,
+}
+
+/// Coda di input servita da un thread di scrittura dedicato
+pub struct InputQueue {
+    session_id: String,
+    state: Arc<(Mutex<InputState>, Condvar)>,
+}
+
+impl InputQueue {
+    /// Avvia il thread di scrittura sul writer fornito
+    pub fn start(session_id: String, writer: Box<dyn Write + Send>) -> Self {
+        let state = Arc::new((Mutex::new(InputState::default()), Condvar::new()));
+        let thread_state = Arc::clone(&state);
+        let thread_session_id = session_id.clone();
+
+        thread::spawn(move || {
+            Self::run_writer(&thread_session_id, writer, &thread_state);
+            info!("Input writer finished for PTY session: {}", thread_session_id);
+        });
+
+        Self { session_id, state }
+    }
+
+    /// Accoda dati da scrivere; non blocca mai il chiamante
+    pub fn enqueue(&self, data: Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        if let Some(err) = &state.error {
+            return Err(anyhow!("PTY input closed for session {}: {}", self.session_id, err));
+        }
+        if state.closed {
+            return Err(anyhow!("PTY input closed for session {}", self.session_id));
+        }
+        // Un blocco singolo più grande del limite è accettato solo a coda vuota
+        if state.pending_bytes > 0 && state.pending_bytes + data.len() > MAX_PENDING_BYTES {
+            return Err(anyhow!(
+                "PTY input queue full for session {} ({} bytes pending)",
+                self.session_id,
+                state.pending_bytes
+            ));
+        }
+
+        state.pending_bytes += data.len();
+        state.queue.push_back(data);
+        condvar.notify_one();
+        Ok(())
+    }
+
+    /// Byte accodati ma non ancora scritti
+    pub fn pending_bytes(&self) -> usize {
+        self.state.0.lock().unwrap().pending_bytes
+    }
+
+    /// Chiude la coda scartando l'input non ancora scritto
+    pub fn close(&self) {
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        state.closed = true;
+        state.queue.clear();
+        state.pending_bytes = 0;
+        condvar.notify_all();
+    }
+
+    fn run_writer(
+        session_id: &str,
+        mut writer: Box<dyn Write + Send>,
+        state: &(Mutex<InputState>, Condvar),
+    ) {
+        let (lock, condvar) = state;
+        loop {
+            let data = {
+                let mut state = lock.lock().unwrap();
+                while state.queue.is_empty() && !state.closed {
+                    state = condvar.wait(state).unwrap();
+                }
+                match state.queue.pop_front() {
+                    Some(data) => data,
+                    None => return,
+                }
+            };
+
+            for chunk in data.chunks(WRITE_CHUNK_SIZE) {
+                let result = writer.write_all(chunk).and_then(|_| writer.flush());
+                let mut state = lock.lock().unwrap();
+                if let Err(e) = result {
+                    error!("Error writing to PTY session {}: {}", session_id, e);
+                    state.error = Some(e.to_string());
+                    state.closed = true;
+                    state.queue.clear();
+                    state.pending_bytes = 0;
+                    return;
+                }
+                if state.closed {
+                    // Coda chiusa durante la scrittura: il resto va scartato
+                    return;
+                }
+                state.pending_bytes = state.pending_bytes.saturating_sub(chunk.len());
+            }
+            debug!("Wrote {} bytes to PTY session {}", data.len(), session_id);
+        }
+    }
+}
+
+impl Drop for InputQueue {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_writes_in_order() {
+        let writer = SharedWriter::default();
+        let queue = InputQueue::start("test".to_string(), Box::new(writer.clone()));
+
+        queue.enqueue(b"ls".to_vec()).unwrap();
+        queue.enqueue(vec![b'x'; WRITE_CHUNK_SIZE * 3 + 1]).unwrap();
+        queue.enqueue(b"\n".to_vec()).unwrap();
+
+        wait_for(|| queue.pending_bytes() == 0);
+        let written = writer.0.lock().unwrap();
+        assert_eq!(written.len(), 2 + WRITE_CHUNK_SIZE * 3 + 1 + 1);
+        assert!(written.starts_with(b"ls"));
+        assert!(written.ends_with(b"x\n"));
+    }
+
+    #[test]
+    fn test_enqueue_after_close_fails() {
+        let queue = InputQueue::start("test".to_string(), Box::new(SharedWriter::default()));
+        queue.close();
+        assert!(queue.enqueue(b"echo".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_queue_full_is_reported() {
+        struct StalledWriter;
+        impl Write for StalledWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                thread::sleep(Duration::from_secs(60));
+                Ok(0)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let queue = InputQueue::start("test".to_string(), Box::new(StalledWriter));
+        queue.enqueue(vec![b'a'; MAX_PENDING_BYTES]).unwrap();
+        assert!(queue.enqueue(b"more".to_vec()).is_err());
+    }
+}
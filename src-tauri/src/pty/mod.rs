@@ -1,24 +1,100 @@
+pub mod backend;
+pub mod broadcast;
+pub mod close_check;
+pub mod cwd;
+pub mod events;
+pub mod export;
+pub mod input;
+#[cfg(unix)]
+pub mod mux;
+pub mod output;
+pub mod persistence;
+pub mod playback;
+pub mod process;
+pub mod profiles;
 pub mod pty_manager;
+pub mod reaper;
+pub mod recording;
+pub mod scrollback;
+pub mod screen;
+pub mod search;
+pub mod serial;
 pub mod session;
+pub mod shell_hooks;
+pub mod shell_integration;
+pub mod shutdown;
+pub mod signals;
+pub mod ssh;
+pub mod stream;
 pub mod sudo_handler;
+pub mod utf8;
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::io::{Read, Write};
-use log::{debug, error, info};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::io::Read;
+use log::{debug, error, info, warn};
 use anyhow::{anyhow, Result};
 
+use self::backend::SessionBackend;
+use self::close_check::SessionCloseInfo;
+use self::events::{SessionEvent, SessionEventListener};
+use self::input::InputQueue;
+use self::output::{OutputBroadcaster, OutputSubscriber};
+use self::persistence::SavedSession;
+use self::process::ProcessInfo;
+use self::recording::{CastHeader, Recorder};
+use self::screen::{ScreenEvent, ScreenSnapshot, TerminalScreen};
+use self::shell_hooks::ShellKind;
+use self::shell_integration::{CommandRecord, CommandTracker};
+use self::shutdown::{ShutdownReport, DEFAULT_CLOSE_GRACE_PERIOD_MS};
+use self::signals::{SessionSignal, SignalTarget};
+use self::scrollback::{ScrollbackBuffer, ScrollbackRead, DEFAULT_SCROLLBACK_LINES};
+use self::reaper::SessionActivity;
+use self::session::{ProcessExit, SessionStatus};
+
 /// Configurazione PTY
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PtyConfig {
     pub cols: u16,
     pub rows: u16,
     pub shell: String,
+    /// Argomenti passati alla shell
+    #[serde(default)]
+    pub args: Vec<String>,
     pub cwd: String,
     pub env_vars: HashMap<String, String>,
+    /// Avvia la shell come shell di login
+    #[serde(default)]
+    pub login: bool,
+    /// Comando inviato alla shell appena avviata
+    #[serde(default)]
+    pub startup_command: Option<String>,
+    /// Righe di scrollback conservate (da `terminal.scrollback`)
+    #[serde(default = "default_scrollback")]
+    pub scrollback: usize,
+    /// Inietta gli hook di integrazione per bash, zsh e fish
+    #[serde(default = "default_shell_integration")]
+    pub shell_integration: bool,
+    /// Attesa tra SIGHUP e SIGKILL alla chiusura (da `terminal.close_grace_period_ms`)
+    #[serde(default = "default_close_grace_period_ms")]
+    pub close_grace_period_ms: u64,
+}
+
+fn default_scrollback() -> usize {
+    DEFAULT_SCROLLBACK_LINES
+}
+
+fn default_shell_integration() -> bool {
+    true
+}
+
+fn default_close_grace_period_ms() -> u64 {
+    DEFAULT_CLOSE_GRACE_PERIOD_MS
 }
 
 impl Default for PtyConfig {
@@ -31,8 +107,14 @@ impl Default for PtyConfig {
             cols: 80,
             rows: 24,
             shell: std::env::var("SHELL").unwrap_or_else(|_| "zsh".to_string()),
+            args: Vec::new(),
             cwd: std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string()),
             env_vars,
+            login: false,
+            startup_command: None,
+            scrollback: DEFAULT_SCROLLBACK_LINES,
+            shell_integration: true,
+            close_grace_period_ms: DEFAULT_CLOSE_GRACE_PERIOD_MS,
         }
     }
 }
@@ -43,14 +125,72 @@ pub struct RealPtySession {
     pub master: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
     pub child_process: Arc<Mutex<Box<dyn portable_pty::Child + Send>>>,
     pub config: PtyConfig,
-    pub buffer: Arc<Mutex<Vec<u8>>>,
+    pub buffer: Arc<Mutex<ScrollbackBuffer>>,
     pub is_active: Arc<Mutex<bool>>,
     pub last_activity: Arc<Mutex<u64>>,
+    pub output: Arc<OutputBroadcaster>,
+    pub input: InputQueue,
+    pub screen: Arc<Mutex<TerminalScreen>>,
+    pub commands: Arc<Mutex<CommandTracker>>,
+    pub state: Arc<Mutex<SessionState>>,
+    /// Istante del salvataggio da cui la sessione è stata ripristinata
+    pub restored_at: Option<u64>,
+    /// Registrazione asciicast in corso
+    pub recorder: Arc<Mutex<Option<Recorder>>>,
+}
+
+/// Stato osservabile della sessione e listener da notificare ai cambiamenti.
+///
+/// Stanno sotto lo stesso lock così ogni evento viene emesso una sola volta
+/// anche se il listener viene registrato mentre lo stato cambia.
+#[derive(Default)]
+pub struct SessionState {
+    pub exit_status: Option<ProcessExit>,
+    /// Ultima directory corrente nota
+    pub cwd: String,
+    /// La shell comunica la directory con OSC 7: `/proc` non serve
+    pub cwd_reported: bool,
+    listener: Option<SessionEventListener>,
+}
+
+impl SessionState {
+    fn emit(&self, event: SessionEvent) {
+        if let Some(listener) = &self.listener {
+            listener(&event);
+        }
+    }
+
+    /// Aggiorna la directory corrente, notificando solo i cambiamenti
+    fn update_cwd(&mut self, session_id: &str, cwd: String) {
+        if cwd.is_empty() || cwd == self.cwd {
+            return;
+        }
+        debug!("PTY session {} changed directory: {}", session_id, cwd);
+        self.cwd = cwd.clone();
+        self.emit(SessionEvent::CwdChanged {
+            session_id: session_id.to_string(),
+            cwd,
+        });
+    }
 }
 
 impl RealPtySession {
     /// Crea una nuova sessione PTY
     pub fn new(id: String, config: PtyConfig) -> Result<Self> {
+        Self::spawn(id, config, None)
+    }
+
+    /// Ricrea una sessione salvata: lo scrollback precedente precede
+    /// l'output della nuova shell
+    pub fn restore(saved: &SavedSession) -> Result<Self> {
+        let mut config = saved.config.clone();
+        if Path::new(&saved.cwd).is_dir() {
+            config.cwd = saved.cwd.clone();
+        }
+        Self::spawn(saved.id.clone(), config, Some(saved))
+    }
+
+    fn spawn(id: String, config: PtyConfig, saved: Option<&SavedSession>) -> Result<Self> {
         info!("Creating real PTY session: {}", id);
         
         let pty_system = native_pty_system();
@@ -62,35 +202,93 @@ impl RealPtySession {
         })?;
 
         let mut cmd = CommandBuilder::new(&config.shell);
+        cmd.args(&config.args);
         cmd.cwd(&config.cwd);
         for (key, val) in &config.env_vars {
             cmd.env(key, val);
         }
+        let kind = ShellKind::detect(&config.shell);
+        let mut integrated = false;
+        if config.shell_integration {
+            if let Some(kind) = kind {
+                // Senza integrazione la sessione funziona comunque
+                match shell_hooks::apply(&mut cmd, kind) {
+                    Ok(()) => integrated = true,
+                    Err(e) => warn!("Shell integration disabled for session {}: {}", id, e),
+                }
+            }
+        }
+        if config.login {
+            if integrated && kind == Some(ShellKind::Bash) {
+                // bash ignora `--rcfile` come shell di login: i file di
+                // login li carica lo script di integrazione
+                cmd.env("TERMINA_BASH_LOGIN", "1");
+            } else {
+                // bash vuole le opzioni lunghe prima di quelle brevi
+                let flag = if kind.is_some() { "--login" } else { "-l" };
+                cmd.get_argv_mut().insert(1, flag.into());
+            }
+        }
 
         let child = pty_pair.slave.spawn_command(cmd)?;
+        let mut buffer = ScrollbackBuffer::new(config.scrollback);
+        let mut screen = TerminalScreen::new(config.cols, config.rows, config.scrollback);
+        if let Some(saved) = saved {
+            // Il reader non è ancora partito: lo storico precede ogni output nuovo
+            let history = saved.history()?;
+            buffer.append(&history);
+            screen.process(&history);
+            if screen.title().is_empty() && !saved.title.is_empty() {
+                screen.set_title(&saved.title);
+            }
+        }
+        // Lo storico non passa dal broadcaster: i blocchi partono dopo di esso
+        let output = Arc::new(OutputBroadcaster::new(id.clone()));
+        output.advance_to(buffer.end_offset());
+        // Il writer si può ottenere una sola volta: resta al thread di input
+        let writer = pty_pair.master.take_writer()?;
+        let state = SessionState {
+            cwd: config.cwd.clone(),
+            ..SessionState::default()
+        };
         
         let session = Self {
             id: id.clone(),
             master: Arc::new(Mutex::new(pty_pair.master)),
             child_process: Arc::new(Mutex::new(child)),
             config,
-            buffer: Arc::new(Mutex::new(Vec::new())),
+            buffer: Arc::new(Mutex::new(buffer)),
             is_active: Arc::new(Mutex::new(true)),
             last_activity: Arc::new(Mutex::new(Self::current_timestamp())),
+            output,
+            input: InputQueue::start(id.clone(), writer),
+            screen: Arc::new(Mutex::new(screen)),
+            commands: Arc::new(Mutex::new(CommandTracker::new())),
+            state: Arc::new(Mutex::new(state)),
+            restored_at: saved.map(|saved| saved.saved_at),
+            recorder: Arc::new(Mutex::new(None)),
         };
         
         session.start_output_reader();
+        if let Some(command) = &session.config.startup_command {
+            // La shell lo legge dal terminale al termine dell'avvio
+            session.input.enqueue(format!("{}\n", command).into_bytes())?;
+        }
         info!("Real PTY session created successfully: {}", id);
         Ok(session)
     }
     
-    /// Scrive dati alla sessione PTY
+    /// Accoda testo da scrivere alla sessione PTY
     pub fn write(&self, data: &str) -> Result<()> {
-        let mut writer = self.master.lock().unwrap().take_writer()?;
-        write!(writer, "{}", data)?;
-        writer.flush()?;
+        self.write_bytes(data.as_bytes())
+    }
+
+    /// Accoda byte arbitrari da scrivere alla sessione PTY
+    pub fn write_bytes(&self, data: &[u8]) -> Result<()> {
+        self.input.enqueue(data.to_vec())?;
+        recording::record(&self.recorder, &self.id, |recorder| recorder.input(data));
         *self.last_activity.lock().unwrap() = Self::current_timestamp();
-        debug!("Wrote {} bytes to PTY session {}", data.len(), self.id);
+        debug!("Queued {} bytes for PTY session {}", data.len(), self.id);
         Ok(())
     }
     
@@ -103,27 +301,112 @@ impl RealPtySession {
             pixel_width: 0,
             pixel_height: 0,
         })?;
+        self.screen.lock().unwrap().resize(cols, rows);
+        recording::record(&self.recorder, &self.id, |recorder| recorder.resize(cols, rows));
         Ok(())
     }
+
+    /// Avvia la registrazione asciicast della sessione
+    pub fn start_recording(&self, path: &Path, record_input: bool) -> Result<()> {
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(active) = recorder.as_ref() {
+            return Err(anyhow!(
+                "Session {} is already being recorded to {}",
+                self.id,
+                active.path().display()
+            ));
+        }
+
+        let (cols, rows, title) = {
+            let screen = self.screen.lock().unwrap();
+            let (cols, rows) = screen.size();
+            (cols as u16, rows as u16, screen.title().to_string())
+        };
+        let mut env = HashMap::new();
+        env.insert("SHELL".to_string(), self.config.shell.clone());
+        if let Some(term) = self.config.env_vars.get("TERM") {
+            env.insert("TERM".to_string(), term.clone());
+        }
+        let header = CastHeader {
+            version: 2,
+            width: cols,
+            height: rows,
+            timestamp: Some(Self::current_timestamp()),
+            idle_time_limit: None,
+            title: (!title.is_empty()).then_some(title),
+            env: Some(env),
+        };
+        *recorder = Some(Recorder::create(path, &header, record_input)?);
+        info!("Recording PTY session {} to {}", self.id, path.display());
+        Ok(())
+    }
+
+    /// Termina la registrazione e restituisce il percorso del file
+    pub fn stop_recording(&self) -> Result<PathBuf> {
+        let recorder = self
+            .recorder
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow!("Session {} is not being recorded", self.id))?;
+        let path = recorder.finish()?;
+        info!("Stopped recording PTY session {}", self.id);
+        Ok(path)
+    }
+
+    /// File della registrazione in corso
+    pub fn recording_path(&self) -> Option<PathBuf> {
+        self.recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|recorder| recorder.path().to_path_buf())
+    }
     
-    /// Termina la sessione PTY
+    /// Termina subito con SIGKILL la shell e tutti i processi della sessione
     pub fn kill(&self) -> Result<()> {
         info!("Killing PTY session: {}", self.id);
-        *self.is_active.lock().unwrap() = false;
-        self.child_process.lock().unwrap().kill()?;
-        Ok(())
+        self.shutdown(Duration::ZERO).map(|_| ())
     }
     
-    /// Chiude la sessione PTY
-    pub fn close(&self) -> Result<()> {
+    /// Chiude la sessione PTY: SIGHUP ai processi della sessione e SIGKILL a
+    /// quelli ancora vivi dopo il periodo di grazia configurato
+    pub fn close(&self) -> Result<ShutdownReport> {
         info!("Closing PTY session: {}", self.id);
-        self.kill()
+        self.shutdown(Duration::from_millis(self.config.close_grace_period_ms))
+    }
+
+    fn shutdown(&self, grace_period: Duration) -> Result<ShutdownReport> {
+        *self.is_active.lock().unwrap() = false;
+        self.input.close();
+        let pid = self.child_process.lock().unwrap().process_id();
+        // Dopo l'uscita della shell restano da chiudere i processi rimasti
+        // nella sua sessione; il pid della shell può essere stato riassegnato
+        if self.exit_status().is_some() {
+            return Ok(match pid {
+                Some(pid) if cfg!(unix) => shutdown::shutdown_leftovers(&self.id, pid as i32, grace_period),
+                _ => ShutdownReport {
+                    session_id: self.id.clone(),
+                    ..Default::default()
+                },
+            });
+        }
+        match pid {
+            Some(pid) if cfg!(unix) => Ok(shutdown::shutdown(&self.id, pid as i32, grace_period)),
+            _ => {
+                self.child_process.lock().unwrap().kill()?;
+                Ok(ShutdownReport {
+                    session_id: self.id.clone(),
+                    ..Default::default()
+                })
+            }
+        }
     }
     
     /// Pulisce il buffer della sessione
     pub fn clear(&self) -> Result<()> {
         debug!("Clearing PTY session: {}", self.id);
-        *self.buffer.lock().unwrap() = Vec::new();
+        self.buffer.lock().unwrap().clear();
         Ok(())
     }
     
@@ -134,35 +417,224 @@ impl RealPtySession {
     }
     
     /// Ottiene l'output incrementale dalla sessione
-    pub fn get_incremental_output(&self, from_index: usize) -> Result<String, anyhow::Error> {
-        let buffer = self.buffer.lock().unwrap();
-        if from_index >= buffer.len() {
-            Ok(String::new())
-        } else {
-            Ok(String::from_utf8_lossy(&buffer[from_index..]).to_string())
-        }
+    pub fn get_incremental_output(&self, from_offset: u64) -> Result<String, anyhow::Error> {
+        let read = self.read_output(from_offset);
+        Ok(String::from_utf8_lossy(&read.data).to_string())
+    }
+
+    /// Legge i byte dello scrollback a partire da un offset assoluto,
+    /// segnalando quanti byte richiesti sono già stati scartati
+    pub fn read_output(&self, from_offset: u64) -> ScrollbackRead {
+        self.buffer.lock().unwrap().read_from(from_offset)
     }
     
     /// Ottiene la lunghezza del buffer
     pub fn get_buffer_length(&self) -> usize {
         self.buffer.lock().unwrap().len()
     }
-    
+
+    /// Istantanea dello schermo renderizzato della sessione
+    pub fn get_screen(&self, include_history: bool) -> ScreenSnapshot {
+        self.screen.lock().unwrap().snapshot(include_history)
+    }
+
+    /// Comandi rilevati tramite l'integrazione con la shell
+    pub fn list_commands(&self) -> Vec<CommandRecord> {
+        self.commands.lock().unwrap().records()
+    }
+
+    /// Restituisce un comando e il suo output grezzo dallo scrollback
+    pub fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        let record = self
+            .commands
+            .lock()
+            .unwrap()
+            .record(command_id)
+            .ok_or_else(|| anyhow!("Command {} not found in session {}", command_id, self.id))?;
+
+        let mut read = self.read_output(record.output_start);
+        if let Some(end) = record.output_end {
+            let len = end.saturating_sub(read.start) as usize;
+            read.data.truncate(len);
+            read.end = read.start + read.data.len() as u64;
+        }
+        Ok((record, read))
+    }
+
+    /// Registra un sottoscrittore all'output in streaming della sessione;
+    /// restituisce (id, offset del primo byte che riceverà)
+    pub fn subscribe_output(&self, subscriber: OutputSubscriber) -> (u64, u64) {
+        self.output.subscribe_at(subscriber)
+    }
+
+    /// Rimuove un sottoscrittore all'output della sessione
+    pub fn unsubscribe_output(&self, subscriber_id: u64) -> bool {
+        self.output.unsubscribe(subscriber_id)
+    }
+
+    /// Registra il listener degli eventi della sessione; se il processo è
+    /// già terminato l'evento di uscita viene emesso subito
+    pub fn set_event_listener(&self, listener: SessionEventListener) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(status) = &state.exit_status {
+            listener(&SessionEvent::Exited {
+                session_id: self.id.clone(),
+                exit_status: status.clone(),
+            });
+        }
+        state.listener = Some(listener);
+    }
+
+    /// Esito del processo, se già terminato
+    pub fn exit_status(&self) -> Option<ProcessExit> {
+        self.state.lock().unwrap().exit_status.clone()
+    }
+
+    /// Directory in cui si trova l'utente.
+    ///
+    /// Se la shell la comunica con OSC 7 si usa l'ultimo valore ricevuto,
+    /// altrimenti (su Linux) quella del gruppo di processi in primo piano.
+    pub fn get_cwd(&self) -> String {
+        let reported = self.state.lock().unwrap().cwd_reported;
+        if !reported {
+            if let Some(cwd) = self.foreground_pid().and_then(process::process_cwd) {
+                self.state.lock().unwrap().update_cwd(&self.id, cwd);
+            }
+        }
+        self.state.lock().unwrap().cwd.clone()
+    }
+
+    /// Pid del leader del gruppo di processi in primo piano sul terminale,
+    /// o della shell se non è determinabile
+    fn foreground_pid(&self) -> Option<i32> {
+        #[cfg(unix)]
+        let leader = self.master.lock().unwrap().process_group_leader();
+        #[cfg(not(unix))]
+        let leader = None;
+        leader.or_else(|| self.child_process.lock().unwrap().process_id().map(|pid| pid as i32))
+    }
+
+    /// Invia un segnale alla shell o al gruppo di processi in primo piano,
+    /// restituendo il pid o il pgid raggiunto
+    pub fn signal(&self, signal: SessionSignal, target: SignalTarget) -> Result<i32> {
+        if self.exit_status().is_some() {
+            return Err(anyhow!("PTY session {} has already exited", self.id));
+        }
+        let shell_pid = self
+            .child_process
+            .lock()
+            .unwrap()
+            .process_id()
+            .map(|pid| pid as i32)
+            .ok_or_else(|| anyhow!("PTY session {} has no process", self.id))?;
+
+        let pid = match target {
+            SignalTarget::Shell => {
+                signals::send_to_process(shell_pid, signal)?;
+                shell_pid
+            }
+            SignalTarget::Foreground => {
+                // Al prompt il gruppo in primo piano è quello della shell
+                let pgid = self.foreground_pid().unwrap_or(shell_pid);
+                signals::send_to_group(pgid, signal)?;
+                pgid
+            }
+        };
+        info!("Sent {:?} to {:?} of PTY session {} ({})", signal, target, self.id, pid);
+        Ok(pid)
+    }
+
+    /// Configurazione per una nuova sessione nello stesso contesto: directory
+    /// corrente e dimensioni attuali, e a richiesta l'ambiente della shell
+    pub fn duplicate_config(&self, inherit_env: bool) -> PtyConfig {
+        let mut config = self.config.clone();
+        config.cwd = self.get_cwd();
+        let (cols, rows) = self.screen.lock().unwrap().size();
+        config.cols = cols as u16;
+        config.rows = rows as u16;
+        // Il comando iniziale appartiene alla sessione originale
+        config.startup_command = None;
+
+        let pid = self.child_process.lock().unwrap().process_id();
+        if inherit_env {
+            match pid.and_then(|pid| process::process_environ(pid as i32)) {
+                Some(mut environ) => {
+                    shell_hooks::restore_user_env(&mut environ);
+                    for key in ["PWD", "OLDPWD"] {
+                        environ.remove(key);
+                    }
+                    config.env_vars.extend(environ);
+                }
+                None => warn!("Cannot read the environment of PTY session {}", self.id),
+            }
+        }
+        config
+    }
+
+    /// Programmi in esecuzione nella sessione e stato del prompt, per
+    /// decidere se chiedere conferma prima della chiusura
+    pub fn close_info(&self, ignore: &[String]) -> SessionCloseInfo {
+        if self.exit_status().is_some() {
+            return SessionCloseInfo::new(&self.id, true, Vec::new());
+        }
+        let pid = self.child_process.lock().unwrap().process_id();
+        let running_command = self.commands.lock().unwrap().current_command().is_some();
+        let idle = match self.foreground_process() {
+            Some(process) => process.is_shell && !running_command,
+            None => !running_command,
+        };
+        let processes = pid
+            .map(|pid| close_check::running_processes(pid as i32, ignore))
+            .unwrap_or_default();
+        SessionCloseInfo::new(&self.id, idle, processes)
+    }
+
+    /// Processo in primo piano sul terminale (la shell quando è al prompt)
+    pub fn foreground_process(&self) -> Option<ProcessInfo> {
+        let pid = self.foreground_pid()?;
+        let shell_pid = self.child_process.lock().unwrap().process_id().map(|pid| pid as i32);
+        process::process_info(pid, shell_pid)
+    }
+
     /// Ottiene lo stato della sessione
     pub fn get_status(&self) -> crate::pty::session::SessionStatus {
-        let mut child = self.child_process.lock().unwrap();
-        let pid = child.process_id();
-        let is_executing = child.try_wait().unwrap_or(None).is_none();
+        let pid = self.child_process.lock().unwrap().process_id();
+        let exit_status = self.exit_status();
+        let foreground = if exit_status.is_none() {
+            self.foreground_process()
+        } else {
+            None
+        };
+        // In esecuzione significa che in primo piano c'è un programma diverso
+        // dalla shell; senza `/proc` si usano i marcatori della shell
+        let is_executing = match &foreground {
+            Some(process) => !process.is_shell,
+            None => exit_status.is_none() && self.commands.lock().unwrap().current_command().is_some(),
+        };
+        let current_command = self
+            .commands
+            .lock()
+            .unwrap()
+            .current_command()
+            .map(|record| record.command.clone())
+            .unwrap_or_default();
 
         crate::pty::session::SessionStatus {
             id: self.id.clone(),
             is_active: *self.is_active.lock().unwrap(),
             is_executing,
-            current_command: String::new(), // Questo è difficile da tracciare in un PTY reale
+            current_command,
             last_activity: *self.last_activity.lock().unwrap(),
             buffer_size: self.buffer.lock().unwrap().len(),
-            cwd: self.config.cwd.clone(),
+            cwd: self.get_cwd(),
             pid,
+            exit_status,
+            foreground_process: foreground,
+            restored_at: self.restored_at,
+            recording: self
+                .recording_path()
+                .map(|path| path.to_string_lossy().to_string()),
+            read_only: false,
         }
     }
     
@@ -174,10 +646,16 @@ impl RealPtySession {
     /// Avvia il thread per leggere l'output
     fn start_output_reader(&self) {
         let master = self.master.clone();
+        let child_process = self.child_process.clone();
+        let state = self.state.clone();
         let buffer = self.buffer.clone();
+        let screen = self.screen.clone();
+        let commands = self.commands.clone();
         let is_active = self.is_active.clone();
         let last_activity = self.last_activity.clone();
         let session_id = self.id.clone();
+        let recorder = self.recorder.clone();
+        let batcher = self.output.start_batcher();
         
         thread::spawn(move || {
             info!("Starting output reader for PTY session: {}", session_id);
@@ -207,8 +685,25 @@ impl RealPtySession {
                     }
                     Ok(n) => {
                         let data = &read_buffer[..n];
-                        buffer.lock().unwrap().extend_from_slice(data);
+                        let offset = buffer.lock().unwrap().append(data);
+                        {
+                            let mut screen = screen.lock().unwrap();
+                            let mut commands = commands.lock().unwrap();
+                            screen.process_with(data, |screen, event| {
+                                commands.handle_event(screen, event);
+                                if let ScreenEvent::Osc(params) = &event.event {
+                                    if let Some(cwd) = cwd::parse_osc7(params) {
+                                        let mut state = state.lock().unwrap();
+                                        state.cwd_reported = true;
+                                        state.update_cwd(&session_id, cwd);
+                                    }
+                                }
+                            });
+                        }
+                        recording::record(&recorder, &session_id, |recorder| recorder.output(data));
                         *last_activity.lock().unwrap() = Self::current_timestamp();
+                        // Il batcher termina da solo quando il reader si chiude
+                        let _ = batcher.send((offset, data.to_vec()));
                     }
                     Err(e) => {
                         // `io::ErrorKind::BrokenPipe` è normale quando il processo figlio termina
@@ -222,6 +717,46 @@ impl RealPtySession {
             
             *is_active.lock().unwrap() = false;
             info!("Output reader finished for PTY session: {}", session_id);
+            // La registrazione si chiude con la sessione
+            if let Some(recorder) = recorder.lock().unwrap().take() {
+                if let Err(e) = recorder.finish() {
+                    warn!("Failed to finish recording of PTY session {}: {}", session_id, e);
+                }
+            }
+            Self::reap_child(&session_id, &child_process, &state);
+        });
+    }
+
+    /// Attende la terminazione del figlio dopo la chiusura del PTY e ne
+    /// registra l'esito, notificando il listener
+    fn reap_child(
+        session_id: &str,
+        child_process: &Mutex<Box<dyn portable_pty::Child + Send>>,
+        state: &Mutex<SessionState>,
+    ) {
+        // Il figlio può chiudere il terminale poco prima di uscire: si
+        // interroga con intervalli crescenti senza tenere il lock bloccato
+        let mut delay = Duration::from_millis(10);
+        let status = loop {
+            match child_process.lock().unwrap().try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to reap child of PTY session {}: {}", session_id, e);
+                    return;
+                }
+            }
+            thread::sleep(delay);
+            delay = (delay * 2).min(Duration::from_secs(1));
+        };
+
+        let status = ProcessExit::from_status(&status, Self::current_timestamp());
+        info!("PTY session {} exited: {:?}", session_id, status);
+        let mut state = state.lock().unwrap();
+        state.exit_status = Some(status.clone());
+        state.emit(SessionEvent::Exited {
+            session_id: session_id.to_string(),
+            exit_status: status,
         });
     }
     
@@ -240,134 +775,102 @@ impl Drop for RealPtySession {
     }
 }
 
-/// Manager per le sessioni PTY reali
-#[derive(Default)]
-pub struct RealPtyManager {
-    sessions: HashMap<String, Arc<RealPtySession>>,
-}
+impl SessionBackend for RealPtySession {
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-impl RealPtyManager {
-    /// Crea un nuovo manager PTY
-    pub fn new() -> Self {
-        Self {
-            sessions: HashMap::new(),
-        }
+    fn write(&self, data: &[u8]) -> Result<()> {
+        self.write_bytes(data)
     }
-    
-    /// Crea una nuova sessione PTY
-    pub fn create_session(&mut self, session_id: &str, cwd: Option<String>) -> Result<()> {
-        info!("Creating real PTY session: {}", session_id);
-        
-        if self.sessions.contains_key(session_id) {
-            return Err(anyhow!("Session with ID {} already exists", session_id));
-        }
 
-        let cwd = cwd.unwrap_or_else(|| {
-            std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string())
-        });
-        
-        let mut config = PtyConfig::default();
-        config.cwd = cwd;
-        
-        let session = RealPtySession::new(session_id.to_string(), config)?;
-        
-        self.sessions.insert(session_id.to_string(), Arc::new(session));
-        info!("Real PTY session created successfully: {}", session_id);
-        
-        Ok(())
+    fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        RealPtySession::resize(self, cols, rows)
     }
-    
-    /// Scrive dati a una sessione
-    pub fn write_to_session(&self, session_id: &str, data: &str) -> Result<()> {
-        if let Some(session) = self.sessions.get(session_id) {
-            debug!("Writing to PTY session {}: {} bytes", session_id, data.len());
-            session.write(data)
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead> {
+        Ok(RealPtySession::read_output(self, from_offset))
     }
-    
-    /// Ridimensiona una sessione PTY
-    pub fn resize_session(&self, session_id: &str, cols: u16, rows: u16) -> Result<()> {
-        if let Some(session) = self.sessions.get(session_id) {
-            debug!("Resizing PTY session {} to {}x{}", session_id, cols, rows);
-            session.resize(cols, rows)
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        Ok(RealPtySession::subscribe_output(self, subscriber))
     }
-    
-    /// Termina una sessione
-    pub fn kill_session(&mut self, session_id: &str) -> Result<()> {
-        if let Some(session) = self.sessions.remove(session_id) {
-            info!("Killing PTY session: {}", session_id);
-            session.kill()
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn unsubscribe_output(&self, subscription_id: u64) -> bool {
+        RealPtySession::unsubscribe_output(self, subscription_id)
     }
-    
-    /// Chiude una sessione
-    pub fn close_session(&mut self, session_id: &str) -> Result<()> {
-        if let Some(session) = self.sessions.remove(session_id) {
-            info!("Closing PTY session: {}", session_id);
-            session.close()
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn clear(&self) -> Result<()> {
+        RealPtySession::clear(self)
     }
-    
-    /// Pulisce una sessione
-    pub fn clear_session(&self, session_id: &str) -> Result<()> {
-        if let Some(session) = self.sessions.get(session_id) {
-            debug!("Clearing PTY session: {}", session_id);
-            session.clear()
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+        Ok(RealPtySession::get_screen(self, include_history))
     }
-    
-    /// Esegue un comando in una sessione
-    pub fn run_command(&self, session_id: &str, command: &str) -> Result<()> {
-        if let Some(session) = self.sessions.get(session_id) {
-            info!("Running command in PTY session {}: {}", session_id, command);
-            session.run_command(command)
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn status(&self) -> Result<SessionStatus> {
+        Ok(self.get_status())
     }
-    
-    /// Ottiene una sessione
-    pub fn get_session(&self, session_id: &str) -> Option<Arc<RealPtySession>> {
-        self.sessions.get(session_id).cloned()
+
+    fn set_event_listener(&self, listener: SessionEventListener) {
+        RealPtySession::set_event_listener(self, listener)
     }
-    
-    /// Ottiene l'output incrementale di una sessione
-    pub fn get_incremental_output(&self, session_id: &str, from_index: usize) -> Result<String> {
-        if let Some(session) = self.sessions.get(session_id) {
-            session.get_incremental_output(from_index)
-        } else {
-            Err(anyhow!("PTY session not found: {}", session_id))
-        }
+
+    fn close(&self) -> Result<ShutdownReport> {
+        RealPtySession::close(self)
     }
-    
-    /// Ottiene tutte le sessioni attive
-    pub fn get_active_sessions(&self) -> Vec<String> {
-        self.sessions.keys().cloned().collect()
+
+    fn kill(&self) -> Result<()> {
+        RealPtySession::kill(self)
     }
-    
-    /// Pulisce le sessioni inattive
-    pub fn cleanup_inactive_sessions(&mut self) {
-        let to_remove: Vec<String> = self.sessions
-            .iter()
-            .filter(|(_, session)| !*session.is_active.lock().unwrap())
-            .map(|(id, _)| id.clone())
-            .collect();
-        
-        for session_id in &to_remove {
-            info!("Cleaning up inactive session: {}", session_id);
-            self.sessions.remove(session_id);
-        }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn cwd(&self) -> Result<String> {
+        Ok(self.get_cwd())
+    }
+
+    fn foreground_process(&self) -> Result<Option<ProcessInfo>> {
+        Ok(RealPtySession::foreground_process(self))
+    }
+
+    fn list_commands(&self) -> Result<Vec<CommandRecord>> {
+        Ok(RealPtySession::list_commands(self))
+    }
+
+    fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        RealPtySession::get_command_output(self, command_id)
+    }
+
+    fn signal(&self, signal: SessionSignal, target: SignalTarget) -> Result<i32> {
+        RealPtySession::signal(self, signal, target)
+    }
+
+    fn start_recording(&self, path: &Path, record_input: bool) -> Result<()> {
+        RealPtySession::start_recording(self, path, record_input)
+    }
+
+    fn stop_recording(&self) -> Result<PathBuf> {
+        RealPtySession::stop_recording(self)
+    }
+
+    fn duplicate_config(&self, inherit_env: bool) -> Result<PtyConfig> {
+        Ok(RealPtySession::duplicate_config(self, inherit_env))
+    }
+
+    fn close_info(&self, ignore: &[String]) -> Result<SessionCloseInfo> {
+        Ok(RealPtySession::close_info(self, ignore))
+    }
+
+    fn activity(&self) -> Option<SessionActivity> {
+        Some(SessionActivity {
+            session_id: self.id.clone(),
+            exited_at: self.exit_status().map(|status| status.exited_at),
+            reader_active: *self.is_active.lock().unwrap(),
+            last_activity: self.get_last_activity(),
+        })
     }
 }
 
@@ -382,10 +885,4 @@ mod tests {
         assert_eq!(config.rows, 24);
         assert!(config.env_vars.contains_key("TERM"));
     }
-    
-    #[test]
-    fn test_real_pty_manager_creation() {
-        let manager = RealPtyManager::new();
-        assert_eq!(manager.sessions.len(), 0);
-    }
 }
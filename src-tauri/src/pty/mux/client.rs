@@ -0,0 +1,424 @@
+//! Lato applicazione di `termina-muxd`
+//!
+//! Il client invia le richieste sul socket e smista i messaggi in arrivo: le
+//! risposte vanno a chi le attende, l'output delle sessioni collegate viene
+//! ripubblicato su un `OutputBroadcaster` locale, così i sottoscrittori
+//! (eventi Tauri, `ipc::Channel`) funzionano come con le sessioni locali.
+
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::protocol::{read_message, write_message, Request, RequestFrame, ServerMessage};
+use crate::pty::events::SessionEventListener;
+use crate::pty::output::{OutputBroadcaster, OutputSubscriber};
+use crate::pty::PtyConfig;
+
+/// Attesa massima di una risposta
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attesa massima dell'avvio del demone
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+type PendingResponse = Sender<Result<Value, String>>;
+
+/// Una connessione al socket, con le risposte che vi sono attese
+struct Connection {
+    writer: Mutex<UnixStream>,
+    pending: Mutex<HashMap<u64, PendingResponse>>,
+    connected: AtomicBool,
+}
+
+/// Connessione al demone. Se il socket cade (es. demone riavviato) la
+/// richiesta successiva si ricollega, avviando il demone se serve, e
+/// ricollega le sessioni già note.
+pub struct MuxClient {
+    socket_path: PathBuf,
+    /// Eseguibile da avviare quando il demone non risponde
+    daemon: Option<PathBuf>,
+    connection: Mutex<Arc<Connection>>,
+    /// Serializza i tentativi di riconnessione
+    reconnecting: Mutex<()>,
+    broadcasters: Arc<Mutex<HashMap<String, Arc<OutputBroadcaster>>>>,
+    event_listener: Arc<Mutex<Option<SessionEventListener>>>,
+    next_id: AtomicU64,
+}
+
+impl MuxClient {
+    /// Si collega a un demone già in esecuzione
+    pub fn connect(socket_path: &Path) -> Result<Arc<Self>> {
+        Self::open(socket_path, None)
+    }
+
+    /// Si collega al demone, avviandolo se non è in esecuzione
+    pub fn connect_or_spawn(socket_path: &Path, daemon: &Path) -> Result<Arc<Self>> {
+        Self::open(socket_path, Some(daemon))
+    }
+
+    fn open(socket_path: &Path, daemon: Option<&Path>) -> Result<Arc<Self>> {
+        let stream = open_stream(socket_path, daemon)?;
+        let client = Arc::new(Self {
+            socket_path: socket_path.to_path_buf(),
+            daemon: daemon.map(Path::to_path_buf),
+            connection: Mutex::new(Arc::new(Connection::new(&stream)?)),
+            reconnecting: Mutex::new(()),
+            broadcasters: Arc::new(Mutex::new(HashMap::new())),
+            event_listener: Arc::new(Mutex::new(None)),
+            next_id: AtomicU64::new(1),
+        });
+        client.start_reader(stream, client.current());
+        info!("Connected to termina-muxd at {}", socket_path.display());
+        Ok(client)
+    }
+
+    fn current(&self) -> Arc<Connection> {
+        Arc::clone(&self.connection.lock().unwrap())
+    }
+
+    fn start_reader(&self, stream: UnixStream, connection: Arc<Connection>) {
+        let broadcasters = Arc::clone(&self.broadcasters);
+        let event_listener = Arc::clone(&self.event_listener);
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+            loop {
+                let message = match read_message::<ServerMessage>(&mut reader) {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("muxd connection error: {}", e);
+                        break;
+                    }
+                };
+                match message {
+                    ServerMessage::Response { id, result, error } => {
+                        if let Some(sender) = connection.pending.lock().unwrap().remove(&id) {
+                            let _ = sender.send(match error {
+                                Some(error) => Err(error),
+                                None => Ok(result),
+                            });
+                        }
+                    }
+                    ServerMessage::Output { session_id, offset, data } => {
+                        let broadcaster = broadcasters.lock().unwrap().get(&session_id).cloned();
+                        match (broadcaster, BASE64.decode(data)) {
+                            (Some(broadcaster), Ok(bytes)) => broadcaster.publish(offset, &bytes),
+                            (_, Err(e)) => warn!("Invalid muxd output for {}: {}", session_id, e),
+                            _ => {}
+                        }
+                    }
+                    ServerMessage::Event { event } => {
+                        if let Some(listener) = event_listener.lock().unwrap().as_ref() {
+                            listener(&event);
+                        }
+                    }
+                }
+            }
+
+            warn!("Disconnected from termina-muxd");
+            connection.connected.store(false, Ordering::SeqCst);
+            // Chi attende una risposta riceve l'errore invece del timeout
+            for (_, sender) in connection.pending.lock().unwrap().drain() {
+                let _ = sender.send(Err("muxd connection lost".to_string()));
+            }
+        });
+    }
+
+    /// La connessione è ancora attiva
+    pub fn is_connected(&self) -> bool {
+        self.current().connected.load(Ordering::SeqCst)
+    }
+
+    /// Imposta il listener degli eventi delle sessioni collegate
+    pub fn set_event_listener(&self, listener: SessionEventListener) {
+        *self.event_listener.lock().unwrap() = Some(listener);
+    }
+
+    /// Invia una richiesta e ne attende la risposta, ricollegandosi prima
+    /// se la connessione è caduta
+    pub fn call<T: DeserializeOwned>(&self, request: Request) -> Result<T> {
+        let connection = self.connected()?;
+        self.send(&connection, request)
+    }
+
+    fn send<T: DeserializeOwned>(&self, connection: &Connection, request: Request) -> Result<T> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        connection.pending.lock().unwrap().insert(id, tx);
+
+        let frame = RequestFrame { id, request };
+        if let Err(e) = write_message(&mut *connection.writer.lock().unwrap(), &frame) {
+            connection.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        let result = rx.recv_timeout(REQUEST_TIMEOUT).map_err(|_| {
+            connection.pending.lock().unwrap().remove(&id);
+            anyhow!("muxd request {} timed out", id)
+        })?;
+        let value = result.map_err(|e| anyhow!(e))?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Connessione attiva, ristabilita se è caduta
+    fn connected(&self) -> Result<Arc<Connection>> {
+        let connection = self.current();
+        if connection.connected.load(Ordering::SeqCst) {
+            return Ok(connection);
+        }
+
+        let _reconnecting = self.reconnecting.lock().unwrap();
+        // Un'altra richiesta potrebbe essersi già ricollegata
+        let connection = self.current();
+        if connection.connected.load(Ordering::SeqCst) {
+            return Ok(connection);
+        }
+        let stream = open_stream(&self.socket_path, self.daemon.as_deref())
+            .context("muxd connection lost")?;
+        let connection = Arc::new(Connection::new(&stream)?);
+        self.start_reader(stream, Arc::clone(&connection));
+        *self.connection.lock().unwrap() = Arc::clone(&connection);
+
+        // Le sessioni collegate tornano a ricevere l'output in push; quelle
+        // che il demone non possiede più (es. dopo un crash) si dimenticano
+        let session_ids: Vec<String> = self.broadcasters.lock().unwrap().keys().cloned().collect();
+        let mut reattached = 0;
+        for session_id in session_ids {
+            let attached: Result<u64> = self.send(
+                &connection,
+                Request::Attach {
+                    session_id: session_id.clone(),
+                },
+            );
+            match attached {
+                Ok(offset) => {
+                    if let Some(broadcaster) = self.broadcaster(&session_id) {
+                        broadcaster.advance_to(offset);
+                    }
+                    reattached += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to reattach muxd session {}: {}", session_id, e);
+                    self.forget(&session_id);
+                }
+            }
+        }
+        info!(
+            "Reconnected to termina-muxd at {}, reattached {} sessions",
+            self.socket_path.display(),
+            reattached
+        );
+        Ok(connection)
+    }
+
+    /// Id delle sessioni del demone, anche quelle create da altri client
+    pub fn list_sessions(&self) -> Result<Vec<String>> {
+        self.call(Request::ListSessions)
+    }
+
+    /// Crea una sessione nel demone, già collegata a questo client
+    pub fn create_session(
+        &self,
+        session_id: &str,
+        config: PtyConfig,
+        listener: Option<OutputSubscriber>,
+    ) -> Result<Arc<OutputBroadcaster>> {
+        // Il broadcaster esiste prima della richiesta: il primo output non va perso
+        let broadcaster = self.register_broadcaster(session_id, listener);
+        let created: Result<String> = self.call(Request::CreateSession {
+            session_id: session_id.to_string(),
+            config,
+        });
+        if let Err(e) = created {
+            self.forget(session_id);
+            return Err(e);
+        }
+        Ok(broadcaster)
+    }
+
+    /// Si collega all'output di una sessione; l'output ricevuto da qui in
+    /// avanti viene pubblicato sul broadcaster restituito
+    pub fn attach(&self, session_id: &str, listener: Option<OutputSubscriber>) -> Result<Arc<OutputBroadcaster>> {
+        if let Some(broadcaster) = self.broadcaster(session_id) {
+            return Ok(broadcaster);
+        }
+        let broadcaster = self.register_broadcaster(session_id, listener);
+        let attached: Result<u64> = self.call(Request::Attach {
+            session_id: session_id.to_string(),
+        });
+        match attached {
+            // Il demone invia in push i byte a partire da questo offset
+            Ok(offset) => {
+                broadcaster.advance_to(offset);
+                Ok(broadcaster)
+            }
+            Err(e) => {
+                self.forget(session_id);
+                Err(e)
+            }
+        }
+    }
+
+    fn register_broadcaster(&self, session_id: &str, listener: Option<OutputSubscriber>) -> Arc<OutputBroadcaster> {
+        let broadcaster = Arc::new(OutputBroadcaster::new(session_id.to_string()));
+        if let Some(listener) = listener {
+            broadcaster.subscribe(listener);
+        }
+        self.broadcasters
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), Arc::clone(&broadcaster));
+        broadcaster
+    }
+
+    /// Broadcaster locale di una sessione collegata
+    pub fn broadcaster(&self, session_id: &str) -> Option<Arc<OutputBroadcaster>> {
+        self.broadcasters.lock().unwrap().get(session_id).cloned()
+    }
+
+    /// Dimentica una sessione chiusa
+    pub fn forget(&self, session_id: &str) {
+        self.broadcasters.lock().unwrap().remove(session_id);
+    }
+}
+
+impl Connection {
+    fn new(stream: &UnixStream) -> Result<Self> {
+        Ok(Self {
+            writer: Mutex::new(stream.try_clone()?),
+            pending: Mutex::new(HashMap::new()),
+            connected: AtomicBool::new(true),
+        })
+    }
+}
+
+/// Si collega al socket solo se esso, le sue directory e il processo
+/// all'altro capo appartengono all'utente corrente
+fn connect_checked(socket_path: &Path) -> Result<UnixStream> {
+    super::check_socket(socket_path)?;
+    let stream = UnixStream::connect(socket_path)
+        .with_context(|| format!("Failed to connect to muxd at {}", socket_path.display()))?;
+    super::check_peer(&stream)?;
+    Ok(stream)
+}
+
+/// Apre il socket del demone; con `daemon` lo avvia se non risponde
+fn open_stream(socket_path: &Path, daemon: Option<&Path>) -> Result<UnixStream> {
+    let connect = || connect_checked(socket_path);
+    let daemon = match (connect(), daemon) {
+        (Ok(stream), _) => return Ok(stream),
+        (Err(e), None) => return Err(e),
+        (Err(_), Some(daemon)) => daemon,
+    };
+
+    info!("Starting termina-muxd: {}", daemon.display());
+    let mut command = Command::new(daemon);
+    command
+        .arg("--socket")
+        .arg(socket_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Nuova sessione: il demone sopravvive alla chiusura dell'applicazione
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    command
+        .spawn()
+        .with_context(|| format!("Failed to start muxd: {}", daemon.display()))?;
+
+    let deadline = Instant::now() + SPAWN_TIMEOUT;
+    loop {
+        match connect() {
+            Ok(stream) => return Ok(stream),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+/// Percorso del demone. Tauri installa il sidecar `binaries/termina-muxd`
+/// (`bundle.externalBin`) accanto all'eseguibile dell'applicazione, senza il
+/// suffisso del target; se manca si cerca `termina-muxd` nel `PATH`.
+pub fn daemon_path() -> PathBuf {
+    let name = format!("termina-muxd{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    fn answer(reader: &mut BufReader<UnixStream>, result: Value) -> Request {
+        let frame: RequestFrame = read_message(reader).unwrap().unwrap();
+        let response = ServerMessage::Response {
+            id: frame.id,
+            result,
+            error: None,
+        };
+        write_message(reader.get_mut(), &response).unwrap();
+        frame.request
+    }
+
+    #[test]
+    fn test_reconnect_reattaches_sessions() {
+        let dir = std::env::temp_dir().join(format!("termina-mux-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("muxd.sock");
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let server = thread::spawn(move || {
+            // Prima connessione: risponde all'attach e cade
+            let mut first = BufReader::new(listener.accept().unwrap().0);
+            assert!(matches!(answer(&mut first, Value::from(0)), Request::Attach { .. }));
+            drop(first);
+            // Seconda connessione: la sessione viene ricollegata prima della richiesta
+            let mut second = BufReader::new(listener.accept().unwrap().0);
+            match answer(&mut second, Value::from(0)) {
+                Request::Attach { session_id } => assert_eq!(session_id, "s"),
+                request => panic!("unexpected request: {:?}", request),
+            }
+            assert!(matches!(
+                answer(&mut second, serde_json::json!(["s"])),
+                Request::ListSessions
+            ));
+        });
+
+        let client = MuxClient::connect(&socket).unwrap();
+        client.attach("s", None).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.is_connected() {
+            assert!(Instant::now() < deadline, "disconnection not detected");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let sessions: Vec<String> = client.call(Request::ListSessions).unwrap();
+        assert_eq!(sessions, vec!["s".to_string()]);
+        assert!(client.broadcaster("s").is_some());
+        server.join().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,165 @@
+//! Demone `termina-muxd`
+//!
+//! Opzionalmente i PTY possono appartenere a un demone separato invece che
+//! all'applicazione: le shell sopravvivono a un crash o a un riavvio della UI
+//! e, al nuovo collegamento, lo scrollback completo è ancora disponibile.
+//! L'applicazione diventa un client sottile che parla con il demone su un
+//! socket Unix (vedi `protocol`).
+
+pub mod client;
+pub mod protocol;
+pub mod remote;
+pub mod server;
+
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+/// Percorso predefinito del socket del demone, privato dell'utente
+pub fn default_socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(fallback_runtime_dir)
+        .join("termina")
+        .join("muxd.sock")
+}
+
+/// Directory usata senza `XDG_RUNTIME_DIR`, in una `/tmp` che può essere
+/// condivisa con altri utenti
+fn fallback_runtime_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("termina-{}", current_uid()))
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Directory del socket da creare e da verificare: quella dedicata
+/// `termina/` e, sotto `/tmp`, la `termina-<uid>` che la contiene
+fn private_dirs(socket_path: &Path) -> Vec<&Path> {
+    let mut dirs = Vec::new();
+    if let Some(parent) = socket_path.parent().filter(|parent| parent.ends_with("termina")) {
+        if let Some(base) = parent.parent().filter(|base| *base == fallback_runtime_dir()) {
+            dirs.push(base);
+        }
+        dirs.push(parent);
+    }
+    dirs
+}
+
+/// Crea le directory private del socket con permessi 0700, rifiutando
+/// quelle create da un altro utente (es. `termina-<uid>` in `/tmp`)
+pub fn create_private_dirs(socket_path: &Path) -> Result<()> {
+    for dir in private_dirs(socket_path) {
+        match DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create muxd directory: {}", dir.display()))
+            }
+        }
+        check_private_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// Verifica che socket e directory appartengano all'utente corrente prima
+/// di collegarsi: un socket altrui riceverebbe tutto l'input delle sessioni
+pub fn check_socket(socket_path: &Path) -> Result<()> {
+    for dir in private_dirs(socket_path) {
+        check_private_dir(dir)?;
+    }
+    let metadata = fs::symlink_metadata(socket_path)
+        .with_context(|| format!("muxd socket not found: {}", socket_path.display()))?;
+    if !metadata.file_type().is_socket() || metadata.uid() != current_uid() {
+        return Err(anyhow!(
+            "Refusing muxd socket {}: not a socket owned by uid {}",
+            socket_path.display(),
+            current_uid()
+        ));
+    }
+    Ok(())
+}
+
+/// La directory esiste, non è un link simbolico, appartiene all'utente
+/// corrente e non dà permessi a gruppo e altri
+fn check_private_dir(dir: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(dir)
+        .with_context(|| format!("muxd directory not found: {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+        return Err(anyhow!(
+            "Refusing muxd directory {}: not a private directory owned by uid {}",
+            dir.display(),
+            current_uid()
+        ));
+    }
+    Ok(())
+}
+
+/// Uid del processo all'altro capo del socket
+pub fn peer_uid(stream: &UnixStream) -> Result<u32> {
+    let fd = stream.as_raw_fd();
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut credentials as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to read muxd peer credentials");
+        }
+        Ok(credentials.uid)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let (mut uid, mut gid) = (0, 0);
+        if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to read muxd peer credentials");
+        }
+        Ok(uid)
+    }
+}
+
+/// Rifiuta una connessione con un processo di un altro utente
+pub fn check_peer(stream: &UnixStream) -> Result<()> {
+    let uid = peer_uid(stream)?;
+    if uid != current_uid() {
+        return Err(anyhow!("Refusing muxd peer with uid {}", uid));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_private_dirs_are_checked() {
+        let base = std::env::temp_dir().join(format!("termina-mux-dirs-{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let socket = base.join("termina").join("muxd.sock");
+
+        create_private_dirs(&socket).unwrap();
+        let mode = fs::metadata(base.join("termina")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // Il socket non esiste ancora
+        assert!(check_socket(&socket).is_err());
+
+        // Una directory leggibile da altri non è accettata
+        fs::set_permissions(base.join("termina"), fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(create_private_dirs(&socket).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,169 @@
+//! Protocollo tra `termina-muxd` e l'applicazione
+//!
+//! Ogni messaggio è un oggetto JSON su una riga. Il client invia richieste
+//! numerate; il demone risponde con lo stesso `id` e, per le sessioni a cui
+//! il client è collegato, invia senza richiesta l'output e gli eventi.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::pty::events::SessionEvent;
+use crate::pty::reaper::ReaperPolicy;
+use crate::pty::scrollback::ScrollbackRead;
+use crate::pty::shell_integration::CommandRecord;
+use crate::pty::signals::{SessionSignal, SignalTarget};
+use crate::pty::PtyConfig;
+
+/// Operazioni richieste al demone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Request {
+    CreateSession { session_id: String, config: PtyConfig },
+    /// Collega la connessione all'output e agli eventi della sessione
+    Attach { session_id: String },
+    Detach { session_id: String },
+    ListSessions,
+    /// `data` in base64
+    Write { session_id: String, data: String },
+    ReadBytes { session_id: String, offset: u64 },
+    Resize { session_id: String, cols: u16, rows: u16 },
+    Clear { session_id: String },
+    Close { session_id: String },
+    Kill { session_id: String },
+    GetScreen { session_id: String, include_history: bool },
+    ListCommands { session_id: String },
+    GetCommandOutput { session_id: String, command_id: u64 },
+    GetStatus { session_id: String },
+    GetForegroundProcess { session_id: String },
+    GetCwd { session_id: String },
+    StartRecording { session_id: String, path: PathBuf, record_input: bool },
+    StopRecording { session_id: String },
+    Signal { session_id: String, signal: SessionSignal, target: SignalTarget },
+    CloseInfo { session_id: String, ignore: Vec<String> },
+    DuplicateConfig { session_id: String, inherit_env: bool },
+    SetReaperPolicy { policy: ReaperPolicy },
+}
+
+/// Richiesta con il suo identificativo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestFrame {
+    pub id: u64,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+/// Messaggi inviati dal demone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Response {
+        id: u64,
+        #[serde(default)]
+        result: Value,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    /// Byte di output in base64 a partire da `offset`
+    Output {
+        session_id: String,
+        offset: u64,
+        data: String,
+    },
+    Event { event: SessionEvent },
+}
+
+/// Lettura dallo scrollback trasportabile in JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BytesRead {
+    /// Byte in base64
+    pub data: String,
+    pub start: u64,
+    pub end: u64,
+    pub dropped: u64,
+}
+
+impl From<ScrollbackRead> for BytesRead {
+    fn from(read: ScrollbackRead) -> Self {
+        Self {
+            data: BASE64.encode(&read.data),
+            start: read.start,
+            end: read.end,
+            dropped: read.dropped,
+        }
+    }
+}
+
+impl BytesRead {
+    pub fn into_read(self) -> Result<ScrollbackRead> {
+        Ok(ScrollbackRead {
+            data: BASE64.decode(&self.data)?,
+            start: self.start,
+            end: self.end,
+            dropped: self.dropped,
+        })
+    }
+}
+
+/// Risposta a `GetCommandOutput`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOutput {
+    pub command: CommandRecord,
+    pub output: BytesRead,
+}
+
+/// Scrive un messaggio seguito da un a capo
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Legge il prossimo messaggio; `None` a fine stream
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid muxd message: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_round_trip() {
+        let frame = RequestFrame {
+            id: 7,
+            request: Request::Resize {
+                session_id: "s".to_string(),
+                cols: 100,
+                rows: 30,
+            },
+        };
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &frame).unwrap();
+        assert!(String::from_utf8_lossy(&buffer).contains("\"method\":\"resize\""));
+
+        let decoded: RequestFrame = read_message(&mut buffer.as_slice()).unwrap().unwrap();
+        assert_eq!(decoded.id, 7);
+        assert!(matches!(decoded.request, Request::Resize { cols: 100, rows: 30, .. }));
+
+        let frame = RequestFrame { id: 8, request: Request::ListSessions };
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &frame).unwrap();
+        let decoded: RequestFrame = read_message(&mut buffer.as_slice()).unwrap().unwrap();
+        assert!(matches!(decoded.request, Request::ListSessions));
+    }
+}
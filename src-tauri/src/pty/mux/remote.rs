@@ -0,0 +1,187 @@
+//! Sessioni di `termina-muxd` viste dall'applicazione
+//!
+//! Ogni operazione diventa una richiesta al demone; l'output arriva in push
+//! sul broadcaster locale del client. Gli eventi di ciclo di vita sono
+//! inoltrati dal client a un unico listener, e la rimozione delle sessioni
+//! morte spetta al reaper del demone.
+
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use super::client::MuxClient;
+use super::protocol::{BytesRead, CommandOutput, Request};
+use crate::pty::backend::SessionBackend;
+use crate::pty::close_check::SessionCloseInfo;
+use crate::pty::events::SessionEventListener;
+use crate::pty::output::OutputSubscriber;
+use crate::pty::process::ProcessInfo;
+use crate::pty::screen::ScreenSnapshot;
+use crate::pty::scrollback::ScrollbackRead;
+use crate::pty::session::SessionStatus;
+use crate::pty::shell_integration::CommandRecord;
+use crate::pty::shutdown::ShutdownReport;
+use crate::pty::signals::{SessionSignal, SignalTarget};
+use crate::pty::PtyConfig;
+
+/// Sessione posseduta dal demone
+pub struct RemoteSession {
+    id: String,
+    client: Arc<MuxClient>,
+}
+
+impl RemoteSession {
+    pub fn new(id: String, client: Arc<MuxClient>) -> Self {
+        Self { id, client }
+    }
+
+    fn session_id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+impl SessionBackend for RemoteSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn write(&self, data: &[u8]) -> Result<()> {
+        self.client.call(Request::Write {
+            session_id: self.session_id(),
+            data: BASE64.encode(data),
+        })
+    }
+
+    fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        self.client.call(Request::Resize {
+            session_id: self.session_id(),
+            cols,
+            rows,
+        })
+    }
+
+    fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead> {
+        let read: BytesRead = self.client.call(Request::ReadBytes {
+            session_id: self.session_id(),
+            offset: from_offset,
+        })?;
+        read.into_read()
+    }
+
+    fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        let broadcaster = self.client.attach(&self.id, None)?;
+        Ok(broadcaster.subscribe_at(subscriber))
+    }
+
+    fn unsubscribe_output(&self, subscription_id: u64) -> bool {
+        self.client
+            .broadcaster(&self.id)
+            .is_some_and(|broadcaster| broadcaster.unsubscribe(subscription_id))
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.client.call(Request::Clear {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+        self.client.call(Request::GetScreen {
+            session_id: self.session_id(),
+            include_history,
+        })
+    }
+
+    fn status(&self) -> Result<SessionStatus> {
+        self.client.call(Request::GetStatus {
+            session_id: self.session_id(),
+        })
+    }
+
+    /// Gli eventi arrivano al listener del client, comune a tutte le sessioni
+    fn set_event_listener(&self, _listener: SessionEventListener) {}
+
+    fn close(&self) -> Result<ShutdownReport> {
+        self.client.forget(&self.id);
+        self.client.call(Request::Close {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn kill(&self) -> Result<()> {
+        self.client.forget(&self.id);
+        self.client.call(Request::Kill {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn cwd(&self) -> Result<String> {
+        self.client.call(Request::GetCwd {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn foreground_process(&self) -> Result<Option<ProcessInfo>> {
+        self.client.call(Request::GetForegroundProcess {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn list_commands(&self) -> Result<Vec<CommandRecord>> {
+        self.client.call(Request::ListCommands {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        let output: CommandOutput = self.client.call(Request::GetCommandOutput {
+            session_id: self.session_id(),
+            command_id,
+        })?;
+        Ok((output.command, output.output.into_read()?))
+    }
+
+    fn signal(&self, signal: SessionSignal, target: SignalTarget) -> Result<i32> {
+        self.client.call(Request::Signal {
+            session_id: self.session_id(),
+            signal,
+            target,
+        })
+    }
+
+    fn start_recording(&self, path: &Path, record_input: bool) -> Result<()> {
+        self.client.call(Request::StartRecording {
+            session_id: self.session_id(),
+            path: path.to_path_buf(),
+            record_input,
+        })
+    }
+
+    fn stop_recording(&self) -> Result<PathBuf> {
+        self.client.call(Request::StopRecording {
+            session_id: self.session_id(),
+        })
+    }
+
+    fn duplicate_config(&self, inherit_env: bool) -> Result<PtyConfig> {
+        self.client.call(Request::DuplicateConfig {
+            session_id: self.session_id(),
+            inherit_env,
+        })
+    }
+
+    fn close_info(&self, ignore: &[String]) -> Result<SessionCloseInfo> {
+        self.client.call(Request::CloseInfo {
+            session_id: self.session_id(),
+            ignore: ignore.to_vec(),
+        })
+    }
+}
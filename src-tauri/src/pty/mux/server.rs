@@ -0,0 +1,331 @@
+//! Lato demone di `termina-muxd`
+//!
+//! Il demone possiede un `PtyManager` locale e lo espone sul socket Unix.
+//! Più client possono collegarsi alla stessa sessione; quando l'ultimo client
+//! si disconnette le shell restano vive e possono essere ricollegate.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufReader;
+use std::net::Shutdown;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, error, info, warn};
+use serde_json::{json, Value};
+
+use super::protocol::{
+    read_message, write_message, BytesRead, CommandOutput, Request, RequestFrame, ServerMessage,
+};
+use crate::pty::events::SessionEvent;
+use crate::pty::output::OutputChunk;
+use crate::pty::pty_manager::PtyManager;
+use crate::pty::reaper;
+
+/// Tempo senza sessioni né client dopo cui il demone termina
+const IDLE_EXIT_AFTER: Duration = Duration::from_secs(30);
+/// Oltre questo tempo un client che non legge viene scollegato
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Client {
+    writer: Mutex<UnixStream>,
+    /// Sessioni collegate, con l'id della sottoscrizione al loro output
+    attached: Mutex<HashMap<String, u64>>,
+}
+
+type Clients = Arc<Mutex<HashMap<u64, Arc<Client>>>>;
+
+/// Invia un evento ai client collegati alla sessione, scollegando quelli
+/// che non rispondono
+fn send_to_attached(clients: &Clients, session_id: &str, message: &ServerMessage) {
+    let targets: Vec<(u64, Arc<Client>)> = clients
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, client)| client.attached.lock().unwrap().contains_key(session_id))
+        .map(|(id, client)| (*id, Arc::clone(client)))
+        .collect();
+
+    for (id, client) in targets {
+        let result = write_message(&mut *client.writer.lock().unwrap(), message);
+        if let Err(e) = result {
+            warn!("Dropping muxd client {}: {}", id, e);
+            clients.lock().unwrap().remove(&id);
+        }
+    }
+}
+
+/// Avvia il demone sul socket indicato; ritorna solo in caso di errore
+pub fn run(socket_path: &Path) -> Result<()> {
+    let listener = bind(socket_path)?;
+    info!("termina-muxd listening on {}", socket_path.display());
+
+    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
+    let manager = Arc::new(Mutex::new(PtyManager::new()));
+    // L'output arriva a ogni client con una sottoscrizione propria (vedi `attach`)
+    let event_clients = Arc::clone(&clients);
+    manager
+        .lock()
+        .unwrap()
+        .set_event_listener(Arc::new(move |event: &SessionEvent| {
+            let message = ServerMessage::Event { event: event.clone() };
+            send_to_attached(&event_clients, event.session_id(), &message);
+        }));
+
+    start_idle_watch(socket_path, Arc::clone(&clients), Arc::clone(&manager));
+    reaper::start(Arc::downgrade(&manager));
+
+    let next_client_id = AtomicU64::new(1);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("muxd accept failed: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = super::check_peer(&stream) {
+            warn!("{}", e);
+            continue;
+        }
+        let client_id = next_client_id.fetch_add(1, Ordering::Relaxed);
+        let writer = stream.try_clone()?;
+        writer.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+        let client = Arc::new(Client {
+            writer: Mutex::new(writer),
+            attached: Mutex::new(HashMap::new()),
+        });
+        clients.lock().unwrap().insert(client_id, Arc::clone(&client));
+
+        let clients = Arc::clone(&clients);
+        let manager = Arc::clone(&manager);
+        thread::spawn(move || {
+            info!("muxd client {} connected", client_id);
+            if let Err(e) = serve_client(stream, &client, &manager) {
+                debug!("muxd client {} error: {}", client_id, e);
+            }
+            clients.lock().unwrap().remove(&client_id);
+            detach_all(&client, &manager);
+            info!("muxd client {} disconnected", client_id);
+        });
+    }
+    Ok(())
+}
+
+/// Crea il socket, rimuovendo quello lasciato da un demone terminato.
+///
+/// Solo una directory dedicata `termina/` (come quella di
+/// `default_socket_path`) viene creata privata, e rifiutata se appartiene a
+/// un altro utente; le altre directory (es. `/tmp` passata con `--socket`)
+/// non si toccano.
+fn bind(socket_path: &Path) -> Result<UnixListener> {
+    if let Some(parent) = socket_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if parent.ends_with("termina") {
+            super::create_private_dirs(socket_path)?;
+        } else {
+            let mode = fs::metadata(parent)
+                .with_context(|| format!("muxd socket directory not found: {}", parent.display()))?
+                .permissions()
+                .mode();
+            if mode & 0o077 != 0 {
+                warn!(
+                    "muxd socket directory {} is accessible to other users (mode {:o})",
+                    parent.display(),
+                    mode & 0o777
+                );
+            }
+        }
+    }
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(anyhow!("termina-muxd already running on {}", socket_path.display()));
+        }
+        fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind muxd socket: {}", socket_path.display()))?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Termina il demone quando resta senza sessioni e senza client
+fn start_idle_watch(socket_path: &Path, clients: Clients, manager: Arc<Mutex<PtyManager>>) {
+    let socket_path = socket_path.to_path_buf();
+    thread::spawn(move || {
+        let mut idle_since = Instant::now();
+        loop {
+            thread::sleep(Duration::from_secs(5));
+            let busy = !clients.lock().unwrap().is_empty()
+                || !manager.lock().unwrap().list_sessions().is_empty();
+            if busy {
+                idle_since = Instant::now();
+            } else if idle_since.elapsed() >= IDLE_EXIT_AFTER {
+                info!("termina-muxd idle, shutting down");
+                let _ = fs::remove_file(&socket_path);
+                std::process::exit(0);
+            }
+        }
+    });
+}
+
+fn serve_client(stream: UnixStream, client: &Arc<Client>, manager: &Mutex<PtyManager>) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    while let Some(frame) = read_message::<RequestFrame>(&mut reader)? {
+        match frame.request {
+            // La chiusura attende il periodo di grazia: la sessione esce dal
+            // registro sotto il lock, poi si chiude e risponde da un altro
+            // thread senza bloccare gli altri client né le richieste successive
+            Request::Close { session_id } => {
+                let session = manager.lock().unwrap().take_session(&session_id);
+                let client = Arc::clone(client);
+                thread::spawn(move || {
+                    let result = session.and_then(|session| Ok(serde_json::to_value(session.close()?)?));
+                    if let Err(e) = respond(&client, frame.id, result) {
+                        debug!("Failed to answer close of {}: {}", session_id, e);
+                    }
+                });
+            }
+            request => respond(client, frame.id, handle_request(request, client, manager))?,
+        }
+    }
+    Ok(())
+}
+
+fn respond(client: &Client, id: u64, result: Result<Value>) -> Result<()> {
+    let (result, error) = match result {
+        Ok(result) => (result, None),
+        Err(e) => (Value::Null, Some(e.to_string())),
+    };
+    let response = ServerMessage::Response { id, result, error };
+    write_message(&mut *client.writer.lock().unwrap(), &response)
+}
+
+/// Collega il client all'output di una sessione; restituisce l'offset da cui
+/// partono i blocchi inviati in push, preso insieme alla sottoscrizione
+fn attach(client: &Arc<Client>, manager: &PtyManager, session_id: &str) -> Result<u64> {
+    let target = Arc::clone(client);
+    let (subscription_id, offset) = manager.subscribe_output(
+        session_id,
+        Arc::new(move |chunk: &OutputChunk| {
+            let message = ServerMessage::Output {
+                session_id: chunk.session_id.clone(),
+                offset: chunk.offset,
+                data: BASE64.encode(&chunk.bytes),
+            };
+            let mut writer = target.writer.lock().unwrap();
+            match write_message(&mut *writer, &message) {
+                Ok(()) => true,
+                Err(e) => {
+                    // Chiudere il socket termina anche il thread del client
+                    warn!("Dropping muxd client: {}", e);
+                    let _ = writer.shutdown(Shutdown::Both);
+                    false
+                }
+            }
+        }),
+    )?;
+    let previous = client
+        .attached
+        .lock()
+        .unwrap()
+        .insert(session_id.to_string(), subscription_id);
+    if let Some(previous) = previous {
+        let _ = manager.unsubscribe_output(session_id, previous);
+    }
+    Ok(offset)
+}
+
+/// Annulla le sottoscrizioni di un client disconnesso
+fn detach_all(client: &Client, manager: &Mutex<PtyManager>) {
+    let attached: Vec<(String, u64)> = client.attached.lock().unwrap().drain().collect();
+    let manager = manager.lock().unwrap();
+    for (session_id, subscription_id) in attached {
+        let _ = manager.unsubscribe_output(&session_id, subscription_id);
+    }
+}
+
+fn handle_request(request: Request, client: &Arc<Client>, manager: &Mutex<PtyManager>) -> Result<Value> {
+    let mut manager = manager.lock().unwrap();
+    let value = match request {
+        Request::CreateSession { session_id, config } => {
+            let session_id = manager.create_session(session_id, config)?;
+            attach(client, &manager, &session_id)?;
+            json!(session_id)
+        }
+        // L'output successivo a questo offset arriva in push
+        Request::Attach { session_id } => json!(attach(client, &manager, &session_id)?),
+        Request::Detach { session_id } => {
+            let subscription_id = client.attached.lock().unwrap().remove(&session_id);
+            if let Some(subscription_id) = subscription_id {
+                manager.unsubscribe_output(&session_id, subscription_id)?;
+            }
+            json!(subscription_id.is_some())
+        }
+        Request::ListSessions => json!(manager.list_sessions()),
+        Request::Write { session_id, data } => {
+            let data = BASE64.decode(data)?;
+            manager.write_bytes_to_session(&session_id, &data)?;
+            Value::Null
+        }
+        Request::ReadBytes { session_id, offset } => {
+            serde_json::to_value(BytesRead::from(manager.read_session_bytes(&session_id, offset)?))?
+        }
+        Request::Resize { session_id, cols, rows } => {
+            manager.resize_session(&session_id, cols, rows)?;
+            Value::Null
+        }
+        Request::Clear { session_id } => {
+            manager.clear_session(&session_id)?;
+            Value::Null
+        }
+        Request::Close { .. } => unreachable!("Close is answered by serve_client"),
+        Request::Kill { session_id } => {
+            manager.kill_session(&session_id)?;
+            Value::Null
+        }
+        Request::GetScreen { session_id, include_history } => {
+            serde_json::to_value(manager.get_screen(&session_id, include_history)?)?
+        }
+        Request::ListCommands { session_id } => serde_json::to_value(manager.list_commands(&session_id)?)?,
+        Request::GetCommandOutput { session_id, command_id } => {
+            let (command, read) = manager.get_command_output(&session_id, command_id)?;
+            serde_json::to_value(CommandOutput {
+                command,
+                output: read.into(),
+            })?
+        }
+        Request::GetStatus { session_id } => serde_json::to_value(manager.get_session_status(&session_id)?)?,
+        Request::GetForegroundProcess { session_id } => {
+            serde_json::to_value(manager.get_foreground_process(&session_id)?)?
+        }
+        Request::GetCwd { session_id } => json!(manager.get_session_cwd(&session_id)?),
+        Request::StartRecording { session_id, path, record_input } => {
+            manager.start_recording(&session_id, &path, record_input)?;
+            Value::Null
+        }
+        Request::StopRecording { session_id } => json!(manager.stop_recording(&session_id)?),
+        Request::SetReaperPolicy { policy } => {
+            manager.set_reaper_policy(policy)?;
+            Value::Null
+        }
+        Request::DuplicateConfig { session_id, inherit_env } => {
+            serde_json::to_value(manager.duplicate_config(&session_id, inherit_env)?)?
+        }
+        Request::CloseInfo { session_id, ignore } => {
+            let check = manager.close_check(Some(&session_id), &ignore)?;
+            serde_json::to_value(check.sessions.into_iter().next())?
+        }
+        Request::Signal { session_id, signal, target } => {
+            json!(manager.signal_session(&session_id, signal, target)?)
+        }
+    };
+    Ok(value)
+}
@@ -0,0 +1,332 @@
+//! Streaming push dell'output PTY
+//!
+//! Il thread di lettura di ogni sessione inoltra i byte letti a un thread
+//! dedicato che li raggruppa in blocchi numerati e li consegna ai
+//! sottoscrittori (eventi Tauri, `ipc::Channel`, ...), evitando che il
+//! frontend debba interrogare continuamente il backend.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use super::utf8::Utf8Decoder;
+
+/// Dimensione oltre la quale un blocco in arrivo indica un flusso sostenuto
+const BURST_THRESHOLD: usize = 4096;
+/// Finestra massima di raggruppamento durante un flusso sostenuto
+const BATCH_WINDOW: Duration = Duration::from_millis(8);
+/// Dimensione massima di un singolo blocco inviato ai sottoscrittori
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// Codifica del campo `data` di un blocco
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputEncoding {
+    /// Testo UTF-8; le sequenze spezzate vengono completate nel blocco successivo
+    #[default]
+    Utf8,
+    /// Byte grezzi in base64
+    Base64,
+}
+
+/// Blocco di output consegnato ai sottoscrittori
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub session_id: String,
+    /// Numero progressivo del blocco all'interno della sessione
+    pub seq: u64,
+    /// Offset assoluto del primo byte del blocco nello stream della sessione
+    pub offset: u64,
+    pub data: String,
+    #[serde(default)]
+    pub encoding: OutputEncoding,
+    /// Byte grezzi del blocco, a partire da `offset`
+    #[serde(skip)]
+    pub bytes: Vec<u8>,
+}
+
+impl OutputChunk {
+    /// Restituisce una copia del blocco con `data` nella codifica richiesta
+    pub fn with_encoding(&self, encoding: OutputEncoding) -> OutputChunk {
+        let mut chunk = self.clone();
+        if encoding == OutputEncoding::Base64 && self.encoding != encoding {
+            chunk.data = BASE64.encode(&self.bytes);
+            chunk.encoding = encoding;
+        }
+        chunk
+    }
+}
+
+/// Callback invocata per ogni blocco di output; restituisce `false` quando
+/// non può più riceverne (es. canale chiuso) e va rimossa
+pub type OutputSubscriber = Arc<dyn Fn(&OutputChunk) -> bool + Send + Sync>;
+
+/// Sottoscrittori e fine dell'ultimo blocco consegnato, sotto lo stesso lock
+#[derive(Default)]
+struct Subscribers {
+    by_id: HashMap<u64, OutputSubscriber>,
+    end_offset: u64,
+}
+
+/// Registro dei sottoscrittori all'output di una sessione
+pub struct OutputBroadcaster {
+    session_id: String,
+    subscribers: Mutex<Subscribers>,
+    decoder: Mutex<Utf8Decoder>,
+    next_subscriber_id: AtomicU64,
+    next_seq: AtomicU64,
+}
+
+impl OutputBroadcaster {
+    /// Crea un nuovo broadcaster per la sessione indicata
+    pub fn new(session_id: String) -> Self {
+        Self {
+            session_id,
+            subscribers: Mutex::new(Subscribers::default()),
+            decoder: Mutex::new(Utf8Decoder::new()),
+            next_subscriber_id: AtomicU64::new(1),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Registra un sottoscrittore e ne restituisce l'identificativo
+    pub fn subscribe(&self, subscriber: OutputSubscriber) -> u64 {
+        self.subscribe_at(subscriber).0
+    }
+
+    /// Registra un sottoscrittore e restituisce (identificativo, offset da
+    /// cui partono i blocchi che riceverà). Offset e registrazione sono presi
+    /// sotto il lock di `publish`: il sottoscrittore riceve tutti i byte
+    /// successivi all'offset, e solo quelli.
+    pub fn subscribe_at(&self, subscriber: OutputSubscriber) -> (u64, u64) {
+        let id = self.next_subscriber_id.fetch_add(1, Ordering::Relaxed);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.by_id.insert(id, subscriber);
+        debug!("Subscriber {} attached to PTY session {}", id, self.session_id);
+        (id, subscribers.end_offset)
+    }
+
+    /// Indica che i byte precedenti a `offset` non verranno pubblicati (es.
+    /// storico ripristinato, output del demone precedente al collegamento)
+    pub fn advance_to(&self, offset: u64) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.end_offset = subscribers.end_offset.max(offset);
+    }
+
+    /// Rimuove un sottoscrittore; restituisce `false` se non esisteva
+    pub fn unsubscribe(&self, subscriber_id: u64) -> bool {
+        self.subscribers.lock().unwrap().by_id.remove(&subscriber_id).is_some()
+    }
+
+    /// Numero di sottoscrittori registrati
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().by_id.len()
+    }
+
+    /// Consegna un blocco a tutti i sottoscrittori
+    pub fn publish(&self, offset: u64, data: &[u8]) {
+        let chunk = OutputChunk {
+            session_id: self.session_id.clone(),
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            offset,
+            data: self.decoder.lock().unwrap().decode(data),
+            encoding: OutputEncoding::Utf8,
+            bytes: data.to_vec(),
+        };
+
+        // Copia i sottoscrittori per non tenere il lock durante le callback;
+        // chi si registra dopo la copia parte dalla fine di questo blocco
+        let subscribers: Vec<(u64, OutputSubscriber)> = {
+            let mut subscribers = self.subscribers.lock().unwrap();
+            subscribers.end_offset = subscribers.end_offset.max(offset + data.len() as u64);
+            subscribers
+                .by_id
+                .iter()
+                .map(|(id, subscriber)| (*id, Arc::clone(subscriber)))
+                .collect()
+        };
+        let dropped: Vec<u64> = subscribers
+            .into_iter()
+            .filter(|(_, subscriber)| !subscriber(&chunk))
+            .map(|(id, _)| id)
+            .collect();
+        if !dropped.is_empty() {
+            let mut subscribers = self.subscribers.lock().unwrap();
+            for id in dropped {
+                debug!("Subscriber {} detached from PTY session {}", id, self.session_id);
+                subscribers.by_id.remove(&id);
+            }
+        }
+    }
+
+    /// Avvia il thread che raggruppa i byte letti e li pubblica.
+    ///
+    /// Restituisce il canale su cui il reader invia `(offset, dati)`; il thread
+    /// termina quando il mittente viene rilasciato.
+    pub fn start_batcher(self: &Arc<Self>) -> Sender<(u64, Vec<u8>)> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let broadcaster = Arc::clone(self);
+        thread::spawn(move || {
+            broadcaster.run_batcher(rx);
+            info!("Output batcher finished for PTY session: {}", broadcaster.session_id);
+        });
+        tx
+    }
+
+    fn run_batcher(&self, rx: Receiver<(u64, Vec<u8>)>) {
+        while let Ok((offset, mut pending)) = rx.recv() {
+            let mut disconnected = false;
+
+            // Raccoglie quanto è già disponibile senza attendere
+            while pending.len() < MAX_BATCH_BYTES {
+                match rx.try_recv() {
+                    Ok((_, more)) => pending.extend_from_slice(&more),
+                    Err(_) => break,
+                }
+            }
+
+            // Durante un flusso sostenuto attende brevemente altri dati, così
+            // `cat` di un file grande produce pochi blocchi grandi mentre
+            // l'eco della digitazione viene consegnato subito
+            if pending.len() >= BURST_THRESHOLD {
+                let deadline = Instant::now() + BATCH_WINDOW;
+                while pending.len() < MAX_BATCH_BYTES {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    match rx.recv_timeout(deadline - now) {
+                        Ok((_, more)) => pending.extend_from_slice(&more),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            disconnected = true;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            self.publish(offset, &pending);
+            if disconnected {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_assigns_sequence_numbers() {
+        let broadcaster = OutputBroadcaster::new("test".to_string());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        broadcaster.subscribe(Arc::new(move |chunk: &OutputChunk| {
+            sink.lock().unwrap().push((chunk.seq, chunk.offset, chunk.data.clone()));
+            true
+        }));
+
+        broadcaster.publish(0, b"hello");
+        broadcaster.publish(5, b" world");
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0], (0, 0, "hello".to_string()));
+        assert_eq!(received[1], (1, 5, " world".to_string()));
+    }
+
+    #[test]
+    fn test_split_characters_are_carried_over() {
+        let broadcaster = OutputBroadcaster::new("test".to_string());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        broadcaster.subscribe(Arc::new(move |chunk: &OutputChunk| {
+            sink.lock().unwrap().push(chunk.clone());
+            true
+        }));
+
+        let euro = "€".as_bytes();
+        broadcaster.publish(0, &euro[..1]);
+        broadcaster.publish(1, &euro[1..]);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0].data, "");
+        assert_eq!(received[1].data, "€");
+        assert_eq!(received[0].with_encoding(OutputEncoding::Base64).data, "4g==");
+    }
+
+    #[test]
+    fn test_subscribe_at_returns_next_offset() {
+        let broadcaster = OutputBroadcaster::new("test".to_string());
+        broadcaster.advance_to(100);
+        let (_, offset) = broadcaster.subscribe_at(Arc::new(|_: &OutputChunk| true));
+        assert_eq!(offset, 100);
+
+        broadcaster.publish(100, b"hello");
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let (_, offset) = broadcaster.subscribe_at(Arc::new(move |chunk: &OutputChunk| {
+            sink.lock().unwrap().push(chunk.offset);
+            true
+        }));
+        assert_eq!(offset, 105);
+        broadcaster.publish(105, b"!");
+        assert_eq!(*received.lock().unwrap(), vec![105]);
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let broadcaster = OutputBroadcaster::new("test".to_string());
+        let id = broadcaster.subscribe(Arc::new(|_: &OutputChunk| true));
+        assert_eq!(broadcaster.subscriber_count(), 1);
+        assert!(broadcaster.unsubscribe(id));
+        assert!(!broadcaster.unsubscribe(id));
+    }
+
+    #[test]
+    fn test_failing_subscriber_is_dropped() {
+        let broadcaster = OutputBroadcaster::new("test".to_string());
+        let (tx, rx) = std::sync::mpsc::channel();
+        broadcaster.subscribe(Arc::new(move |chunk: &OutputChunk| tx.send(chunk.offset).is_ok()));
+        broadcaster.subscribe(Arc::new(|_: &OutputChunk| true));
+
+        broadcaster.publish(0, b"a");
+        assert_eq!(rx.recv().unwrap(), 0);
+        drop(rx);
+        broadcaster.publish(1, b"b");
+        assert_eq!(broadcaster.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_batcher_coalesces_pending_data() {
+        let broadcaster = Arc::new(OutputBroadcaster::new("test".to_string()));
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        broadcaster.subscribe(Arc::new(move |chunk: &OutputChunk| {
+            done_tx.send(chunk.clone()).is_ok()
+        }));
+
+        let tx = broadcaster.start_batcher();
+        tx.send((0, vec![b'a'; BURST_THRESHOLD])).unwrap();
+        tx.send((BURST_THRESHOLD as u64, vec![b'b'; 10])).unwrap();
+        drop(tx);
+
+        let mut total = 0;
+        let mut first_offset = None;
+        while total < BURST_THRESHOLD + 10 {
+            let chunk = done_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+            first_offset.get_or_insert(chunk.offset);
+            total += chunk.data.len();
+        }
+        assert_eq!(first_offset, Some(0));
+        assert_eq!(total, BURST_THRESHOLD + 10);
+    }
+}
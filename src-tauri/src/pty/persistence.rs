@@ -0,0 +1,229 @@
+//! Salvataggio e ripristino delle sessioni
+//!
+//! Alla chiusura dell'applicazione ogni sessione viene salvata con la sua
+//! configurazione, l'ultima directory nota, il titolo e lo scrollback
+//! compresso. All'avvio le shell vengono riavviate nelle stesse directory e lo
+//! scrollback salvato viene riproposto prima dell'output nuovo, seguito da un
+//! marcatore che indica il ripristino.
+
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{Local, TimeZone};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use super::{PtyConfig, RealPtySession};
+
+/// Versione del formato del file delle sessioni
+const FORMAT_VERSION: u32 = 1;
+
+/// Sessione salvata su disco
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub id: String,
+    pub config: PtyConfig,
+    pub cwd: String,
+    pub title: String,
+    /// Secondi dall'epoch
+    pub saved_at: u64,
+    /// Scrollback compresso con gzip e codificato in base64
+    pub scrollback: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedSessions {
+    version: u32,
+    sessions: Vec<SavedSession>,
+}
+
+impl SavedSession {
+    /// Cattura lo stato corrente di una sessione
+    pub fn capture(session: &RealPtySession, saved_at: u64) -> Result<Self> {
+        let title = {
+            let screen = session.screen.lock().unwrap();
+            screen.title().to_string()
+        };
+        let title = if title.is_empty() {
+            session
+                .foreground_process()
+                .map(|process| process.title())
+                .unwrap_or_default()
+        } else {
+            title
+        };
+
+        let contents = session.buffer.lock().unwrap().contents();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&contents)?;
+        let compressed = encoder.finish()?;
+
+        Ok(Self {
+            id: session.id.clone(),
+            config: session.config.clone(),
+            cwd: session.get_cwd(),
+            title,
+            saved_at,
+            scrollback: BASE64.encode(compressed),
+        })
+    }
+
+    /// Scrollback salvato seguito dal marcatore di ripristino
+    pub fn history(&self) -> Result<Vec<u8>> {
+        let compressed = BASE64
+            .decode(&self.scrollback)
+            .context("Invalid scrollback encoding")?;
+        let mut history = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut history)
+            .context("Invalid scrollback compression")?;
+        history.extend_from_slice(restored_marker(self.saved_at).as_bytes());
+        Ok(history)
+    }
+}
+
+/// Marcatore scritto tra lo scrollback salvato e la nuova shell. Esce da un
+/// eventuale schermo alternativo rimasto attivo e azzera gli attributi.
+pub fn restored_marker(saved_at: u64) -> String {
+    let saved = Local
+        .timestamp_opt(saved_at as i64, 0)
+        .single()
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    format!(
+        "\x1b[?1049l\x1b[0m\r\n\x1b[2m[restored \u{2014} session saved {}]\x1b[0m\r\n",
+        saved
+    )
+}
+
+/// Percorso predefinito del file delle sessioni
+pub fn default_sessions_path() -> PathBuf {
+    let base = dirs::config_dir()
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    base.join("TermInA").join("sessions.json")
+}
+
+/// Scrive le sessioni su disco; senza sessioni il file viene rimosso
+pub fn save(path: &Path, sessions: Vec<SavedSession>) -> Result<()> {
+    if sessions.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove sessions file: {}", path.display()))?;
+        }
+        return Ok(());
+    }
+
+    // Lo scrollback può contenere dati riservati: file e directory sono
+    // leggibili solo dal proprietario
+    if let Some(parent) = path.parent() {
+        let mut builder = DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(parent).with_context(|| format!(
+            "Failed to create sessions directory: {}",
+            parent.display()
+        ))?;
+    }
+    let data = SavedSessions {
+        version: FORMAT_VERSION,
+        sessions,
+    };
+    let contents = serde_json::to_vec(&data)?;
+    let temp = path.with_extension("json.tmp");
+    // Un file temporaneo rimasto da un salvataggio interrotto manterrebbe i
+    // suoi permessi
+    if let Err(e) = fs::remove_file(&temp) {
+        if e.kind() != ErrorKind::NotFound {
+            return Err(e).with_context(|| format!("Failed to remove sessions file: {}", temp.display()));
+        }
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(&contents))
+        .with_context(|| format!("Failed to write sessions file: {}", temp.display()))?;
+    fs::rename(&temp, path)
+        .with_context(|| format!("Failed to write sessions file: {}", path.display()))?;
+    Ok(())
+}
+
+/// Legge e rimuove il file delle sessioni, così un ripristino non viene
+/// ripetuto a ogni avvio
+pub fn take(path: &Path) -> Result<Vec<SavedSession>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read(path)
+        .with_context(|| format!("Failed to read sessions file: {}", path.display()))?;
+    fs::remove_file(path)
+        .with_context(|| format!("Failed to remove sessions file: {}", path.display()))?;
+
+    let data: SavedSessions = serde_json::from_slice(&contents)
+        .with_context(|| format!("Failed to parse sessions file: {}", path.display()))?;
+    if data.version != FORMAT_VERSION {
+        return Err(anyhow::anyhow!("Unsupported sessions file version: {}", data.version));
+    }
+    Ok(data.sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_session(scrollback: &[u8]) -> SavedSession {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(scrollback).unwrap();
+        SavedSession {
+            id: "saved".to_string(),
+            config: PtyConfig::default(),
+            cwd: "/tmp".to_string(),
+            title: "vim".to_string(),
+            saved_at: 0,
+            scrollback: BASE64.encode(encoder.finish().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_history_ends_with_marker() {
+        let history = saved_session(b"$ ls\r\nfile\r\n").history().unwrap();
+        let history = String::from_utf8(history).unwrap();
+        assert!(history.starts_with("$ ls\r\nfile\r\n"));
+        assert!(history.contains("[restored"));
+    }
+
+    #[test]
+    fn test_save_and_take() {
+        let path = std::env::temp_dir()
+            .join(format!("termina-sessions-{}", std::process::id()))
+            .join("sessions.json");
+
+        save(&path, vec![saved_session(b"hello")]).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&path), 0o600);
+            assert_eq!(mode(path.parent().unwrap()), 0o700);
+        }
+        let sessions = take(&path).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].title, "vim");
+        // Il file viene consumato dal ripristino
+        assert!(!path.exists());
+        assert!(take(&path).unwrap().is_empty());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+//! Riproduzione di registrazioni asciicast in sessioni di sola lettura
+//!
+//! Una sessione di riproduzione non ha un processo: un thread scrive l'output
+//! registrato nello scrollback e nello schermo rispettando i tempi originali
+//! (scalati dalla velocità), e lo pubblica ai sottoscrittori come farebbe
+//! una sessione PTY. La velocità si può cambiare durante la riproduzione.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::backend::SessionBackend;
+use super::events::{SessionEvent, SessionEventListener};
+use super::output::{OutputBroadcaster, OutputSubscriber};
+use super::recording::{unix_timestamp, Recording};
+use super::scrollback::{ScrollbackBuffer, ScrollbackRead, DEFAULT_SCROLLBACK_LINES};
+use super::screen::{ScreenSnapshot, TerminalScreen};
+use super::session::SessionStatus;
+use super::shutdown::ShutdownReport;
+
+/// Intervallo massimo tra due controlli di velocità e interruzione
+const TICK: Duration = Duration::from_millis(50);
+
+/// Opzioni di riproduzione
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybackOptions {
+    /// Moltiplicatore della velocità originale
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// Pausa massima tra due eventi, in secondi; prevale su quella del file
+    #[serde(default)]
+    pub idle_time_limit: Option<f64>,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: default_speed(),
+            idle_time_limit: None,
+        }
+    }
+}
+
+/// Sessione di sola lettura alimentata da una registrazione
+pub struct PlaybackSession {
+    pub id: String,
+    pub buffer: Arc<Mutex<ScrollbackBuffer>>,
+    pub screen: Arc<Mutex<TerminalScreen>>,
+    pub output: Arc<OutputBroadcaster>,
+    speed: Arc<Mutex<f64>>,
+    stopped: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+    last_activity: Arc<Mutex<u64>>,
+    listener: Arc<Mutex<Option<SessionEventListener>>>,
+}
+
+impl PlaybackSession {
+    /// Avvia la riproduzione di una registrazione
+    pub fn start(id: String, recording: Recording, options: PlaybackOptions) -> Result<Self> {
+        validate_speed(options.speed)?;
+        let header = &recording.header;
+        let session = Self {
+            id: id.clone(),
+            buffer: Arc::new(Mutex::new(ScrollbackBuffer::new(DEFAULT_SCROLLBACK_LINES))),
+            screen: Arc::new(Mutex::new(TerminalScreen::new(
+                header.width,
+                header.height,
+                DEFAULT_SCROLLBACK_LINES,
+            ))),
+            output: Arc::new(OutputBroadcaster::new(id)),
+            speed: Arc::new(Mutex::new(options.speed)),
+            stopped: Arc::new(AtomicBool::new(false)),
+            finished: Arc::new(AtomicBool::new(false)),
+            last_activity: Arc::new(Mutex::new(unix_timestamp())),
+            listener: Arc::new(Mutex::new(None)),
+        };
+        let idle_time_limit = options.idle_time_limit.or(header.idle_time_limit);
+        session.start_player(recording, idle_time_limit);
+        Ok(session)
+    }
+
+    fn start_player(&self, recording: Recording, idle_time_limit: Option<f64>) {
+        let session_id = self.id.clone();
+        let buffer = Arc::clone(&self.buffer);
+        let screen = Arc::clone(&self.screen);
+        let speed = Arc::clone(&self.speed);
+        let stopped = Arc::clone(&self.stopped);
+        let finished = Arc::clone(&self.finished);
+        let last_activity = Arc::clone(&self.last_activity);
+        let listener = Arc::clone(&self.listener);
+        let batcher = self.output.start_batcher();
+
+        thread::spawn(move || {
+            info!("Starting playback in session {}", session_id);
+            let mut previous = 0.0;
+            for event in recording.events {
+                let mut pause = (event.time - previous).max(0.0);
+                if let Some(limit) = idle_time_limit {
+                    pause = pause.min(limit);
+                }
+                previous = event.time;
+                if !wait(pause, &speed, &stopped) {
+                    break;
+                }
+
+                if let Some((cols, rows)) = event.size() {
+                    screen.lock().unwrap().resize(cols, rows);
+                } else if event.code == "o" {
+                    let data = event.data.as_bytes();
+                    let offset = buffer.lock().unwrap().append(data);
+                    screen.lock().unwrap().process(data);
+                    *last_activity.lock().unwrap() = unix_timestamp();
+                    let _ = batcher.send((offset, data.to_vec()));
+                }
+            }
+
+            info!("Playback finished in session {}", session_id);
+            // Sotto il lock del listener l'evento viene emesso una sola volta
+            let listener = listener.lock().unwrap();
+            finished.store(true, Ordering::SeqCst);
+            if let Some(listener) = listener.as_ref() {
+                listener(&SessionEvent::PlaybackFinished { session_id });
+            }
+        });
+    }
+
+    /// Cambia la velocità; vale anche per la pausa in corso
+    pub fn set_speed(&self, speed: f64) -> Result<()> {
+        validate_speed(speed)?;
+        *self.speed.lock().unwrap() = speed;
+        Ok(())
+    }
+
+    /// Interrompe la riproduzione
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Registra il listener; se la riproduzione è già finita l'evento viene
+    /// emesso subito
+    pub fn set_event_listener(&self, listener: SessionEventListener) {
+        let mut slot = self.listener.lock().unwrap();
+        if self.is_finished() {
+            listener(&SessionEvent::PlaybackFinished {
+                session_id: self.id.clone(),
+            });
+        }
+        *slot = Some(listener);
+    }
+
+    pub fn read_output(&self, from_offset: u64) -> ScrollbackRead {
+        self.buffer.lock().unwrap().read_from(from_offset)
+    }
+
+    pub fn get_screen(&self, include_history: bool) -> ScreenSnapshot {
+        self.screen.lock().unwrap().snapshot(include_history)
+    }
+
+    /// Registra un sottoscrittore; restituisce (id, offset del primo byte che riceverà)
+    pub fn subscribe_output(&self, subscriber: OutputSubscriber) -> (u64, u64) {
+        self.output.subscribe_at(subscriber)
+    }
+
+    pub fn unsubscribe_output(&self, subscriber_id: u64) -> bool {
+        self.output.unsubscribe(subscriber_id)
+    }
+
+    pub fn get_last_activity(&self) -> u64 {
+        *self.last_activity.lock().unwrap()
+    }
+
+    pub fn get_status(&self) -> SessionStatus {
+        SessionStatus {
+            id: self.id.clone(),
+            is_active: !self.is_finished(),
+            is_executing: false,
+            current_command: String::new(),
+            last_activity: self.get_last_activity(),
+            buffer_size: self.buffer.lock().unwrap().len(),
+            cwd: String::new(),
+            pid: None,
+            exit_status: None,
+            foreground_process: None,
+            restored_at: None,
+            recording: None,
+            read_only: true,
+        }
+    }
+}
+
+impl SessionBackend for PlaybackSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn write(&self, _data: &[u8]) -> Result<()> {
+        Err(anyhow!("Session {} is read-only", self.id))
+    }
+
+    fn resize(&self, _cols: u16, _rows: u16) -> Result<()> {
+        Err(anyhow!("Session {} is read-only", self.id))
+    }
+
+    fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead> {
+        Ok(PlaybackSession::read_output(self, from_offset))
+    }
+
+    fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        Ok(PlaybackSession::subscribe_output(self, subscriber))
+    }
+
+    fn unsubscribe_output(&self, subscription_id: u64) -> bool {
+        PlaybackSession::unsubscribe_output(self, subscription_id)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.buffer.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+        Ok(PlaybackSession::get_screen(self, include_history))
+    }
+
+    fn status(&self) -> Result<SessionStatus> {
+        Ok(self.get_status())
+    }
+
+    fn set_event_listener(&self, listener: SessionEventListener) {
+        PlaybackSession::set_event_listener(self, listener)
+    }
+
+    fn close(&self) -> Result<ShutdownReport> {
+        self.stop();
+        Ok(ShutdownReport {
+            session_id: self.id.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn kill(&self) -> Result<()> {
+        self.stop();
+        Ok(())
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl Drop for PlaybackSession {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn validate_speed(speed: f64) -> Result<()> {
+    if speed.is_finite() && speed > 0.0 {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid playback speed: {}", speed))
+    }
+}
+
+/// Attende `pause` secondi di registrazione alla velocità corrente;
+/// restituisce `false` se la riproduzione è stata interrotta
+fn wait(pause: f64, speed: &Mutex<f64>, stopped: &AtomicBool) -> bool {
+    let mut remaining = pause;
+    while remaining > 0.0 {
+        if stopped.load(Ordering::SeqCst) {
+            return false;
+        }
+        let speed = *speed.lock().unwrap();
+        let step = TICK.min(Duration::from_secs_f64(remaining / speed));
+        let started = Instant::now();
+        thread::sleep(step);
+        remaining -= started.elapsed().as_secs_f64() * speed;
+    }
+    !stopped.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pty::recording::{CastEvent, CastHeader};
+
+    fn event(time: f64, code: &str, data: &str) -> CastEvent {
+        CastEvent {
+            time,
+            code: code.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_playback_reaches_screen() {
+        let recording = Recording {
+            header: CastHeader {
+                version: 2,
+                width: 40,
+                height: 10,
+                timestamp: None,
+                idle_time_limit: None,
+                title: None,
+                env: None,
+            },
+            events: vec![
+                event(0.01, "o", "$ echo hi\r\n"),
+                event(0.02, "r", "60x12"),
+                // La pausa lunga viene ridotta dal limite
+                event(30.0, "o", "hi\r\n"),
+            ],
+        };
+        let options = PlaybackOptions {
+            speed: 2.0,
+            idle_time_limit: Some(0.1),
+        };
+        let playback = PlaybackSession::start("p".to_string(), recording, options).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !playback.is_finished() {
+            assert!(Instant::now() < deadline, "playback did not finish");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(playback.get_screen(false).plain_text().contains("hi"));
+        assert_eq!(playback.screen.lock().unwrap().size(), (60, 12));
+        assert!(playback.set_speed(0.0).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+//! Informazioni sui processi in esecuzione nel PTY
+//!
+//! Su Linux i dati vengono letti da `/proc`; sugli altri sistemi le funzioni
+//! restituiscono `None` e il chiamante usa i valori noti dalla configurazione.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Processo in primo piano su un terminale
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: i32,
+    /// Nome del programma (es. `vim`)
+    pub name: String,
+    /// Argomenti completi, programma incluso
+    pub argv: Vec<String>,
+    /// Il processo è la shell della sessione
+    pub is_shell: bool,
+}
+
+impl ProcessInfo {
+    /// Titolo adatto a una scheda, es. `cargo build`
+    pub fn title(&self) -> String {
+        if self.argv.is_empty() {
+            return self.name.clone();
+        }
+        let mut argv = self.argv.clone();
+        argv[0] = self.name.clone();
+        argv.join(" ")
+    }
+}
+
+/// Directory corrente del processo indicato
+pub fn process_cwd(pid: i32) -> Option<String> {
+    if pid <= 0 {
+        return None;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        std::fs::read_link(format!("/proc/{}/cwd", pid))
+            .ok()
+            .map(|path| path.display().to_string())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Ambiente del processo indicato. È quello ricevuto all'avvio: le
+/// variabili esportate in seguito dalla shell non compaiono.
+pub fn process_environ(pid: i32) -> Option<HashMap<String, String>> {
+    if pid <= 0 {
+        return None;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let environ = std::fs::read(format!("/proc/{}/environ", pid)).ok()?;
+        Some(
+            environ
+                .split(|&byte| byte == 0)
+                .filter_map(|entry| {
+                    let entry = String::from_utf8_lossy(entry);
+                    let (key, value) = entry.split_once('=')?;
+                    (!key.is_empty()).then(|| (key.to_string(), value.to_string()))
+                })
+                .collect(),
+        )
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Nome e argomenti del processo indicato
+pub fn process_info(pid: i32, shell_pid: Option<i32>) -> Option<ProcessInfo> {
+    if pid <= 0 {
+        return None;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let argv: Vec<String> = std::fs::read(format!("/proc/{}/cmdline", pid))
+            .ok()?
+            .split(|&byte| byte == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        // `comm` è troncato a 15 caratteri: si preferisce il nome da argv
+        let name = argv
+            .first()
+            .and_then(|program| std::path::Path::new(program.trim_start_matches('-')).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .or_else(|| {
+                std::fs::read_to_string(format!("/proc/{}/comm", pid))
+                    .ok()
+                    .map(|comm| comm.trim_end().to_string())
+            })?;
+        Some(ProcessInfo {
+            pid,
+            name,
+            argv,
+            is_shell: shell_pid == Some(pid),
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = shell_pid;
+        None
+    }
+}
+
+/// Voce di `/proc/<pid>/stat` con i legami di parentela del processo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessStat {
+    pub pid: i32,
+    pub ppid: i32,
+    pub pgid: i32,
+    /// Sessione del processo (il pid del suo leader)
+    pub sid: i32,
+    /// Processo terminato ma non ancora raccolto dal padre
+    pub zombie: bool,
+    /// Avvio del processo in tick di clock dall'avvio del sistema
+    pub start_time: u64,
+}
+
+/// Legge lo stato di un processo
+pub fn process_stat(pid: i32) -> Option<ProcessStat> {
+    if pid <= 0 {
+        return None;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        parse_stat(&stat)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Interpreta `/proc/<pid>/stat`; il nome tra parentesi può contenere spazi
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str) -> Option<ProcessStat> {
+    let pid = stat.split_whitespace().next()?.parse().ok()?;
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    let state = fields.next()?;
+    let fields: Vec<&str> = fields.collect();
+    let field = |index: usize| fields.get(index).and_then(|field| field.parse().ok());
+    Some(ProcessStat {
+        pid,
+        ppid: field(0)?,
+        pgid: field(1)?,
+        sid: field(2)?,
+        zombie: state == "Z" || state == "X",
+        // Campo 22 di `stat`: i primi tre sono pid, nome e stato
+        start_time: fields.get(18).and_then(|field| field.parse().ok()).unwrap_or(0),
+    })
+}
+
+/// Secondi trascorsi dall'avvio del processo
+pub fn process_runtime(stat: &ProcessStat) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let uptime: f64 = std::fs::read_to_string("/proc/uptime")
+            .ok()?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()?;
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks <= 0 {
+            return None;
+        }
+        let started = stat.start_time as f64 / ticks as f64;
+        Some((uptime - started).max(0.0) as u64)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = stat;
+        None
+    }
+}
+
+/// Processo ancora in esecuzione (gli zombie non contano)
+pub fn is_alive(pid: i32) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        process_stat(pid).is_some_and(|stat| !stat.zombie)
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        pid > 0 && unsafe { libc::kill(pid, 0) } == 0
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        false
+    }
+}
+
+/// Processi vivi appartenenti alla sessione di una shell: i suoi discendenti
+/// e quelli rimasti nella sua sessione del terminale dopo essere stati
+/// adottati da init (job in background, processi figli dei comandi).
+///
+/// La shell è leader della propria sessione, quindi ne condivide il pid.
+pub fn session_processes(shell_pid: i32) -> Vec<ProcessStat> {
+    #[cfg(target_os = "linux")]
+    {
+        collect_session(&all_processes(), shell_pid, true)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        if is_alive(shell_pid) {
+            vec![ProcessStat {
+                pid: shell_pid,
+                ppid: 0,
+                pgid: shell_pid,
+                sid: shell_pid,
+                zombie: false,
+                start_time: 0,
+            }]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Processi rimasti nella sessione di una shell già uscita (job in
+/// background, server avviati dai comandi) e i loro discendenti.
+///
+/// Il pid della shell può essere stato riassegnato, quindi non lo si cerca;
+/// l'id di sessione invece resta valido finché un membro è vivo, perché il
+/// kernel non riusa un pid ancora in uso come id di sessione o di gruppo.
+pub fn leftover_session_processes(shell_pid: i32) -> Vec<ProcessStat> {
+    #[cfg(target_os = "linux")]
+    {
+        let all = all_processes();
+        // Un processo vivo con il pid della shell è un altro programma: la
+        // sessione si è svuotata e l'id potrebbe essere già di qualcun altro
+        if all.iter().any(|stat| stat.pid == shell_pid) {
+            return Vec::new();
+        }
+        collect_session(&all, shell_pid, false)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = shell_pid;
+        Vec::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn all_processes() -> Vec<ProcessStat> {
+    std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .filter_map(process_stat)
+                .filter(|stat| !stat.zombie)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Membri della sessione `shell_pid`, con la shell stessa se `include_shell`,
+/// più i loro discendenti
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn collect_session(all: &[ProcessStat], shell_pid: i32, include_shell: bool) -> Vec<ProcessStat> {
+    let mut members: Vec<ProcessStat> = all
+        .iter()
+        .filter(|stat| (include_shell && stat.pid == shell_pid) || stat.sid == shell_pid)
+        .copied()
+        .collect();
+    // Discendenti che hanno cambiato sessione (es. con `setsid`)
+    let mut index = 0;
+    while index < members.len() {
+        let parent = members[index].pid;
+        for stat in all {
+            if stat.ppid == parent && !members.iter().any(|member| member.pid == stat.pid) {
+                members.push(*stat);
+            }
+        }
+        index += 1;
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_process_cwd_of_current_process() {
+        let expected = std::env::current_dir().unwrap().display().to_string();
+        assert_eq!(process_cwd(std::process::id() as i32), Some(expected));
+        assert_eq!(process_cwd(0), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_process_info_of_current_process() {
+        let pid = std::process::id() as i32;
+        let info = process_info(pid, Some(pid)).unwrap();
+        assert_eq!(info.pid, pid);
+        assert!(info.is_shell);
+        assert!(!info.argv.is_empty());
+        assert!(info.title().starts_with(&info.name));
+
+        let stat = process_stat(pid).unwrap();
+        assert!(process_runtime(&stat).is_some());
+
+        let environ = process_environ(pid).unwrap();
+        assert_eq!(environ.get("PATH"), std::env::var("PATH").ok().as_ref());
+    }
+
+    #[test]
+    fn test_session_members() {
+        let stat = parse_stat(
+            "42 (my (odd) prog) S 1 42 40 34816 42 4194560 100 0 0 0 5 2 0 0 20 0 1 0 98765 1000",
+        )
+        .unwrap();
+        assert_eq!((stat.pid, stat.ppid, stat.pgid, stat.sid), (42, 1, 42, 40));
+        assert_eq!(stat.start_time, 98765);
+        assert!(!stat.zombie);
+
+        let stat = |pid, ppid, sid| ProcessStat {
+            pid,
+            ppid,
+            pgid: pid,
+            sid,
+            zombie: false,
+            start_time: 0,
+        };
+        let all = [
+            stat(10, 1, 10),
+            // Job in background adottato da init
+            stat(11, 1, 10),
+            // Figlio della shell in una nuova sessione e il suo discendente
+            stat(12, 10, 12),
+            stat(13, 12, 12),
+            stat(20, 1, 20),
+        ];
+        let mut pids: Vec<i32> = collect_session(&all, 10, true).iter().map(|stat| stat.pid).collect();
+        pids.sort();
+        assert_eq!(pids, vec![10, 11, 12, 13]);
+
+        // Shell uscita: restano solo i membri della sessione
+        let leftovers: Vec<i32> = collect_session(&all[1..], 10, false).iter().map(|stat| stat.pid).collect();
+        assert_eq!(leftovers, vec![11]);
+    }
+}
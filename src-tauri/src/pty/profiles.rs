@@ -0,0 +1,123 @@
+//! Profili di shell
+//!
+//! I profili sono definiti nella configurazione in `terminal.profiles`, per
+//! nome. Ogni campo è facoltativo: quelli assenti restano ai valori
+//! predefiniti della sessione.
+//!
+//! ```json
+//! "profiles": {
+//!   "zsh login": { "shell": "/bin/zsh", "login": true },
+//!   "python": { "shell": "python3", "args": ["-q"], "shell_integration": false },
+//!   "nix": { "shell": "bash", "cwd": "~/src/app", "startup_command": "nix develop" }
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::PtyConfig;
+
+/// Profilo di shell con nome
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShellProfile {
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Directory iniziale; `~` indica la home
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Avvia la shell come shell di login
+    #[serde(default)]
+    pub login: bool,
+    /// Comando inviato alla shell appena avviata
+    #[serde(default)]
+    pub startup_command: Option<String>,
+    #[serde(default)]
+    pub shell_integration: Option<bool>,
+}
+
+impl ShellProfile {
+    /// Cerca un profilo nella sezione `profiles` della configurazione
+    pub fn from_config(profiles: Option<&Value>, name: &str) -> Result<Self> {
+        let profile = profiles
+            .and_then(|profiles| profiles.get(name))
+            .ok_or_else(|| anyhow!("Shell profile not found: {}", name))?;
+        serde_json::from_value(profile.clone())
+            .map_err(|e| anyhow!("Invalid shell profile {}: {}", name, e))
+    }
+
+    /// Applica il profilo alla configurazione della sessione
+    pub fn apply(&self, config: &mut PtyConfig) {
+        if let Some(shell) = &self.shell {
+            config.shell = shell.clone();
+        }
+        if !self.args.is_empty() {
+            config.args = self.args.clone();
+        }
+        config
+            .env_vars
+            .extend(self.env.iter().map(|(key, value)| (key.clone(), value.clone())));
+        if let Some(cwd) = &self.cwd {
+            config.cwd = expand_home(cwd);
+        }
+        config.login = self.login;
+        if let Some(command) = &self.startup_command {
+            config.startup_command = Some(command.clone());
+        }
+        if let Some(enabled) = self.shell_integration {
+            config.shell_integration = enabled;
+        }
+    }
+}
+
+fn expand_home(path: &str) -> String {
+    let home = || dirs::home_dir().map(|home| home.to_string_lossy().to_string());
+    if path == "~" {
+        return home().unwrap_or_else(|| path.to_string());
+    }
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_profile_overrides_config() {
+        let profiles = json!({
+            "bash clean": {
+                "shell": "/bin/bash",
+                "args": ["--norc"],
+                "env": { "PS1": "$ " },
+                "cwd": "/srv",
+                "startup_command": "ls"
+            }
+        });
+        let profile = ShellProfile::from_config(Some(&profiles), "bash clean").unwrap();
+        let mut config = PtyConfig::default();
+        profile.apply(&mut config);
+
+        assert_eq!(config.shell, "/bin/bash");
+        assert_eq!(config.args, vec!["--norc".to_string()]);
+        assert_eq!(config.env_vars.get("PS1").map(String::as_str), Some("$ "));
+        // Le variabili predefinite restano
+        assert!(config.env_vars.contains_key("TERM"));
+        assert_eq!(config.cwd, "/srv");
+        assert_eq!(config.startup_command.as_deref(), Some("ls"));
+        assert!(!config.login);
+
+        assert!(ShellProfile::from_config(Some(&profiles), "missing").is_err());
+        assert!(ShellProfile::from_config(None, "bash clean").is_err());
+    }
+}
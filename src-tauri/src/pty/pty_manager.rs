@@ -3,23 +3,280 @@
 //! Questo modulo gestisce la creazione e la gestione dei pseudo-terminali
 //! per l'esecuzione di comandi interattivi reali.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
-use log::{debug, info};
+use log::{debug, info, warn};
 
+use super::backend::SessionBackend;
+use super::broadcast::{BroadcastGroups, BroadcastResult};
+use super::close_check::{CloseCheck, SessionCloseInfo};
+use super::events::SessionEventListener;
+use super::export::{self, ExportFormat};
+#[cfg(unix)]
+use super::mux::client::MuxClient;
+#[cfg(unix)]
+use super::mux::protocol::Request;
+#[cfg(unix)]
+use super::mux::remote::RemoteSession;
+use super::output::OutputSubscriber;
+use super::persistence::{self, SavedSession};
+use super::playback::{PlaybackOptions, PlaybackSession};
+use super::process::ProcessInfo;
+use super::reaper::{ReapReason, ReaperPolicy, SessionActivity};
+use super::recording::Recording;
+use super::screen::ScreenSnapshot;
+use super::scrollback::ScrollbackRead;
+use super::search::{self, SearchOptions, SearchResults};
+use super::session::SessionStatus;
+use super::shell_integration::CommandRecord;
+use super::shutdown::ShutdownReport;
+use super::signals::{SessionSignal, SignalTarget};
+use super::utf8;
 use super::{PtyConfig, RealPtySession};
 
-struct SessionEntry {
-    session: Arc<RealPtySession>,
-    last_sent_index: usize,
+/// Sessione del registro.
+///
+/// Con `termina-muxd` ogni chiamata al backend è una richiesta al demone:
+/// chi condivide il manager dietro un lock prende la sessione con
+/// `PtyManager::session` e la usa dopo averlo rilasciato.
+pub struct ManagedSession {
+    backend: Arc<dyn SessionBackend>,
+    last_sent_offset: AtomicU64,
+    /// Un client ha mostrato la sessione (sottoscrizione, lettura
+    /// incrementale, input o resize); le sessioni ripristinate partono
+    /// scollegate e non vengono salvate di nuovo finché nessuno le apre
+    attached: AtomicBool,
 }
 
-/// Manager per la gestione dei PTY reali
+impl ManagedSession {
+    fn new(backend: Arc<dyn SessionBackend>) -> Self {
+        Self {
+            backend,
+            last_sent_offset: AtomicU64::new(0),
+            attached: AtomicBool::new(true),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        self.backend.id()
+    }
+
+    /// Segna la sessione come mostrata da un client
+    fn mark_attached(&self) {
+        self.attached.store(true, Ordering::SeqCst);
+    }
+
+    /// Scrive byte arbitrari nella sessione
+    pub fn write(&self, data: &[u8]) -> Result<()> {
+        self.backend.write(data)?;
+        self.mark_attached();
+        Ok(())
+    }
+
+    /// Sottoscrive l'output in streaming della sessione.
+    ///
+    /// Restituisce l'id della sottoscrizione e l'offset da cui partiranno i
+    /// prossimi blocchi, così il chiamante può recuperare lo storico precedente.
+    pub fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        let subscription = self.backend.subscribe_output(subscriber)?;
+        self.mark_attached();
+        Ok(subscription)
+    }
+
+    /// Annulla una sottoscrizione all'output della sessione
+    pub fn unsubscribe_output(&self, subscription_id: u64) -> bool {
+        self.backend.unsubscribe_output(subscription_id)
+    }
+
+    /// Legge i byte grezzi della sessione a partire da un offset assoluto
+    pub fn read_bytes(&self, from_offset: u64) -> Result<ScrollbackRead> {
+        self.backend.read_output(from_offset)
+    }
+
+    /// Restituisce l'output completo della sessione
+    pub fn output(&self) -> Result<String> {
+        let read = self.read_bytes(0)?;
+        Ok(String::from_utf8_lossy(&read.data).to_string())
+    }
+
+    /// Restituisce l'istantanea dello schermo
+    pub fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+        self.backend.get_screen(include_history)
+    }
+
+    /// Elenca i comandi rilevati nella sessione
+    pub fn list_commands(&self) -> Result<Vec<CommandRecord>> {
+        self.backend.list_commands()
+    }
+
+    /// Restituisce un comando della sessione con il relativo output
+    pub fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        self.backend.get_command_output(command_id)
+    }
+
+    /// Esporta lo scrollback, o solo gli ultimi `last_commands` comandi
+    /// rilevati con i rispettivi prompt
+    pub fn export_scrollback(&self, format: ExportFormat, last_commands: Option<usize>) -> Result<String> {
+        let screen = self.get_screen(false)?;
+        let read = match last_commands {
+            Some(count) => {
+                let commands = self.list_commands()?;
+                let first = commands
+                    .len()
+                    .checked_sub(count.max(1))
+                    .map_or(commands.first(), |index| commands.get(index))
+                    .ok_or_else(|| anyhow!("No commands recorded in session {}", self.id()))?;
+                let start = first.prompt_offset.unwrap_or(first.output_start);
+                let mut read = self.read_bytes(start)?;
+                if let Some(end) = commands.last().and_then(|last| last.output_end) {
+                    read.data.truncate(end.saturating_sub(read.start) as usize);
+                }
+                read
+            }
+            None => self.read_bytes(0)?,
+        };
+
+        Ok(export::export(
+            &read.data,
+            screen.cols as u16,
+            screen.rows as u16,
+            format,
+            &screen.title,
+        ))
+    }
+
+    /// Ridimensiona la sessione
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        self.backend.resize(cols, rows)?;
+        self.mark_attached();
+        Ok(())
+    }
+
+    /// Invia un segnale ai processi della sessione
+    pub fn signal(&self, signal: SessionSignal, target: SignalTarget) -> Result<i32> {
+        self.backend.signal(signal, target)
+    }
+
+    /// Configurazione per una nuova sessione nel contesto di questa
+    pub fn duplicate_config(&self, inherit_env: bool) -> Result<PtyConfig> {
+        self.backend.duplicate_config(inherit_env)
+    }
+
+    /// Pulisce il buffer della sessione
+    pub fn clear(&self) -> Result<()> {
+        self.backend.clear()?;
+        // Una lettura oltre la fine restituisce solo l'offset corrente
+        let end = self.backend.read_output(u64::MAX)?.end;
+        self.last_sent_offset.store(end, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Recupera output incrementale basato sull'offset interno
+    pub fn incremental_output(&self) -> Result<IncrementalOutput> {
+        let read = self.backend.read_output(self.last_sent_offset.load(Ordering::SeqCst))?;
+        let last_activity = self.backend.status()?.last_activity;
+        let output = IncrementalOutput::from_read(self.id(), read, last_activity);
+        self.last_sent_offset.store(output.offset, Ordering::SeqCst);
+        self.mark_attached();
+        Ok(output)
+    }
+
+    /// Stato della sessione
+    pub fn status(&self) -> Result<SessionStatus> {
+        self.backend.status()
+    }
+
+    /// Processo in primo piano della sessione
+    pub fn foreground_process(&self) -> Result<Option<ProcessInfo>> {
+        self.backend.foreground_process()
+    }
+
+    /// Directory corrente della sessione
+    pub fn cwd(&self) -> Result<String> {
+        self.backend.cwd()
+    }
+
+    /// Avvia la registrazione asciicast della sessione
+    pub fn start_recording(&self, path: &Path, record_input: bool) -> Result<()> {
+        self.backend.start_recording(path, record_input)
+    }
+
+    /// Termina la registrazione, restituendo il file scritto
+    pub fn stop_recording(&self) -> Result<PathBuf> {
+        self.backend.stop_recording()
+    }
+
+    /// Programmi in esecuzione, per chiedere conferma prima della chiusura;
+    /// una sessione che non risponde va confermata
+    pub fn close_info(&self, ignore: &[String]) -> SessionCloseInfo {
+        self.backend.close_info(ignore).unwrap_or_else(|e| {
+            warn!("Failed to check PTY session {} before closing: {}", self.id(), e);
+            SessionCloseInfo::failed(self.id(), e.to_string())
+        })
+    }
+}
+
+/// Output incrementale restituito al frontend
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalOutput {
+    pub output: String,
+    pub last_activity: u64,
+    pub has_new_data: bool,
+    /// Offset assoluto raggiunto dopo questa lettura
+    pub offset: u64,
+    /// Byte persi perché scartati dallo scrollback prima di essere letti
+    pub dropped_bytes: u64,
+}
+
+impl IncrementalOutput {
+    fn from_read(session_id: &str, read: ScrollbackRead, last_activity: u64) -> Self {
+        if read.dropped > 0 {
+            debug!(
+                "Session {}: {} bytes dropped from scrollback before being read",
+                session_id, read.dropped
+            );
+        }
+
+        // Dopo uno scarto la lettura può iniziare a metà di un carattere;
+        // una sequenza incompleta finale resta invece per la prossima lettura
+        let skip = if read.dropped > 0 {
+            utf8::leading_continuation_len(&read.data)
+        } else {
+            0
+        };
+        let complete = skip.max(utf8::complete_prefix_len(&read.data));
+        let text = &read.data[skip..complete];
+
+        Self {
+            has_new_data: !text.is_empty(),
+            output: String::from_utf8_lossy(text).to_string(),
+            last_activity,
+            offset: read.start + complete as u64,
+            dropped_bytes: read.dropped + skip as u64,
+        }
+    }
+}
+
+/// Registro delle sessioni, qualunque sia il loro backend (vedi `backend`).
+///
+/// Con `termina-muxd` le nuove sessioni appartengono al demone e vengono
+/// raggiunte tramite `RemoteSession`; quelle create da altri client entrano
+/// nel registro con `merge_remote_sessions`. Le riproduzioni di
+/// registrazioni restano sempre locali.
 #[derive(Default)]
 pub struct PtyManager {
-    sessions: HashMap<String, SessionEntry>,
+    sessions: HashMap<String, Arc<ManagedSession>>,
+    output_listener: Option<OutputSubscriber>,
+    event_listener: Option<SessionEventListener>,
+    #[cfg(unix)]
+    remote: Option<Arc<MuxClient>>,
+    broadcast_groups: BroadcastGroups,
+    reaper_policy: ReaperPolicy,
 }
 
 impl PtyManager {
@@ -27,7 +284,82 @@ impl PtyManager {
     pub fn new() -> Self {
         Self {
             sessions: HashMap::new(),
+            output_listener: None,
+            event_listener: None,
+            #[cfg(unix)]
+            remote: None,
+            broadcast_groups: BroadcastGroups::new(),
+            reaper_policy: ReaperPolicy::default(),
+        }
+    }
+
+    /// Passa al demone `termina-muxd` e si ricollega alle sessioni che già
+    /// possiede, restituendone gli id. I listener vanno impostati prima.
+    #[cfg(unix)]
+    pub fn use_remote(&mut self, client: Arc<MuxClient>) -> Result<Vec<String>> {
+        if let Some(listener) = &self.event_listener {
+            client.set_event_listener(Arc::clone(listener));
+        }
+        let sessions = client.list_sessions()?;
+        for session_id in &sessions {
+            client.attach(session_id, self.output_listener.clone())?;
+            self.insert(Arc::new(RemoteSession::new(session_id.clone(), Arc::clone(&client))));
+        }
+        info!("Reattached {} PTY sessions from muxd", sessions.len());
+        self.remote = Some(client);
+        Ok(sessions)
+    }
+
+    /// Client del demone, per le richieste da fare fuori dal lock del manager
+    #[cfg(unix)]
+    pub fn mux_client(&self) -> Option<Arc<MuxClient>> {
+        self.remote.clone()
+    }
+
+    /// Registra le sessioni del demone create da altri client, dato l'elenco
+    /// restituito da `ListSessions`, e restituisce gli id di tutte le
+    /// sessioni. Quelle del demone chiuse da altri client non vengono
+    /// riportate.
+    #[cfg(unix)]
+    pub fn merge_remote_sessions(&mut self, remote_sessions: Vec<String>) -> Vec<String> {
+        if let Some(remote) = self.remote.clone() {
+            for session_id in &remote_sessions {
+                if !self.sessions.contains_key(session_id) {
+                    self.insert(Arc::new(RemoteSession::new(session_id.clone(), Arc::clone(&remote))));
+                }
+            }
+        }
+        let mut sessions = remote_sessions;
+        for (session_id, entry) in &self.sessions {
+            if !sessions.contains(session_id) && !Self::is_remote_session(&entry.backend) {
+                sessions.push(session_id.clone());
+            }
+        }
+        sessions
+    }
+
+    /// Le sessioni appartengono al demone
+    pub fn is_remote(&self) -> bool {
+        #[cfg(unix)]
+        {
+            self.remote.is_some()
         }
+        #[cfg(not(unix))]
+        {
+            false
+        }
+    }
+
+    /// Imposta il listener che riceve l'output di tutte le sessioni create
+    /// da qui in avanti (es. emissione di eventi Tauri)
+    pub fn set_output_listener(&mut self, listener: OutputSubscriber) {
+        self.output_listener = Some(listener);
+    }
+
+    /// Imposta il listener degli eventi di ciclo di vita delle sessioni
+    /// create da qui in avanti (es. terminazione del processo)
+    pub fn set_event_listener(&mut self, listener: SessionEventListener) {
+        self.event_listener = Some(listener);
     }
 
     /// Crea una nuova sessione PTY con la configurazione fornita
@@ -39,132 +371,461 @@ impl PtyManager {
             config.cwd = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
         }
 
+        if self.sessions.contains_key(&session_id) {
+            return Err(anyhow!("Session with ID {} already exists", session_id));
+        }
+        #[cfg(unix)]
+        if let Some(remote) = &self.remote {
+            remote.create_session(&session_id, config, self.output_listener.clone())?;
+            let session = RemoteSession::new(session_id.clone(), Arc::clone(remote));
+            self.insert(Arc::new(session));
+            info!("PTY session created in muxd: {}", session_id);
+            return Ok(session_id);
+        }
+
         let session = RealPtySession::new(session_id.clone(), config)?;
-        self.sessions.insert(
-            session_id.clone(),
-            SessionEntry {
-                session: Arc::new(session),
-                last_sent_index: 0,
-            },
-        );
+        self.register_session(Arc::new(session))?;
 
         info!("PTY session created successfully: {}", session_id);
         Ok(session_id)
     }
 
-    /// Scrive dati a una sessione esistente
-    pub fn write_to_session(&self, session_id: &str, data: &str) -> Result<()> {
-        if let Some(entry) = self.sessions.get(session_id) {
-            entry.session.write(data)
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+    /// Aggiunge al registro una sessione con un backend qualsiasi, collegando
+    /// l'output e gli eventi ai listener del manager
+    pub fn register_session(&mut self, backend: Arc<dyn SessionBackend>) -> Result<()> {
+        if self.sessions.contains_key(backend.id()) {
+            return Err(anyhow!("Session with ID {} already exists", backend.id()));
+        }
+        if let Some(listener) = &self.output_listener {
+            backend.subscribe_output(Arc::clone(listener))?;
         }
+        if let Some(listener) = &self.event_listener {
+            backend.set_event_listener(Arc::clone(listener));
+        }
+        self.insert(backend);
+        Ok(())
     }
 
-    /// Ridimensiona una sessione PTY
-    pub fn resize_session(&self, session_id: &str, cols: u16, rows: u16) -> Result<()> {
-        if let Some(entry) = self.sessions.get(session_id) {
-            entry.session.resize(cols, rows)
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
-        }
+    fn insert(&mut self, backend: Arc<dyn SessionBackend>) {
+        self.sessions
+            .insert(backend.id().to_string(), Arc::new(ManagedSession::new(backend)));
     }
 
-    /// Chiude e rimuove una sessione
-    pub fn close_session(&mut self, session_id: &str) -> Result<()> {
-        if let Some(entry) = self.sessions.remove(session_id) {
-            entry.session.close()
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+    /// Sessione del registro, da usare anche dopo aver rilasciato il manager
+    pub fn session(&self, session_id: &str) -> Result<Arc<ManagedSession>> {
+        self.sessions
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Session not found: {}", session_id))
+    }
+
+    /// Sessioni del registro tra quelle indicate, nello stesso ordine
+    pub fn sessions(&self, session_ids: &[String]) -> Vec<Arc<ManagedSession>> {
+        session_ids
+            .iter()
+            .filter_map(|session_id| self.sessions.get(session_id).cloned())
+            .collect()
+    }
+
+    /// Toglie una sessione dal registro e dai gruppi di broadcast
+    fn remove(&mut self, session_id: &str) -> Result<Arc<dyn SessionBackend>> {
+        let entry = self
+            .sessions
+            .remove(session_id)
+            .ok_or_else(|| anyhow!("Session not found: {}", session_id))?;
+        self.broadcast_groups.forget_session(session_id);
+        Ok(Arc::clone(&entry.backend))
+    }
+
+    #[cfg(unix)]
+    fn is_remote_session(backend: &Arc<dyn SessionBackend>) -> bool {
+        Arc::clone(backend).into_any().is::<RemoteSession>()
+    }
+
+    /// Avvia la riproduzione di una registrazione in una sessione di sola lettura
+    pub fn play_recording(&mut self, session_id: String, path: &Path, options: PlaybackOptions) -> Result<String> {
+        if self.has_session(&session_id) {
+            return Err(anyhow!("Session with ID {} already exists", session_id));
         }
+        let recording = Recording::load(path)?;
+        let playback = PlaybackSession::start(session_id.clone(), recording, options)?;
+        self.register_session(Arc::new(playback))?;
+        info!("Playing {} in session {}", path.display(), session_id);
+        Ok(session_id)
     }
 
-    /// Uccide una sessione
-    pub fn kill_session(&mut self, session_id: &str) -> Result<()> {
-        if let Some(entry) = self.sessions.remove(session_id) {
-            entry.session.kill()
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+    /// Cambia la velocità di una riproduzione
+    pub fn set_playback_speed(&self, session_id: &str, speed: f64) -> Result<()> {
+        self.sessions
+            .get(session_id)
+            .and_then(|entry| Arc::clone(&entry.backend).into_any().downcast::<PlaybackSession>().ok())
+            .ok_or_else(|| anyhow!("Playback session not found: {}", session_id))?
+            .set_speed(speed)
+    }
+
+    /// Avvia la registrazione asciicast di una sessione
+    pub fn start_recording(&self, session_id: &str, path: &Path, record_input: bool) -> Result<()> {
+        self.session(session_id)?.start_recording(path, record_input)
+    }
+
+    /// Termina la registrazione di una sessione, restituendo il file scritto
+    pub fn stop_recording(&self, session_id: &str) -> Result<PathBuf> {
+        self.session(session_id)?.stop_recording()
+    }
+
+    /// Salva su disco le sessioni ancora attive
+    pub fn save_sessions(&self, path: &Path) -> Result<usize> {
+        // Le sessioni del demone sopravvivono da sole alla chiusura
+        if self.is_remote() {
+            return Ok(0);
+        }
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut saved = Vec::new();
+        // Solo i PTY locali hanno una shell da ricreare
+        let attached = self
+            .sessions
+            .iter()
+            .filter(|(_, entry)| entry.attached.load(Ordering::SeqCst))
+            .filter_map(|(session_id, _)| self.get_session(session_id));
+        for session in attached {
+            if session.exit_status().is_some() {
+                continue;
+            }
+            match SavedSession::capture(&session, saved_at) {
+                Ok(saved_session) => saved.push(saved_session),
+                Err(e) => warn!("Failed to save PTY session {}: {}", session.id, e),
+            }
         }
+
+        let count = saved.len();
+        persistence::save(path, saved)?;
+        info!("Saved {} PTY sessions to {}", count, path.display());
+        Ok(count)
     }
 
-    /// Pulisce il buffer di una sessione
-    pub fn clear_session(&mut self, session_id: &str) -> Result<()> {
-        if let Some(entry) = self.sessions.get_mut(session_id) {
-            entry.last_sent_index = 0;
-            entry.session.clear()
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+    /// Ripristina le sessioni salvate, restituendo gli id ricreati
+    pub fn restore_sessions(&mut self, path: &Path) -> Result<Vec<String>> {
+        if self.is_remote() {
+            return Ok(Vec::new());
+        }
+        let mut restored = Vec::new();
+        for saved in persistence::take(path)? {
+            if self.sessions.contains_key(&saved.id) {
+                continue;
+            }
+            let registered = RealPtySession::restore(&saved)
+                .and_then(|session| self.register_session(Arc::new(session)));
+            match registered {
+                Ok(()) => {
+                    self.sessions[&saved.id].attached.store(false, Ordering::SeqCst);
+                    restored.push(saved.id);
+                }
+                Err(e) => warn!("Failed to restore PTY session {}: {}", saved.id, e),
+            }
         }
+
+        info!("Restored {} PTY sessions", restored.len());
+        Ok(restored)
     }
 
-    /// Recupera output incrementale basato sull'indice interno
-    pub fn get_incremental_output(&mut self, session_id: &str, _from_timestamp: u64) -> Result<(String, u64, bool)> {
-        if let Some(entry) = self.sessions.get_mut(session_id) {
-            let new_output = entry
-                .session
-                .get_incremental_output(entry.last_sent_index)
-                .map_err(|e| anyhow!(e))?;
+    /// Sessioni ripristinate che nessun client ha ancora aperto
+    pub fn restored_sessions(&self) -> Vec<String> {
+        self.sessions
+            .iter()
+            .filter(|(_, entry)| !entry.attached.load(Ordering::SeqCst))
+            .map(|(session_id, _)| session_id.clone())
+            .collect()
+    }
 
-            if !new_output.is_empty() {
-                entry.last_sent_index = entry.session.get_buffer_length();
-                let last_activity = entry.session.get_last_activity();
-                return Ok((new_output, last_activity, true));
-            }
+    /// Scrive dati a una sessione esistente
+    pub fn write_to_session(&self, session_id: &str, data: &str) -> Result<()> {
+        self.write_bytes_to_session(session_id, data.as_bytes())
+    }
 
-            let last_activity = entry.session.get_last_activity();
-            Ok((String::new(), last_activity, false))
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+    /// Sessioni di un gruppo di broadcast e quelle indicate, da passare a
+    /// `broadcast_write`; le sessioni sconosciute riportano l'errore
+    pub fn broadcast_targets(
+        &self,
+        group: Option<&str>,
+        session_ids: &[String],
+    ) -> Result<Vec<(String, Result<Arc<ManagedSession>>)>> {
+        let mut targets: Vec<String> = match group {
+            Some(name) => self
+                .broadcast_groups
+                .members(name)
+                .ok_or_else(|| anyhow!("Broadcast group not found: {}", name))?
+                .to_vec(),
+            None => Vec::new(),
+        };
+        for session_id in session_ids {
+            if !targets.contains(session_id) {
+                targets.push(session_id.clone());
+            }
         }
+        if targets.is_empty() {
+            return Err(anyhow!("No sessions to broadcast to"));
+        }
+        Ok(targets
+            .into_iter()
+            .map(|session_id| {
+                let session = self.session(&session_id);
+                (session_id, session)
+            })
+            .collect())
     }
 
-    /// Restituisce l'output completo della sessione
-    pub fn get_session_output(&self, session_id: &str) -> Result<String> {
-        if let Some(entry) = self.sessions.get(session_id) {
-            entry
-                .session
-                .get_incremental_output(0)
-                .map_err(|e| anyhow!(e))
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+    /// Sostituisce le sessioni di un gruppo di broadcast; un elenco vuoto lo elimina
+    pub fn set_broadcast_group(&mut self, name: &str, session_ids: &[String]) -> Result<()> {
+        self.ensure_sessions_exist(session_ids)?;
+        self.broadcast_groups.set(name, session_ids);
+        Ok(())
+    }
+
+    /// Aggiunge e toglie sessioni da un gruppo di broadcast
+    pub fn edit_broadcast_group(&mut self, name: &str, add: &[String], remove: &[String]) -> Result<()> {
+        self.ensure_sessions_exist(add)?;
+        self.broadcast_groups.add(name, add);
+        self.broadcast_groups.remove(name, remove);
+        Ok(())
+    }
+
+    pub fn delete_broadcast_group(&mut self, name: &str) -> bool {
+        self.broadcast_groups.delete(name)
+    }
+
+    /// Gruppi di broadcast con le loro sessioni
+    pub fn broadcast_groups(&self) -> BTreeMap<String, Vec<String>> {
+        self.broadcast_groups.list()
+    }
+
+    fn ensure_sessions_exist(&self, session_ids: &[String]) -> Result<()> {
+        match session_ids.iter().find(|session_id| !self.has_session(session_id)) {
+            Some(session_id) => Err(anyhow!("Session not found: {}", session_id)),
+            None => Ok(()),
         }
     }
 
+    /// Sottoscrive l'output in streaming di una sessione (vedi
+    /// `ManagedSession::subscribe_output`)
+    pub fn subscribe_output(&self, session_id: &str, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        self.session(session_id)?.subscribe_output(subscriber)
+    }
+
+    /// Annulla una sottoscrizione all'output di una sessione
+    pub fn unsubscribe_output(&self, session_id: &str, subscription_id: u64) -> Result<bool> {
+        Ok(self.session(session_id)?.unsubscribe_output(subscription_id))
+    }
+
+    /// Scrive byte arbitrari a una sessione esistente
+    pub fn write_bytes_to_session(&self, session_id: &str, data: &[u8]) -> Result<()> {
+        self.session(session_id)?.write(data)
+    }
+
+    /// Legge i byte grezzi di una sessione a partire da un offset assoluto
+    pub fn read_session_bytes(&self, session_id: &str, from_offset: u64) -> Result<ScrollbackRead> {
+        self.session(session_id)?.read_bytes(from_offset)
+    }
+
+    /// Restituisce l'istantanea dello schermo di una sessione
+    pub fn get_screen(&self, session_id: &str, include_history: bool) -> Result<ScreenSnapshot> {
+        self.session(session_id)?.get_screen(include_history)
+    }
+
+    /// Elenca i comandi rilevati in una sessione
+    pub fn list_commands(&self, session_id: &str) -> Result<Vec<CommandRecord>> {
+        self.session(session_id)?.list_commands()
+    }
+
+    /// Restituisce un comando di una sessione con il relativo output
+    pub fn get_command_output(&self, session_id: &str, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        self.session(session_id)?.get_command_output(command_id)
+    }
+
+    /// Ridimensiona una sessione PTY
+    pub fn resize_session(&self, session_id: &str, cols: u16, rows: u16) -> Result<()> {
+        self.session(session_id)?.resize(cols, rows)
+    }
+
+    /// Invia un segnale ai processi di una sessione
+    pub fn signal_session(&self, session_id: &str, signal: SessionSignal, target: SignalTarget) -> Result<i32> {
+        self.session(session_id)?.signal(signal, target)
+    }
+
+    /// Configurazione per una nuova sessione nel contesto di quella indicata
+    pub fn duplicate_config(&self, session_id: &str, inherit_env: bool) -> Result<PtyConfig> {
+        self.session(session_id)?.duplicate_config(inherit_env)
+    }
+
+    /// Chiude e rimuove una sessione, riportando i processi che è stato
+    /// necessario uccidere. La chiusura può attendere il periodo di grazia:
+    /// chi condivide il manager deve usare `take_session` e chiudere fuori
+    /// dal lock
+    pub fn close_session(&mut self, session_id: &str) -> Result<ShutdownReport> {
+        self.remove(session_id)?.close()
+    }
+
+    /// Toglie una sessione dal registro senza chiuderla
+    pub fn take_session(&mut self, session_id: &str) -> Result<Arc<dyn SessionBackend>> {
+        self.remove(session_id)
+    }
+
+    /// Uccide una sessione
+    pub fn kill_session(&mut self, session_id: &str) -> Result<()> {
+        self.remove(session_id)?.kill()
+    }
+
+    /// Pulisce il buffer di una sessione
+    pub fn clear_session(&self, session_id: &str) -> Result<()> {
+        self.session(session_id)?.clear()
+    }
+    /// Id delle sessioni del registro; con `termina-muxd` quelle create da
+    /// altri client compaiono dopo `merge_remote_sessions`
     pub fn list_sessions(&self) -> Vec<String> {
         self.sessions.keys().cloned().collect()
     }
 
-    /// Restituisce una sessione per usi speciali (es. sudo handler)
+    /// La sessione è nel registro
+    pub fn has_session(&self, session_id: &str) -> bool {
+        self.sessions.contains_key(session_id)
+    }
+
+    /// Programmi in esecuzione in una sessione, o in tutte, per chiedere
+    /// conferma prima della chiusura
+    pub fn close_check(&self, session_id: Option<&str>, ignore: &[String]) -> Result<CloseCheck> {
+        let sessions = match session_id {
+            Some(session_id) => vec![self.session(session_id)?],
+            None => self.sessions(&self.list_sessions()),
+        };
+        Ok(close_check_sessions(&sessions, ignore))
+    }
+
+    /// Restituisce una sessione PTY locale per usi speciali (es. salvataggio)
     pub fn get_session(&self, session_id: &str) -> Option<Arc<RealPtySession>> {
-        self.sessions
-            .get(session_id)
-            .map(|entry| Arc::clone(&entry.session))
+        let entry = self.sessions.get(session_id)?;
+        Arc::clone(&entry.backend).into_any().downcast::<RealPtySession>().ok()
+    }
+
+    /// Stato di una sessione
+    pub fn get_session_status(&self, session_id: &str) -> Result<SessionStatus> {
+        self.session(session_id)?.status()
+    }
+
+    /// Processo in primo piano di una sessione
+    pub fn get_foreground_process(&self, session_id: &str) -> Result<Option<ProcessInfo>> {
+        self.session(session_id)?.foreground_process()
+    }
+
+    /// Directory corrente di una sessione
+    pub fn get_session_cwd(&self, session_id: &str) -> Result<String> {
+        self.session(session_id)?.cwd()
     }
 
     /// Aggiorna il prompt inviando un comando direttamente
     pub fn run_command(&self, session_id: &str, command: &str) -> Result<()> {
-        if let Some(entry) = self.sessions.get(session_id) {
-            entry.session.run_command(command)
-        } else {
-            Err(anyhow!("Session not found: {}", session_id))
+        info!("Running command in session {}: {}", session_id, command);
+        self.write_to_session(session_id, &format!("{}\n", command))
+    }
+
+    /// Criteri usati dal reaper per rimuovere le sessioni
+    pub fn reaper_policy(&self) -> &ReaperPolicy {
+        &self.reaper_policy
+    }
+
+    /// Imposta i criteri del reaper; con `termina-muxd` valgono per il demone
+    pub fn set_reaper_policy(&mut self, policy: ReaperPolicy) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(remote) = &self.remote {
+            remote.call::<()>(Request::SetReaperPolicy { policy: policy.clone() })?;
         }
+        self.reaper_policy = policy;
+        Ok(())
     }
 
-    /// Pulisce le sessioni inattive (quelle non più attive)
-    pub fn cleanup_inactive_sessions(&mut self) {
-        let inactive: Vec<String> = self
-            .sessions
-            .iter()
-            .filter(|(_, entry)| !*entry.session.is_active.lock().unwrap())
-            .map(|(id, _)| id.clone())
-            .collect();
-
-        for session_id in inactive {
-            if let Some(entry) = self.sessions.remove(&session_id) {
-                debug!("Cleaning up inactive session: {}", session_id);
-                let _ = entry.session.close();
+    /// Stato per il reaper delle sessioni che lo gestiscono da qui
+    pub fn session_activity(&self) -> Vec<SessionActivity> {
+        self.sessions
+            .values()
+            .filter_map(|entry| entry.backend.activity())
+            .collect()
+    }
+
+    /// Toglie dal registro le sessioni scelte dal reaper; la chiusura spetta
+    /// al chiamante
+    pub fn take_sessions(
+        &mut self,
+        selected: &[(String, ReapReason)],
+    ) -> Vec<(Arc<dyn SessionBackend>, ReapReason)> {
+        let mut taken = Vec::new();
+        for (session_id, reason) in selected {
+            if let Some(entry) = self.sessions.remove(session_id) {
+                debug!("Removing session {} ({:?})", session_id, reason);
+                self.broadcast_groups.forget_session(session_id);
+                taken.push((Arc::clone(&entry.backend), *reason));
             }
         }
+        taken
+    }
+
+    pub fn event_listener(&self) -> Option<SessionEventListener> {
+        self.event_listener.clone()
     }
 }
+
+/// Scrive lo stesso input nelle sessioni di `PtyManager::broadcast_targets`,
+/// riportando l'esito per ognuna
+pub fn broadcast_write(targets: Vec<(String, Result<Arc<ManagedSession>>)>, data: &str) -> Vec<BroadcastResult> {
+    targets
+        .into_iter()
+        .map(|(session_id, session)| {
+            let written = session.and_then(|session| session.write(data.as_bytes()));
+            BroadcastResult::new(&session_id, written)
+        })
+        .collect()
+}
+
+/// Cerca nello scrollback delle sessioni indicate; con `all_sessions` quelle
+/// che non rispondono vengono saltate invece di interrompere la ricerca
+pub fn search_sessions(
+    sessions: &[Arc<ManagedSession>],
+    all_sessions: bool,
+    options: &SearchOptions,
+) -> Result<SearchResults> {
+    let regex = options.compile()?;
+    let mut results = SearchResults::default();
+    for session in sessions {
+        let session_id = session.id();
+        let remaining = options.max_results.saturating_sub(results.matches.len());
+        let read = match session.read_bytes(0) {
+            Ok(read) => read,
+            // Cercando in tutte le sessioni, una che non risponde (es. chiusa
+            // nel frattempo da un altro client del demone) si salta
+            Err(e) if all_sessions => {
+                warn!("Skipping PTY session {} in search: {}", session_id, e);
+                results.skipped_sessions.push(session_id.to_string());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        // Le riproduzioni non hanno comandi rilevati
+        let commands = session.list_commands().unwrap_or_default();
+        let mut matches = search::search(session_id, &read, &commands, &regex, options, remaining + 1);
+        if matches.len() > remaining {
+            matches.truncate(remaining);
+            results.truncated = true;
+        }
+        results.matches.extend(matches);
+        if results.truncated {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Programmi in esecuzione nelle sessioni indicate, per chiedere conferma
+/// prima della chiusura; una sessione che non risponde non blocca le altre
+pub fn close_check_sessions(sessions: &[Arc<ManagedSession>], ignore: &[String]) -> CloseCheck {
+    CloseCheck::new(sessions.iter().map(|session| session.close_info(ignore)).collect())
+}
@@ -0,0 +1,203 @@
+//! Manutenzione periodica delle sessioni
+//!
+//! Un thread in background rimuove dal `PtyManager` le sessioni morte: quelle
+//! il cui processo è terminato, dopo un periodo di grazia che lascia leggibile
+//! l'output finale, e quelle il cui reader si è fermato. A richiesta chiude
+//! anche le sessioni abbandonate, senza input né output da troppo tempo.
+//! Ogni rimozione viene notificata con l'evento `pty-session-reaped`.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use super::events::SessionEvent;
+use super::pty_manager::PtyManager;
+
+/// Intervallo tra due passaggi del reaper
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Criteri di rimozione delle sessioni
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReaperPolicy {
+    /// Secondi per cui una sessione terminata resta leggibile
+    /// (da `terminal.exited_session_grace_secs`)
+    #[serde(default = "default_exited_grace_secs")]
+    pub exited_grace_secs: u64,
+    /// Chiude le sessioni senza attività da questo numero di secondi
+    /// (da `terminal.idle_timeout_secs`, 0 per disattivarlo)
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+}
+
+fn default_exited_grace_secs() -> u64 {
+    300
+}
+
+impl Default for ReaperPolicy {
+    fn default() -> Self {
+        Self {
+            exited_grace_secs: default_exited_grace_secs(),
+            idle_timeout_secs: None,
+        }
+    }
+}
+
+/// Motivo della rimozione di una sessione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReapReason {
+    /// Il processo è terminato e il periodo di grazia è trascorso
+    Exited,
+    /// Il reader dell'output si è fermato con il processo ancora vivo
+    ReaderStopped,
+    /// Nessuna attività per più del tempo massimo configurato
+    IdleTimeout,
+}
+
+/// Stato di una sessione rilevante per il reaper
+#[derive(Debug, Clone)]
+pub struct SessionActivity {
+    pub session_id: String,
+    /// Istante di terminazione del processo
+    pub exited_at: Option<u64>,
+    pub reader_active: bool,
+    /// Ultimo input o output
+    pub last_activity: u64,
+}
+
+/// Sceglie le sessioni da rimuovere, ricordando da quando il reader è fermo
+#[derive(Debug, Default)]
+pub struct Reaper {
+    stopped_since: HashMap<String, u64>,
+}
+
+impl Reaper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn select(
+        &mut self,
+        sessions: &[SessionActivity],
+        policy: &ReaperPolicy,
+        now: u64,
+    ) -> Vec<(String, ReapReason)> {
+        self.stopped_since
+            .retain(|session_id, _| sessions.iter().any(|session| &session.session_id == session_id));
+
+        let mut reaped = Vec::new();
+        for session in sessions {
+            let expired = |since: u64| now.saturating_sub(since) >= policy.exited_grace_secs;
+            let reason = if let Some(exited_at) = session.exited_at {
+                expired(exited_at).then_some(ReapReason::Exited)
+            } else if !session.reader_active {
+                let since = *self.stopped_since.entry(session.session_id.clone()).or_insert(now);
+                expired(since).then_some(ReapReason::ReaderStopped)
+            } else {
+                policy
+                    .idle_timeout_secs
+                    .filter(|timeout| now.saturating_sub(session.last_activity) >= *timeout)
+                    .map(|_| ReapReason::IdleTimeout)
+            };
+            if let Some(reason) = reason {
+                self.stopped_since.remove(&session.session_id);
+                reaped.push((session.session_id.clone(), reason));
+            }
+        }
+        reaped
+    }
+}
+
+/// Avvia il reaper; il thread termina quando il manager viene rilasciato
+pub fn start(manager: Weak<Mutex<PtyManager>>) {
+    thread::spawn(move || {
+        let mut reaper = Reaper::new();
+        loop {
+            thread::sleep(REAP_INTERVAL);
+            let Some(manager) = manager.upgrade() else {
+                break;
+            };
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0);
+
+            // La chiusura può attendere il periodo di grazia dei processi:
+            // avviene fuori dal lock del manager
+            let (sessions, listener) = {
+                let mut manager = manager.lock().unwrap();
+                let policy = manager.reaper_policy().clone();
+                let selected = reaper.select(&manager.session_activity(), &policy, now);
+                (manager.take_sessions(&selected), manager.event_listener())
+            };
+            for (session, reason) in sessions {
+                info!("Reaping PTY session {} ({:?})", session.id(), reason);
+                match session.close() {
+                    Ok(report) if !report.killed.is_empty() => {
+                        debug!("Reaped session {} killed {} processes", session.id(), report.killed.len())
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("Failed to close reaped session {}: {}", session.id(), e),
+                }
+                if let Some(listener) = &listener {
+                    listener(&SessionEvent::Reaped {
+                        session_id: session.id().to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(session_id: &str, exited_at: Option<u64>, reader_active: bool, last_activity: u64) -> SessionActivity {
+        SessionActivity {
+            session_id: session_id.to_string(),
+            exited_at,
+            reader_active,
+            last_activity,
+        }
+    }
+
+    #[test]
+    fn test_select_sessions_to_reap() {
+        let policy = ReaperPolicy {
+            exited_grace_secs: 60,
+            idle_timeout_secs: Some(1000),
+        };
+        let sessions = [
+            activity("exited", Some(1000), false, 1000),
+            activity("recent", Some(1050), false, 1050),
+            activity("stopped", None, false, 0),
+            activity("idle", None, true, 0),
+            activity("busy", None, true, 1090),
+        ];
+        let mut reaper = Reaper::new();
+        assert_eq!(
+            reaper.select(&sessions, &policy, 1090),
+            vec![
+                ("exited".to_string(), ReapReason::Exited),
+                ("idle".to_string(), ReapReason::IdleTimeout),
+            ]
+        );
+        // Il reader fermo conta dal primo passaggio che lo ha visto
+        assert!(reaper.select(&sessions[2..3], &policy, 1140).is_empty());
+        assert_eq!(
+            reaper.select(&sessions[1..3], &policy, 1150),
+            vec![
+                ("recent".to_string(), ReapReason::Exited),
+                ("stopped".to_string(), ReapReason::ReaderStopped),
+            ]
+        );
+        let policy = ReaperPolicy::default();
+        assert!(reaper.select(&sessions[3..], &policy, 1_000_000).is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+//! Registrazione delle sessioni in formato asciicast v2
+//!
+//! Un file `.cast` è composto da un'intestazione JSON seguita da un evento
+//! per riga: `[tempo, codice, dati]`, dove il tempo è in secondi dall'inizio
+//! e il codice è `o` (output), `i` (input) o `r` (ridimensionamento,
+//! `"COLONNExRIGHE"`). Ogni riga viene scritta subito, così una
+//! registrazione interrotta resta leggibile.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::utf8::Utf8Decoder;
+
+/// Intestazione di una registrazione asciicast v2
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CastHeader {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Pausa massima in riproduzione, in secondi
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<HashMap<String, String>>,
+}
+
+/// Evento di una registrazione
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Secondi dall'inizio della registrazione
+    pub time: f64,
+    pub code: String,
+    pub data: String,
+}
+
+impl CastEvent {
+    /// Dimensioni di un evento di ridimensionamento
+    pub fn size(&self) -> Option<(u16, u16)> {
+        if self.code != "r" {
+            return None;
+        }
+        let (cols, rows) = self.data.split_once('x')?;
+        Some((cols.trim().parse().ok()?, rows.trim().parse().ok()?))
+    }
+}
+
+/// Registrazione caricata da file
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub header: CastHeader,
+    pub events: Vec<CastEvent>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open recording: {}", path.display()))?;
+        Self::parse(BufReader::new(file))
+            .with_context(|| format!("Invalid recording: {}", path.display()))
+    }
+
+    pub fn parse(reader: impl BufRead) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header_line = lines.next().ok_or_else(|| anyhow!("Empty recording"))??;
+        let header: CastHeader = serde_json::from_str(&header_line)?;
+        if header.version != 2 {
+            return Err(anyhow!("Unsupported asciicast version: {}", header.version));
+        }
+
+        let mut events = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, code, data): (f64, String, String) = serde_json::from_str(&line)
+                .map_err(|e| anyhow!("Invalid event on line {}: {}", index + 2, e))?;
+            events.push(CastEvent { time, code, data });
+        }
+        Ok(Self { header, events })
+    }
+}
+
+/// Scrive gli eventi di una sessione su un file `.cast`
+pub struct Recorder {
+    path: PathBuf,
+    writer: LineWriter<File>,
+    started: Instant,
+    record_input: bool,
+    output: Utf8Decoder,
+    input: Utf8Decoder,
+}
+
+impl Recorder {
+    /// Crea il file e ne scrive l'intestazione
+    pub fn create(path: &Path, header: &CastHeader, record_input: bool) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!(
+                "Failed to create recordings directory: {}",
+                parent.display()
+            ))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording: {}", path.display()))?;
+        let mut writer = LineWriter::new(file);
+        serde_json::to_writer(&mut writer, header)?;
+        writer.write_all(b"\n")?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            started: Instant::now(),
+            record_input,
+            output: Utf8Decoder::new(),
+            input: Utf8Decoder::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn output(&mut self, data: &[u8]) -> Result<()> {
+        let text = self.output.decode(data);
+        self.event("o", &text)
+    }
+
+    /// Registra l'input solo se richiesto all'avvio
+    pub fn input(&mut self, data: &[u8]) -> Result<()> {
+        if !self.record_input {
+            return Ok(());
+        }
+        let text = self.input.decode(data);
+        self.event("i", &text)
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+
+    /// Scrive quanto trattenuto dai decoder e chiude il file
+    pub fn finish(mut self) -> Result<PathBuf> {
+        let output = self.output.flush();
+        self.event("o", &output)?;
+        let input = self.input.flush();
+        self.event("i", &input)?;
+        self.writer.flush()?;
+        Ok(self.path)
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        // Risoluzione al microsecondo, come asciinema
+        let time = (self.started.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        serde_json::to_writer(&mut self.writer, &(time, code, data))?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Passa un evento al registratore della sessione, se attivo. Un errore di
+/// scrittura interrompe la registrazione senza toccare la sessione.
+pub fn record(slot: &Mutex<Option<Recorder>>, session_id: &str, f: impl FnOnce(&mut Recorder) -> Result<()>) {
+    let mut slot = slot.lock().unwrap();
+    if let Some(recorder) = slot.as_mut() {
+        if let Err(e) = f(recorder) {
+            warn!(
+                "Recording of session {} stopped ({}): {}",
+                session_id,
+                recorder.path().display(),
+                e
+            );
+            *slot = None;
+        }
+    }
+}
+
+/// Percorso predefinito di una nuova registrazione
+pub fn default_recording_path(session_id: &str) -> PathBuf {
+    let base = dirs::data_dir()
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    let started = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    base.join("TermInA")
+        .join("recordings")
+        .join(format!("{}-{}.cast", started, session_id))
+}
+
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_parse() {
+        let path = std::env::temp_dir().join(format!("termina-cast-{}.cast", std::process::id()));
+        let header = CastHeader {
+            version: 2,
+            width: 80,
+            height: 24,
+            timestamp: Some(0),
+            idle_time_limit: None,
+            title: None,
+            env: None,
+        };
+        let mut recorder = Recorder::create(&path, &header, false).unwrap();
+        let euro = "€".as_bytes();
+        recorder.output(b"$ ls\r\n").unwrap();
+        // Il carattere spezzato viene scritto intero nell'evento successivo
+        recorder.output(&euro[..1]).unwrap();
+        recorder.output(&euro[1..]).unwrap();
+        recorder.input(b"ls\r").unwrap();
+        recorder.resize(100, 30).unwrap();
+        recorder.finish().unwrap();
+
+        let recording = Recording::load(&path).unwrap();
+        assert_eq!(recording.header.width, 80);
+        let codes: Vec<&str> = recording.events.iter().map(|event| event.code.as_str()).collect();
+        assert_eq!(codes, vec!["o", "o", "r"]);
+        assert_eq!(recording.events[1].data, "€");
+        assert_eq!(recording.events[2].size(), Some((100, 30)));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
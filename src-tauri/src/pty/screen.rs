@@ -0,0 +1,1176 @@
+//! Modello dello schermo lato Rust
+//!
+//! Emulatore VT/ANSI minimale alimentato con lo stesso output che arriva al
+//! frontend. Tiene traccia della griglia di celle, del cursore, degli
+//! attributi SGR, dello schermo alternativo e della regione di scroll, così
+//! il backend può sapere cosa è visualizzato senza rigiocare le sequenze di
+//! escape (contesto AI, ricerca, esportazione, finestre che si ricollegano).
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, Parser, Perform};
+
+/// Distanza predefinita tra le tabulazioni
+const TAB_WIDTH: usize = 8;
+
+/// Colore di una cella
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Attributi grafici (SGR) di una cella
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellAttributes {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    ch: char,
+    /// 0 per la seconda metà di un carattere largo
+    width: u8,
+    attrs: CellAttributes,
+}
+
+impl Cell {
+    fn blank(attrs: CellAttributes) -> Self {
+        // Le celle cancellate mantengono solo lo sfondo corrente
+        Self {
+            ch: ' ',
+            width: 1,
+            attrs: CellAttributes {
+                bg: attrs.bg,
+                ..CellAttributes::default()
+            },
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(CellAttributes::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Row {
+    cells: Vec<Cell>,
+    /// La riga prosegue nella successiva per l'a capo automatico
+    wrapped: bool,
+}
+
+impl Row {
+    fn new(cols: usize, attrs: CellAttributes) -> Self {
+        Self {
+            cells: vec![Cell::blank(attrs); cols],
+            wrapped: false,
+        }
+    }
+
+    fn resize(&mut self, cols: usize) {
+        self.cells.resize(cols, Cell::default());
+    }
+
+    fn text(&self) -> String {
+        let text: String = self
+            .cells
+            .iter()
+            .filter(|cell| cell.width > 0)
+            .map(|cell| cell.ch)
+            .collect();
+        text.trim_end().to_string()
+    }
+
+    fn spans(&self) -> Vec<StyleSpan> {
+        let mut spans: Vec<StyleSpan> = Vec::new();
+        for (col, cell) in self.cells.iter().enumerate() {
+            if cell.attrs == CellAttributes::default() {
+                continue;
+            }
+            match spans.last_mut() {
+                Some(span) if span.start + span.len == col && span.attributes == cell.attrs => {
+                    span.len += 1;
+                }
+                _ => spans.push(StyleSpan {
+                    start: col,
+                    len: 1,
+                    attributes: cell.attrs,
+                }),
+            }
+        }
+        spans
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    attrs: CellAttributes,
+    origin_mode: bool,
+}
+
+/// Eventi rilevati durante l'interpretazione dell'output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScreenEvent {
+    Bell,
+    TitleChanged(String),
+    /// Sequenza OSC non gestita dallo schermo, con i parametri grezzi
+    Osc(Vec<Vec<u8>>),
+}
+
+/// Evento con la sua posizione nello stream della sessione
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionedEvent {
+    /// Offset del primo byte della sequenza che ha generato l'evento
+    pub start: u64,
+    /// Offset successivo alla sequenza
+    pub end: u64,
+    pub event: ScreenEvent,
+}
+
+/// Posizione del cursore nello snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorState {
+    pub row: usize,
+    pub col: usize,
+    pub visible: bool,
+}
+
+/// Tratto di riga con attributi non predefiniti
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleSpan {
+    pub start: usize,
+    pub len: usize,
+    pub attributes: CellAttributes,
+}
+
+/// Riga renderizzata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenLine {
+    pub text: String,
+    pub wrapped: bool,
+    pub spans: Vec<StyleSpan>,
+}
+
+/// Istantanea dello schermo di una sessione
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenSnapshot {
+    pub cols: usize,
+    pub rows: usize,
+    pub cursor: CursorState,
+    pub alternate_screen: bool,
+    pub title: String,
+    pub scroll_region: (usize, usize),
+    /// Righe di storico incluse all'inizio di `lines`
+    pub history_lines: usize,
+    pub lines: Vec<ScreenLine>,
+}
+
+impl ScreenSnapshot {
+    /// Testo semplice dello snapshot, una riga per linea
+    pub fn plain_text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_string()
+    }
+}
+
+/// Stato dello schermo manipolato dal parser
+struct ScreenState {
+    cols: usize,
+    rows: usize,
+    primary: Vec<Row>,
+    alternate: Vec<Row>,
+    alternate_active: bool,
+    history: VecDeque<Row>,
+    history_limit: usize,
+    cursor_row: usize,
+    cursor_col: usize,
+    attrs: CellAttributes,
+    wrap_pending: bool,
+    saved_primary: Option<SavedCursor>,
+    saved_alternate: Option<SavedCursor>,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    autowrap: bool,
+    cursor_visible: bool,
+    origin_mode: bool,
+    insert_mode: bool,
+    title: String,
+    /// Eventi in attesa, con la lunghezza della sequenza che li ha generati
+    events: Vec<(usize, ScreenEvent)>,
+    /// Offset assoluto del prossimo byte da interpretare
+    offset: u64,
+    /// Righe uscite dalla cima dello schermo principale dall'inizio
+    lines_scrolled: u64,
+}
+
+/// Emulatore di terminale per una sessione
+pub struct TerminalScreen {
+    parser: Parser,
+    state: ScreenState,
+}
+
+impl TerminalScreen {
+    /// Crea uno schermo vuoto; `history_limit` righe uscite dall'alto restano
+    /// disponibili come storico
+    pub fn new(cols: u16, rows: u16, history_limit: usize) -> Self {
+        let cols = (cols as usize).max(1);
+        let rows = (rows as usize).max(1);
+        Self {
+            parser: Parser::new(),
+            state: ScreenState {
+                cols,
+                rows,
+                primary: ScreenState::blank_grid(cols, rows),
+                alternate: ScreenState::blank_grid(cols, rows),
+                alternate_active: false,
+                history: VecDeque::new(),
+                history_limit,
+                cursor_row: 0,
+                cursor_col: 0,
+                attrs: CellAttributes::default(),
+                wrap_pending: false,
+                saved_primary: None,
+                saved_alternate: None,
+                scroll_top: 0,
+                scroll_bottom: rows - 1,
+                autowrap: true,
+                cursor_visible: true,
+                origin_mode: false,
+                insert_mode: false,
+                title: String::new(),
+                events: Vec::new(),
+                offset: 0,
+                lines_scrolled: 0,
+            },
+        }
+    }
+
+    /// Interpreta un blocco di output; i caratteri UTF-8 spezzati vengono
+    /// completati al blocco successivo
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.process_with(bytes, |_, _| {});
+    }
+
+    /// Interpreta un blocco di output invocando `on_event` per ogni evento,
+    /// con lo schermo nello stato in cui si trovava subito dopo la sequenza
+    pub fn process_with<F>(&mut self, mut bytes: &[u8], mut on_event: F)
+    where
+        F: FnMut(&TerminalScreen, &PositionedEvent),
+    {
+        while !bytes.is_empty() {
+            // Il parser si ferma dopo ogni evento, così la sua posizione nello
+            // stream è esatta anche se arriva a metà di un blocco
+            let consumed = self.parser.advance_until_terminated(&mut self.state, bytes);
+            self.state.offset += consumed as u64;
+            bytes = &bytes[consumed..];
+
+            let end = self.state.offset;
+            for (len, event) in std::mem::take(&mut self.state.events) {
+                let positioned = PositionedEvent {
+                    start: end.saturating_sub(len as u64),
+                    end,
+                    event,
+                };
+                on_event(self, &positioned);
+            }
+        }
+    }
+
+    /// Offset assoluto del prossimo byte da interpretare
+    pub fn offset(&self) -> u64 {
+        self.state.offset
+    }
+
+    /// Posizione del cursore come (riga assoluta, colonna): la riga conta
+    /// anche quelle già scorse nello storico, quindi resta valida dopo lo scroll
+    pub fn cursor_position(&self) -> (u64, usize) {
+        (
+            self.state.lines_scrolled + self.state.cursor_row as u64,
+            self.state.cursor_col,
+        )
+    }
+
+    /// Testo visualizzato tra due posizioni ottenute da `cursor_position`
+    /// (fine esclusa); le righe spezzate dall'a capo automatico vengono unite
+    pub fn text_between(&self, start: (u64, usize), end: (u64, usize)) -> String {
+        let state = &self.state;
+        let mut text = String::new();
+        for line in start.0..=end.0 {
+            let Some(row) = state.row_at(line) else {
+                continue;
+            };
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 { end.1 } else { row.cells.len() };
+            let segment: String = row
+                .cells
+                .iter()
+                .take(to)
+                .skip(from)
+                .filter(|cell| cell.width > 0)
+                .map(|cell| cell.ch)
+                .collect();
+            if line != end.0 && row.wrapped {
+                text.push_str(&segment);
+            } else {
+                text.push_str(segment.trim_end());
+                if line != end.0 {
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+
+    /// Ridimensiona lo schermo (senza riflusso delle righe)
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.state.resize((cols as usize).max(1), (rows as usize).max(1));
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.state.cols, self.state.rows)
+    }
+
+    pub fn title(&self) -> &str {
+        &self.state.title
+    }
+
+    /// Imposta il titolo senza elaborare byte, così l'offset resta allineato
+    /// allo scrollback (es. titolo di una sessione ripristinata)
+    pub fn set_title(&mut self, title: &str) {
+        self.state.title = title.to_string();
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.state.alternate_active
+    }
+
+    /// Istantanea dello schermo, opzionalmente preceduta dallo storico
+    pub fn snapshot(&self, include_history: bool) -> ScreenSnapshot {
+        let state = &self.state;
+        let to_line = |row: &Row| ScreenLine {
+            text: row.text(),
+            wrapped: row.wrapped,
+            spans: row.spans(),
+        };
+
+        let history_lines = if include_history && !state.alternate_active {
+            state.history.len()
+        } else {
+            0
+        };
+        let mut lines = Vec::with_capacity(history_lines + state.rows);
+        if history_lines > 0 {
+            lines.extend(state.history.iter().map(to_line));
+        }
+        lines.extend(state.grid().iter().map(to_line));
+
+        ScreenSnapshot {
+            cols: state.cols,
+            rows: state.rows,
+            cursor: CursorState {
+                row: state.cursor_row,
+                col: state.cursor_col,
+                visible: state.cursor_visible,
+            },
+            alternate_screen: state.alternate_active,
+            title: state.title.clone(),
+            scroll_region: (state.scroll_top, state.scroll_bottom),
+            history_lines,
+            lines,
+        }
+    }
+}
+
+impl ScreenState {
+    fn blank_grid(cols: usize, rows: usize) -> Vec<Row> {
+        (0..rows)
+            .map(|_| Row::new(cols, CellAttributes::default()))
+            .collect()
+    }
+
+    fn grid(&self) -> &Vec<Row> {
+        if self.alternate_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    fn grid_mut(&mut self) -> &mut Vec<Row> {
+        if self.alternate_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        // Se il cursore finirebbe fuori dallo schermo le righe in alto
+        // scorrono nello storico, come fa xterm. Con lo schermo alternativo
+        // attivo il cursore è il suo: quello principale viene solo troncato
+        if self.cursor_row >= rows {
+            let overflow = self.cursor_row + 1 - rows;
+            for _ in 0..overflow {
+                if self.alternate_active {
+                    self.alternate.remove(0);
+                } else {
+                    let row = self.primary.remove(0);
+                    self.push_history(row);
+                }
+            }
+            self.cursor_row -= overflow;
+        }
+
+        for grid in [&mut self.primary, &mut self.alternate] {
+            grid.truncate(rows);
+            while grid.len() < rows {
+                grid.push(Row::new(cols, CellAttributes::default()));
+            }
+            for row in grid.iter_mut() {
+                row.resize(cols);
+            }
+        }
+        for row in self.history.iter_mut() {
+            row.resize(cols);
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor_row = self.cursor_row.min(rows - 1);
+        self.cursor_col = self.cursor_col.min(cols - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Riga identificata dal numero assoluto usato da `cursor_position`
+    fn row_at(&self, line: u64) -> Option<&Row> {
+        if self.alternate_active {
+            return None;
+        }
+        let first = self.lines_scrolled.checked_sub(self.history.len() as u64)?;
+        let index = line.checked_sub(first)? as usize;
+        if index < self.history.len() {
+            self.history.get(index)
+        } else {
+            self.primary.get(index - self.history.len())
+        }
+    }
+
+    fn push_history(&mut self, row: Row) {
+        self.lines_scrolled += 1;
+        if self.history_limit == 0 {
+            return;
+        }
+        if self.history.len() >= self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(row);
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        // Solo le righe che escono dalla cima dello schermo principale
+        // finiscono nello storico
+        let to_history = self.scroll_top == 0 && !self.alternate_active;
+        self.scroll_region_up(self.scroll_top, self.scroll_bottom, count, to_history);
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        self.scroll_region_down(self.scroll_top, self.scroll_bottom, count);
+    }
+
+    fn scroll_region_up(&mut self, top: usize, bottom: usize, count: usize, to_history: bool) {
+        let count = count.min(bottom - top + 1);
+        let (cols, attrs) = (self.cols, self.attrs);
+        for _ in 0..count {
+            let row = self.grid_mut().remove(top);
+            self.grid_mut().insert(bottom, Row::new(cols, attrs));
+            if to_history {
+                self.push_history(row);
+            }
+        }
+    }
+
+    fn scroll_region_down(&mut self, top: usize, bottom: usize, count: usize) {
+        let count = count.min(bottom - top + 1);
+        let (cols, attrs) = (self.cols, self.attrs);
+        for _ in 0..count {
+            self.grid_mut().remove(bottom);
+            self.grid_mut().insert(top, Row::new(cols, attrs));
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor_row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor_row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor_row > 0 {
+            self.cursor_row -= 1;
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        let (min_row, max_row) = if self.origin_mode {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        let row = if self.origin_mode { row + self.scroll_top } else { row };
+        self.cursor_row = row.clamp(min_row, max_row);
+        self.cursor_col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn put_char(&mut self, c: char) {
+        let width = match c.width() {
+            Some(width) if width > 0 => width,
+            // Combinanti e caratteri di controllo non occupano celle
+            _ => return,
+        };
+
+        if self.wrap_pending && self.autowrap {
+            let row = self.cursor_row;
+            self.grid_mut()[row].wrapped = true;
+            self.carriage_return();
+            self.linefeed();
+        }
+        if width == 2 && self.cursor_col + 1 >= self.cols {
+            if !self.autowrap || self.cols < 2 {
+                return;
+            }
+            let row = self.cursor_row;
+            self.grid_mut()[row].wrapped = true;
+            self.carriage_return();
+            self.linefeed();
+        }
+
+        let (row, col, cols, attrs) = (self.cursor_row, self.cursor_col, self.cols, self.attrs);
+        let insert_mode = self.insert_mode;
+        let cells = &mut self.grid_mut()[row].cells;
+        if insert_mode {
+            for _ in 0..width {
+                cells.insert(col, Cell::blank(attrs));
+            }
+            cells.truncate(cols);
+        }
+        cells[col] = Cell {
+            ch: c,
+            width: width as u8,
+            attrs,
+        };
+        if width == 2 {
+            cells[col + 1] = Cell {
+                ch: ' ',
+                width: 0,
+                attrs,
+            };
+        }
+
+        if col + width >= cols {
+            self.cursor_col = cols - 1;
+            self.wrap_pending = self.autowrap;
+        } else {
+            self.cursor_col = col + width;
+        }
+    }
+
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+        let attrs = self.attrs;
+        let cells = &mut self.grid_mut()[row].cells;
+        let to = to.min(cells.len());
+        for cell in cells.iter_mut().take(to).skip(from) {
+            *cell = Cell::blank(attrs);
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let (row, col, rows, cols) = (self.cursor_row, self.cursor_col, self.rows, self.cols);
+        match mode {
+            0 => {
+                self.erase_cells(row, col, cols);
+                for r in row + 1..rows {
+                    self.erase_cells(r, 0, cols);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    self.erase_cells(r, 0, cols);
+                }
+                self.erase_cells(row, 0, col + 1);
+            }
+            2 => {
+                for r in 0..rows {
+                    self.erase_cells(r, 0, cols);
+                    self.grid_mut()[r].wrapped = false;
+                }
+            }
+            3 => self.history.clear(),
+            _ => {}
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let (row, col, cols) = (self.cursor_row, self.cursor_col, self.cols);
+        match mode {
+            0 => self.erase_cells(row, col, cols),
+            1 => self.erase_cells(row, 0, col + 1),
+            2 => self.erase_cells(row, 0, cols),
+            _ => {}
+        }
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor_row < self.scroll_top || self.cursor_row > self.scroll_bottom {
+            return;
+        }
+        self.scroll_region_down(self.cursor_row, self.scroll_bottom, count);
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor_row < self.scroll_top || self.cursor_row > self.scroll_bottom {
+            return;
+        }
+        self.scroll_region_up(self.cursor_row, self.scroll_bottom, count, false);
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn insert_chars(&mut self, count: usize) {
+        let (row, col, cols, attrs) = (self.cursor_row, self.cursor_col, self.cols, self.attrs);
+        let cells = &mut self.grid_mut()[row].cells;
+        for _ in 0..count.min(cols - col) {
+            cells.insert(col, Cell::blank(attrs));
+        }
+        cells.truncate(cols);
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let (row, col, cols, attrs) = (self.cursor_row, self.cursor_col, self.cols, self.attrs);
+        let cells = &mut self.grid_mut()[row].cells;
+        let count = count.min(cols - col);
+        cells.drain(col..col + count);
+        cells.resize(cols, Cell::blank(attrs));
+    }
+
+    fn save_cursor(&mut self) {
+        let saved = SavedCursor {
+            row: self.cursor_row,
+            col: self.cursor_col,
+            attrs: self.attrs,
+            origin_mode: self.origin_mode,
+        };
+        if self.alternate_active {
+            self.saved_alternate = Some(saved);
+        } else {
+            self.saved_primary = Some(saved);
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = if self.alternate_active {
+            self.saved_alternate
+        } else {
+            self.saved_primary
+        }
+        .unwrap_or_default();
+        self.cursor_row = saved.row.min(self.rows - 1);
+        self.cursor_col = saved.col.min(self.cols - 1);
+        self.attrs = saved.attrs;
+        self.origin_mode = saved.origin_mode;
+        self.wrap_pending = false;
+    }
+
+    fn set_alternate_screen(&mut self, enabled: bool, save_cursor: bool) {
+        if enabled == self.alternate_active {
+            return;
+        }
+        if enabled {
+            if save_cursor {
+                self.save_cursor();
+            }
+            self.alternate = Self::blank_grid(self.cols, self.rows);
+            self.alternate_active = true;
+        } else {
+            self.alternate_active = false;
+            if save_cursor {
+                self.restore_cursor();
+            }
+        }
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows - 1;
+    }
+
+    fn reset(&mut self) {
+        let (cols, rows, limit) = (self.cols, self.rows, self.history_limit);
+        let mut fresh = TerminalScreen::new(cols as u16, rows as u16, limit).state;
+        fresh.history = std::mem::take(&mut self.history);
+        fresh.events = std::mem::take(&mut self.events);
+        fresh.offset = self.offset;
+        fresh.lines_scrolled = self.lines_scrolled;
+        *self = fresh;
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            6 => {
+                self.origin_mode = enabled;
+                self.move_to(0, 0);
+            }
+            7 => self.autowrap = enabled,
+            25 => self.cursor_visible = enabled,
+            47 | 1047 => self.set_alternate_screen(enabled, false),
+            1048 => {
+                if enabled {
+                    self.save_cursor();
+                } else {
+                    self.restore_cursor();
+                }
+            }
+            1049 => self.set_alternate_screen(enabled, true),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let groups: Vec<&[u16]> = params.iter().collect();
+        if groups.is_empty() {
+            self.attrs = CellAttributes::default();
+            return;
+        }
+
+        let mut index = 0;
+        while index < groups.len() {
+            let group = groups[index];
+            index += 1;
+            match group[0] {
+                0 => self.attrs = CellAttributes::default(),
+                1 => self.attrs.bold = true,
+                2 => self.attrs.dim = true,
+                3 => self.attrs.italic = true,
+                4 => self.attrs.underline = group.get(1).map_or(true, |&style| style != 0),
+                5 | 6 => self.attrs.blink = true,
+                7 => self.attrs.inverse = true,
+                8 => self.attrs.hidden = true,
+                9 => self.attrs.strikethrough = true,
+                21 => self.attrs.underline = true,
+                22 => {
+                    self.attrs.bold = false;
+                    self.attrs.dim = false;
+                }
+                23 => self.attrs.italic = false,
+                24 => self.attrs.underline = false,
+                25 => self.attrs.blink = false,
+                27 => self.attrs.inverse = false,
+                28 => self.attrs.hidden = false,
+                29 => self.attrs.strikethrough = false,
+                code @ 30..=37 => self.attrs.fg = Color::Indexed((code - 30) as u8),
+                38 => {
+                    if let Some(color) = Self::extended_color(group, &groups, &mut index) {
+                        self.attrs.fg = color;
+                    }
+                }
+                39 => self.attrs.fg = Color::Default,
+                code @ 40..=47 => self.attrs.bg = Color::Indexed((code - 40) as u8),
+                48 => {
+                    if let Some(color) = Self::extended_color(group, &groups, &mut index) {
+                        self.attrs.bg = color;
+                    }
+                }
+                49 => self.attrs.bg = Color::Default,
+                code @ 90..=97 => self.attrs.fg = Color::Indexed((code - 90 + 8) as u8),
+                code @ 100..=107 => self.attrs.bg = Color::Indexed((code - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+
+    /// Interpreta `38;5;n`, `38;2;r;g;b` e le varianti con `:`
+    fn extended_color(group: &[u16], groups: &[&[u16]], index: &mut usize) -> Option<Color> {
+        let values: Vec<u16> = if group.len() > 1 {
+            // Forma con sottoparametri: 38:2::r:g:b oppure 38:2:r:g:b
+            let mut values = group[1..].to_vec();
+            if values.first() == Some(&2) && values.len() == 5 {
+                values.remove(1);
+            }
+            values
+        } else {
+            let kind = groups.get(*index).map(|g| g[0])?;
+            let needed = if kind == 5 { 2 } else { 4 };
+            let values: Vec<u16> = groups
+                .iter()
+                .skip(*index)
+                .take(needed)
+                .map(|g| g[0])
+                .collect();
+            *index += values.len();
+            values
+        };
+
+        match values.as_slice() {
+            [5, n, ..] => Some(Color::Indexed(*n as u8)),
+            [2, r, g, b, ..] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+            _ => None,
+        }
+    }
+}
+
+fn param(params: &Params, index: usize, default: u16) -> u16 {
+    params
+        .iter()
+        .nth(index)
+        .and_then(|group| group.first().copied())
+        .filter(|&value| value != 0)
+        .unwrap_or(default)
+}
+
+impl Perform for ScreenState {
+    fn terminated(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    fn print(&mut self, c: char) {
+        self.put_char(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x07 => self.events.push((1, ScreenEvent::Bell)),
+            0x08 => {
+                self.cursor_col = self.cursor_col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            0x09 => {
+                let next = (self.cursor_col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor_col = next.min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            0x0A..=0x0C => self.linefeed(),
+            0x0D => self.carriage_return(),
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        // ESC ] + parametri separati da ';' + terminatore (BEL o ESC di ST)
+        let len = 3 + params.iter().map(|p| p.len()).sum::<usize>() + params.len().saturating_sub(1);
+        match params.first() {
+            Some(&b"0") | Some(&b"2") => {
+                let title = params
+                    .get(1)
+                    .map(|title| String::from_utf8_lossy(title).into_owned())
+                    .unwrap_or_default();
+                self.title = title.clone();
+                self.events.push((len, ScreenEvent::TitleChanged(title)));
+            }
+            Some(_) => self.events.push((
+                len,
+                ScreenEvent::Osc(params.iter().map(|p| p.to_vec()).collect()),
+            )),
+            None => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+
+        if intermediates == [b'?'] {
+            let enabled = match action {
+                'h' => true,
+                'l' => false,
+                _ => return,
+            };
+            for group in params.iter() {
+                self.set_private_mode(group[0], enabled);
+            }
+            return;
+        }
+        if !intermediates.is_empty() {
+            return;
+        }
+
+        let n = param(params, 0, 1) as usize;
+        match action {
+            'A' => self.move_to_clamped_row(self.cursor_row.saturating_sub(n)),
+            'B' | 'e' => self.move_to_clamped_row(self.cursor_row + n),
+            'C' | 'a' => {
+                self.cursor_col = (self.cursor_col + n).min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            'D' => {
+                self.cursor_col = self.cursor_col.saturating_sub(n);
+                self.wrap_pending = false;
+            }
+            'E' => {
+                self.move_to_clamped_row(self.cursor_row + n);
+                self.cursor_col = 0;
+            }
+            'F' => {
+                self.move_to_clamped_row(self.cursor_row.saturating_sub(n));
+                self.cursor_col = 0;
+            }
+            'G' | '`' => {
+                self.cursor_col = (n - 1).min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            'H' | 'f' => {
+                let row = param(params, 0, 1) as usize - 1;
+                let col = param(params, 1, 1) as usize - 1;
+                self.move_to(row, col);
+            }
+            'd' => {
+                let col = self.cursor_col;
+                self.move_to(n - 1, col);
+            }
+            'J' => self.erase_in_display(param(params, 0, 0)),
+            'K' => self.erase_in_line(param(params, 0, 0)),
+            'L' => self.insert_lines(n),
+            'M' => self.delete_lines(n),
+            '@' => self.insert_chars(n),
+            'P' => self.delete_chars(n),
+            'X' => {
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                self.erase_cells(row, col, col + n);
+            }
+            'S' => self.scroll_up(n),
+            'T' => self.scroll_down(n),
+            'm' => self.select_graphic_rendition(params),
+            'r' => {
+                let top = param(params, 0, 1) as usize - 1;
+                let bottom = (param(params, 1, self.rows as u16) as usize).min(self.rows) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            'h' | 'l' if param(params, 0, 0) == 4 => self.insert_mode = action == 'h',
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore || !intermediates.is_empty() {
+            return;
+        }
+        match byte {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+}
+
+impl ScreenState {
+    /// Spostamento verticale relativo, limitato alla regione di scroll se il
+    /// cursore vi si trova già dentro
+    fn move_to_clamped_row(&mut self, row: usize) {
+        let (top, bottom) = if self.cursor_row >= self.scroll_top && self.cursor_row <= self.scroll_bottom {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.cursor_row = row.clamp(top, bottom);
+        self.wrap_pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen_with(input: &str) -> TerminalScreen {
+        let mut screen = TerminalScreen::new(20, 5, 100);
+        screen.process(input.as_bytes());
+        screen
+    }
+
+    fn lines(screen: &TerminalScreen) -> Vec<String> {
+        screen
+            .snapshot(false)
+            .lines
+            .into_iter()
+            .map(|line| line.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_text_and_cursor() {
+        let screen = screen_with("hello\r\nworld");
+        let snapshot = screen.snapshot(false);
+        assert_eq!(snapshot.lines[0].text, "hello");
+        assert_eq!(snapshot.lines[1].text, "world");
+        assert_eq!((snapshot.cursor.row, snapshot.cursor.col), (1, 5));
+    }
+
+    #[test]
+    fn test_cursor_movement_and_erase() {
+        let screen = screen_with("abcdef\x1b[1;3H\x1b[K");
+        assert_eq!(lines(&screen)[0], "ab");
+
+        let screen = screen_with("line1\r\nline2\x1b[2J\x1b[Hnew");
+        assert_eq!(lines(&screen)[0], "new");
+        assert_eq!(lines(&screen)[1], "");
+    }
+
+    #[test]
+    fn test_autowrap_and_scroll_into_history() {
+        let mut screen = TerminalScreen::new(4, 2, 10);
+        screen.process(b"abcdefgh\r\nij");
+        let snapshot = screen.snapshot(true);
+        assert_eq!(snapshot.history_lines, 1);
+        let texts: Vec<_> = snapshot.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["abcd", "efgh", "ij"]);
+        assert!(snapshot.lines[0].wrapped);
+    }
+
+    #[test]
+    fn test_sgr_attributes() {
+        let screen = screen_with("\x1b[1;31mred\x1b[0m \x1b[38;2;1;2;3mrgb\x1b[48;5;200mx");
+        let snapshot = screen.snapshot(false);
+        let spans = &snapshot.lines[0].spans;
+        assert_eq!(spans[0].start, 0);
+        assert_eq!(spans[0].len, 3);
+        assert!(spans[0].attributes.bold);
+        assert_eq!(spans[0].attributes.fg, Color::Indexed(1));
+        assert_eq!(spans[1].attributes.fg, Color::Rgb(1, 2, 3));
+        assert_eq!(spans[2].attributes.bg, Color::Indexed(200));
+    }
+
+    #[test]
+    fn test_alternate_screen_restores_primary() {
+        let mut screen = screen_with("shell$ ");
+        screen.process(b"\x1b[?1049h\x1b[Hvim buffer");
+        assert!(screen.is_alternate_screen());
+        assert_eq!(lines(&screen)[0], "vim buffer");
+
+        screen.process(b"\x1b[?1049l");
+        assert!(!screen.is_alternate_screen());
+        assert_eq!(lines(&screen)[0], "shell$");
+        assert_eq!(screen.snapshot(false).cursor.col, 7);
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut screen = TerminalScreen::new(10, 4, 10);
+        screen.process(b"top\r\na\r\nb\r\nbottom");
+        // Regione sulle righe 2-3: lo scroll non tocca la prima e l'ultima
+        screen.process(b"\x1b[2;3r\x1b[3;1H\n");
+        assert_eq!(lines(&screen), vec!["top", "b", "", "bottom"]);
+        assert_eq!(screen.snapshot(true).history_lines, 0);
+    }
+
+    #[test]
+    fn test_wide_characters_and_title() {
+        let screen = screen_with("\x1b]0;my title\x07日本");
+        assert_eq!(lines(&screen)[0], "日本");
+        assert_eq!(screen.snapshot(false).cursor.col, 4);
+        assert_eq!(screen.title(), "my title");
+    }
+
+    #[test]
+    fn test_set_title_keeps_offset() {
+        let mut screen = screen_with("abc");
+        screen.set_title("restored");
+        assert_eq!(screen.title(), "restored");
+        assert_eq!(screen.offset(), 3);
+        assert_eq!(screen.snapshot(false).title, "restored");
+    }
+
+    #[test]
+    fn test_events_carry_stream_offsets() {
+        let mut screen = TerminalScreen::new(20, 5, 10);
+        let mut events = Vec::new();
+        screen.process_with(b"ab\x1b]133;A\x07cd\x1b]133;B\x1b\\ef", |screen, event| {
+            events.push((event.clone(), screen.cursor_position()));
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].0.start, events[0].0.end), (2, 10));
+        assert_eq!(events[0].1, (0, 2));
+        assert_eq!((events[1].0.start, events[1].0.end), (12, 20));
+        assert_eq!(events[1].1, (0, 4));
+        assert_eq!(screen.offset(), 23);
+    }
+
+    #[test]
+    fn test_text_between_follows_scrolled_lines() {
+        let mut screen = TerminalScreen::new(5, 2, 10);
+        screen.process(b"$ ");
+        let start = screen.cursor_position();
+        screen.process(b"echo hi\r\nhi\r\n");
+        let end = (start.0, 5);
+        assert_eq!(screen.text_between(start, end), "ech");
+        assert_eq!(screen.text_between(start, (start.0 + 1, 4)), "echo hi");
+    }
+
+    #[test]
+    fn test_resize_keeps_cursor_visible() {
+        let mut screen = screen_with("1\r\n2\r\n3\r\n4\r\n5");
+        screen.resize(20, 3);
+        assert_eq!(lines(&screen), vec!["3", "4", "5"]);
+        assert_eq!(screen.snapshot(false).cursor.row, 2);
+    }
+
+    #[test]
+    fn test_resize_in_alternate_screen_keeps_history() {
+        let mut screen = screen_with("shell$ ");
+        screen.process(b"\x1b[?1049h\x1b[Htop\x1b[5;1Hbottom");
+        screen.resize(20, 3);
+        assert_eq!(lines(&screen)[2], "bottom");
+
+        screen.process(b"\x1b[?1049l");
+        let snapshot = screen.snapshot(true);
+        assert_eq!(snapshot.history_lines, 0);
+        assert_eq!(lines(&screen)[0], "shell$");
+    }
+}
@@ -0,0 +1,228 @@
+//! Buffer di scrollback limitato
+//!
+//! L'output di una sessione viene conservato in segmenti di dimensione fissa;
+//! quando si supera il limite di righe (o di byte) i segmenti più vecchi
+//! vengono scartati. Gli offset sono assoluti rispetto all'inizio dello
+//! stream, quindi restano validi anche dopo lo scarto e un lettore
+//! incrementale può accorgersi dei dati persi.
+
+use std::collections::VecDeque;
+
+/// Righe di scrollback predefinite (come `terminal.scrollback` nella config)
+pub const DEFAULT_SCROLLBACK_LINES: usize = 4000;
+/// Stima dei byte massimi per riga, usata per il limite in byte
+const MAX_BYTES_PER_LINE: usize = 1024;
+/// Limite minimo in byte, per non scartare output con scrollback molto piccoli
+const MIN_CAPACITY_BYTES: usize = 64 * 1024;
+/// Dimensione di un singolo segmento
+const SEGMENT_SIZE: usize = 16 * 1024;
+
+struct Segment {
+    data: Vec<u8>,
+    newlines: usize,
+}
+
+/// Risultato di una lettura dallo scrollback
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrollbackRead {
+    pub data: Vec<u8>,
+    /// Offset assoluto del primo byte restituito
+    pub start: u64,
+    /// Offset assoluto successivo all'ultimo byte restituito
+    pub end: u64,
+    /// Byte richiesti ma già scartati dallo scrollback
+    pub dropped: u64,
+}
+
+/// Buffer circolare a segmenti con offset stabili
+pub struct ScrollbackBuffer {
+    segments: VecDeque<Segment>,
+    start_offset: u64,
+    end_offset: u64,
+    line_count: usize,
+    max_lines: usize,
+    max_bytes: usize,
+}
+
+impl ScrollbackBuffer {
+    /// Crea un buffer che conserva circa `max_lines` righe
+    pub fn new(max_lines: usize) -> Self {
+        let max_lines = max_lines.max(1);
+        Self {
+            segments: VecDeque::new(),
+            start_offset: 0,
+            end_offset: 0,
+            line_count: 0,
+            max_lines,
+            max_bytes: max_lines
+                .saturating_mul(MAX_BYTES_PER_LINE)
+                .max(MIN_CAPACITY_BYTES),
+        }
+    }
+
+    /// Aggiunge dati in coda e restituisce l'offset del primo byte aggiunto
+    pub fn append(&mut self, mut data: &[u8]) -> u64 {
+        let offset = self.end_offset;
+
+        while !data.is_empty() {
+            let needs_segment = self
+                .segments
+                .back()
+                .map_or(true, |segment| segment.data.len() >= SEGMENT_SIZE);
+            if needs_segment {
+                self.segments.push_back(Segment {
+                    data: Vec::with_capacity(SEGMENT_SIZE),
+                    newlines: 0,
+                });
+            }
+
+            let segment = self.segments.back_mut().unwrap();
+            let take = (SEGMENT_SIZE - segment.data.len()).min(data.len());
+            let (head, tail) = data.split_at(take);
+            let newlines = head.iter().filter(|&&b| b == b'\n').count();
+            segment.data.extend_from_slice(head);
+            segment.newlines += newlines;
+            self.line_count += newlines;
+            self.end_offset += take as u64;
+            data = tail;
+        }
+
+        self.trim();
+        offset
+    }
+
+    /// Scarta i segmenti più vecchi finché il buffer rientra nei limiti
+    fn trim(&mut self) {
+        while self.segments.len() > 1
+            && (self.line_count > self.max_lines || self.len() > self.max_bytes)
+        {
+            if let Some(segment) = self.segments.pop_front() {
+                self.start_offset += segment.data.len() as u64;
+                self.line_count -= segment.newlines;
+            }
+        }
+    }
+
+    /// Legge tutti i dati disponibili a partire da `offset`
+    pub fn read_from(&self, offset: u64) -> ScrollbackRead {
+        let dropped = self.start_offset.saturating_sub(offset);
+        let start = offset.clamp(self.start_offset, self.end_offset);
+
+        let mut data = Vec::with_capacity((self.end_offset - start) as usize);
+        let mut segment_start = self.start_offset;
+        for segment in &self.segments {
+            let segment_end = segment_start + segment.data.len() as u64;
+            if segment_end > start {
+                let skip = start.saturating_sub(segment_start) as usize;
+                data.extend_from_slice(&segment.data[skip..]);
+            }
+            segment_start = segment_end;
+        }
+
+        ScrollbackRead {
+            data,
+            start,
+            end: self.end_offset,
+            dropped,
+        }
+    }
+
+    /// Restituisce tutto il contenuto conservato
+    pub fn contents(&self) -> Vec<u8> {
+        self.read_from(self.start_offset).data
+    }
+
+    /// Svuota il buffer mantenendo la continuità degli offset
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.start_offset = self.end_offset;
+        self.line_count = 0;
+    }
+
+    /// Byte attualmente conservati
+    pub fn len(&self) -> usize {
+        (self.end_offset - self.start_offset) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Offset del byte più vecchio ancora disponibile
+    pub fn start_offset(&self) -> u64 {
+        self.start_offset
+    }
+
+    /// Offset successivo all'ultimo byte ricevuto
+    pub fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+
+    /// Limite di righe configurato
+    pub fn max_lines(&self) -> usize {
+        self.max_lines
+    }
+}
+
+impl Default for ScrollbackBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCROLLBACK_LINES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read() {
+        let mut buffer = ScrollbackBuffer::new(100);
+        assert_eq!(buffer.append(b"hello "), 0);
+        assert_eq!(buffer.append(b"world"), 6);
+
+        let read = buffer.read_from(6);
+        assert_eq!(read.data, b"world");
+        assert_eq!(read.start, 6);
+        assert_eq!(read.end, 11);
+        assert_eq!(read.dropped, 0);
+    }
+
+    #[test]
+    fn test_old_segments_are_dropped_by_line_count() {
+        let mut buffer = ScrollbackBuffer::new(10);
+        let line = vec![b'x'; 99].into_iter().chain([b'\n']).collect::<Vec<_>>();
+        for _ in 0..1000 {
+            buffer.append(&line);
+        }
+
+        assert_eq!(buffer.end_offset(), 100_000);
+        assert!(buffer.start_offset() > 0);
+        assert!(buffer.len() <= 10 * 100 + SEGMENT_SIZE);
+
+        // Un lettore rimasto indietro vede il buco invece di dati sbagliati
+        let read = buffer.read_from(0);
+        assert_eq!(read.dropped, buffer.start_offset());
+        assert_eq!(read.start, buffer.start_offset());
+        assert_eq!(read.data.len(), buffer.len());
+    }
+
+    #[test]
+    fn test_byte_cap_without_newlines() {
+        let mut buffer = ScrollbackBuffer::new(1);
+        let chunk = vec![b'a'; 4096];
+        for _ in 0..100 {
+            buffer.append(&chunk);
+        }
+        assert!(buffer.len() <= MIN_CAPACITY_BYTES + SEGMENT_SIZE);
+    }
+
+    #[test]
+    fn test_clear_keeps_offsets() {
+        let mut buffer = ScrollbackBuffer::new(100);
+        buffer.append(b"abc");
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.append(b"d"), 3);
+        assert_eq!(buffer.read_from(3).data, b"d");
+    }
+}
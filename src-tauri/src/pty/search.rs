@@ -0,0 +1,267 @@
+//! Ricerca nello scrollback delle sessioni
+//!
+//! Lo scrollback viene ripulito dalle sequenze di escape con un parser VT
+//! dedicato, conservando per ogni carattere l'offset assoluto da cui
+//! proviene: così ogni risultato si può ricondurre al comando che lo ha
+//! prodotto e all'output grezzo della sessione.
+
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use vte::{Parser, Perform};
+
+use super::scrollback::ScrollbackRead;
+use super::shell_integration::CommandRecord;
+
+/// Numero massimo predefinito di risultati
+const DEFAULT_MAX_RESULTS: usize = 1000;
+
+/// Parametri di ricerca
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchOptions {
+    pub query: String,
+    /// `query` è un'espressione regolare invece che un testo letterale
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Righe di contesto prima e dopo ogni risultato
+    #[serde(default = "default_context_lines")]
+    pub context_lines: usize,
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+}
+
+fn default_context_lines() -> usize {
+    2
+}
+
+fn default_max_results() -> usize {
+    DEFAULT_MAX_RESULTS
+}
+
+impl SearchOptions {
+    /// Compila la ricerca in un'espressione regolare
+    pub fn compile(&self) -> Result<Regex> {
+        if self.query.is_empty() {
+            return Err(anyhow!("Search query is empty"));
+        }
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map_err(|e| anyhow!("Invalid search pattern: {}", e))
+    }
+}
+
+/// Risultato di una ricerca
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub session_id: String,
+    /// Riga del testo ripulito, contando dall'inizio dello scrollback attuale
+    pub line: usize,
+    /// Colonna in caratteri all'interno della riga
+    pub column: usize,
+    /// Lunghezza in caratteri
+    pub length: usize,
+    /// Offset assoluto del primo byte nell'output della sessione
+    pub offset: u64,
+    pub text: String,
+    pub line_text: String,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+    /// Comando che ha prodotto la riga (con l'integrazione della shell)
+    pub command: Option<CommandRecord>,
+}
+
+/// Risultati di una ricerca su una o più sessioni
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub matches: Vec<SearchMatch>,
+    /// La ricerca si è fermata a `max_results`
+    pub truncated: bool,
+    /// Sessioni che non si sono potute leggere, saltate
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_sessions: Vec<String>,
+}
+
+/// Riga di testo senza sequenze di escape
+#[derive(Debug, Default)]
+struct StrippedLine {
+    text: String,
+    /// Offset assoluto di ogni carattere, indicizzato per posizione in `text`
+    offsets: Vec<(usize, u64)>,
+}
+
+impl StrippedLine {
+    /// Offset del carattere che inizia alla posizione `index` di `text`
+    fn offset_at(&self, index: usize) -> u64 {
+        let position = self.offsets.partition_point(|(start, _)| *start < index);
+        self.offsets.get(position).map_or(0, |(_, offset)| *offset)
+    }
+}
+
+/// Raccoglie i caratteri stampabili riga per riga
+#[derive(Default)]
+struct Stripper {
+    lines: Vec<StrippedLine>,
+    current: StrippedLine,
+    /// Offset dell'ultimo byte passato al parser
+    offset: u64,
+}
+
+impl Perform for Stripper {
+    fn print(&mut self, c: char) {
+        // Il carattere viene stampato all'ultimo dei suoi byte
+        let start = self.offset + 1 - c.len_utf8() as u64;
+        self.current.offsets.push((self.current.text.len(), start));
+        self.current.text.push(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.lines.push(std::mem::take(&mut self.current)),
+            b'\t' => self.print('\t'),
+            _ => {}
+        }
+    }
+}
+
+fn strip(read: &ScrollbackRead) -> Vec<StrippedLine> {
+    let mut parser = Parser::new();
+    let mut stripper = Stripper::default();
+    for (index, byte) in read.data.iter().enumerate() {
+        stripper.offset = read.start + index as u64;
+        parser.advance(&mut stripper, &[*byte]);
+    }
+    let mut lines = stripper.lines;
+    if !stripper.current.text.is_empty() {
+        lines.push(stripper.current);
+    }
+    lines
+}
+
+/// Comando il cui prompt o output contiene l'offset
+fn owning_command(commands: &[CommandRecord], offset: u64) -> Option<&CommandRecord> {
+    commands.iter().rev().find(|command| {
+        let start = command.prompt_offset.unwrap_or(command.output_start);
+        start <= offset && !matches!(command.output_end, Some(end) if offset >= end)
+    })
+}
+
+/// Cerca nello scrollback di una sessione, aggiungendo al più `limit` risultati
+pub fn search(
+    session_id: &str,
+    read: &ScrollbackRead,
+    commands: &[CommandRecord],
+    regex: &Regex,
+    options: &SearchOptions,
+    limit: usize,
+) -> Vec<SearchMatch> {
+    let lines = strip(read);
+    let mut matches = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        for found in regex.find_iter(&line.text) {
+            if matches.len() >= limit {
+                return matches;
+            }
+            if found.is_empty() {
+                continue;
+            }
+            let offset = line.offset_at(found.start());
+            let context = |range: std::ops::Range<usize>| -> Vec<String> {
+                lines[range].iter().map(|line| line.text.clone()).collect()
+            };
+            matches.push(SearchMatch {
+                session_id: session_id.to_string(),
+                line: index,
+                column: line.text[..found.start()].chars().count(),
+                length: found.as_str().chars().count(),
+                offset,
+                text: found.as_str().to_string(),
+                line_text: line.text.clone(),
+                context_before: context(index.saturating_sub(options.context_lines)..index),
+                context_after: context(index + 1..(index + 1 + options.context_lines).min(lines.len())),
+                command: owning_command(commands, offset).cloned(),
+            });
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(query: &str) -> SearchOptions {
+        SearchOptions {
+            query: query.to_string(),
+            regex: false,
+            case_sensitive: false,
+            context_lines: 1,
+            max_results: DEFAULT_MAX_RESULTS,
+        }
+    }
+
+    fn read(data: &[u8]) -> ScrollbackRead {
+        ScrollbackRead {
+            data: data.to_vec(),
+            start: 100,
+            end: 100 + data.len() as u64,
+            dropped: 0,
+        }
+    }
+
+    #[test]
+    fn test_search_strips_escapes() {
+        let output = read(b"$ make\r\n\x1b[1;31mError\x1b[0m: caff\xc3\xa8 missing\r\ndone\r\n");
+        let regex = options("error: caffè").compile().unwrap();
+        let matches = search("s", &output, &[], &regex, &options(""), 10);
+
+        assert_eq!(matches.len(), 1);
+        let found = &matches[0];
+        assert_eq!((found.line, found.column, found.length), (1, 0, 12));
+        assert_eq!(found.text, "Error: caffè");
+        // L'offset punta alla `E`, dopo la sequenza di colore
+        assert_eq!(found.offset, 100 + 8 + 7);
+        assert_eq!(found.context_before, vec!["$ make".to_string()]);
+        assert_eq!(found.context_after, vec!["done".to_string()]);
+    }
+
+    #[test]
+    fn test_regex_case_and_owning_command() {
+        let output = read(b"one\nTWO two\n");
+        let mut regex_options = options("t[wo]+");
+        regex_options.regex = true;
+        regex_options.case_sensitive = true;
+        let regex = regex_options.compile().unwrap();
+        // Solo il prompt e l'output tra 100 e 112 appartengono al comando
+
+        let command = CommandRecord {
+            id: 1,
+            command: "echo".to_string(),
+            prompt_offset: Some(100),
+            output_start: 104,
+            output_end: Some(112),
+            exit_code: Some(0),
+            started_at: 0,
+            finished_at: None,
+        };
+        let matches = search("s", &output, &[command], &regex, &regex_options, 10);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].column, 4);
+        assert_eq!(matches[0].command.as_ref().map(|command| command.id), Some(1));
+
+        // Senza distinzione tra maiuscole e minuscole, entro il limite
+        let regex = options("two").compile().unwrap();
+        assert_eq!(search("s", &output, &[], &regex, &options("two"), 10).len(), 2);
+        assert_eq!(search("s", &output, &[], &regex, &options("two"), 1).len(), 1);
+
+        regex_options.query = "(".to_string();
+        assert!(regex_options.compile().is_err());
+    }
+}
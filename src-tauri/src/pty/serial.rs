@@ -0,0 +1,545 @@
+//! Sessioni su porta seriale
+//!
+//! Una console seriale (`/dev/ttyUSB*`, `/dev/ttyACM*`, ...) viene aperta
+//! come sessione: l'output del dispositivo passa per `TerminalStream` come
+//! quello delle sessioni SSH, l'input viene scritto sulla porta. Non c'è un
+//! processo né una dimensione della finestra da comunicare: il resize
+//! cambia solo lo schermo locale.
+//!
+//! Molti dispositivi non fanno eco di quello che ricevono e usano fine
+//! riga diversi da quelli del terminale; `local_echo` e le traduzioni
+//! `send_crlf` / `receive_crlf` coprono i casi comuni, come `picocom`.
+
+use std::any::Any;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serialport::{DataBits, FlowControl, Parity, SerialPort, SerialPortType, StopBits};
+
+use super::backend::SessionBackend;
+use super::events::SessionEventListener;
+use super::output::OutputSubscriber;
+use super::reaper::SessionActivity;
+use super::recording::unix_timestamp;
+use super::screen::ScreenSnapshot;
+use super::scrollback::{ScrollbackRead, DEFAULT_SCROLLBACK_LINES};
+use super::session::{ProcessExit, SessionStatus};
+use super::shell_integration::CommandRecord;
+use super::shutdown::ShutdownReport;
+use super::stream::TerminalStream;
+
+/// Attesa massima di una lettura, dopo la quale si controlla la chiusura
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Parametri della porta
+#[derive(Debug, Clone, Deserialize)]
+pub struct SerialOptions {
+    /// Percorso del dispositivo (es. `/dev/ttyUSB0`)
+    pub path: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    /// Bit di dati, da 5 a 8
+    #[serde(default = "default_data_bits")]
+    pub data_bits: u8,
+    #[serde(default)]
+    pub parity: SerialParity,
+    /// Bit di stop, 1 o 2
+    #[serde(default = "default_stop_bits")]
+    pub stop_bits: u8,
+    #[serde(default)]
+    pub flow_control: SerialFlowControl,
+    /// Mostra l'input inviato, per i dispositivi che non ne fanno eco
+    #[serde(default)]
+    pub local_echo: bool,
+    /// Invia Invio (`\r`) come `\r\n`
+    #[serde(default)]
+    pub send_crlf: bool,
+    /// Mostra i `\n` ricevuti senza `\r` come `\r\n`
+    #[serde(default)]
+    pub receive_crlf: bool,
+    #[serde(default)]
+    pub cols: Option<u16>,
+    #[serde(default)]
+    pub rows: Option<u16>,
+    #[serde(default)]
+    pub scrollback: Option<usize>,
+}
+
+fn default_baud_rate() -> u32 {
+    115_200
+}
+
+fn default_data_bits() -> u8 {
+    8
+}
+
+fn default_stop_bits() -> u8 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialParity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialFlowControl {
+    #[default]
+    None,
+    /// XON/XOFF
+    Software,
+    /// RTS/CTS
+    Hardware,
+}
+
+impl SerialOptions {
+    /// Configura e apre la porta
+    fn open(&self) -> Result<Box<dyn SerialPort>> {
+        let data_bits = match self.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            8 => DataBits::Eight,
+            bits => return Err(anyhow!("Invalid data bits: {}", bits)),
+        };
+        let stop_bits = match self.stop_bits {
+            1 => StopBits::One,
+            2 => StopBits::Two,
+            bits => return Err(anyhow!("Invalid stop bits: {}", bits)),
+        };
+        let parity = match self.parity {
+            SerialParity::None => Parity::None,
+            SerialParity::Odd => Parity::Odd,
+            SerialParity::Even => Parity::Even,
+        };
+        let flow_control = match self.flow_control {
+            SerialFlowControl::None => FlowControl::None,
+            SerialFlowControl::Software => FlowControl::Software,
+            SerialFlowControl::Hardware => FlowControl::Hardware,
+        };
+        serialport::new(&self.path, self.baud_rate)
+            .data_bits(data_bits)
+            .stop_bits(stop_bits)
+            .parity(parity)
+            .flow_control(flow_control)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .with_context(|| format!("Failed to open serial port {}", self.path))
+    }
+}
+
+/// Porta seriale disponibile
+#[derive(Debug, Clone, Serialize)]
+pub struct SerialPortEntry {
+    pub path: String,
+    /// `usb`, `pci`, `bluetooth` o `unknown`
+    pub kind: String,
+    /// Produttore e prodotto dei dispositivi USB
+    pub description: Option<String>,
+}
+
+/// Porte seriali presenti nel sistema
+pub fn available_ports() -> Result<Vec<SerialPortEntry>> {
+    let ports = serialport::available_ports().context("Failed to list serial ports")?;
+    Ok(ports
+        .into_iter()
+        .map(|port| {
+            let (kind, description) = match port.port_type {
+                SerialPortType::UsbPort(usb) => {
+                    let description = [usb.manufacturer, usb.product]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    ("usb", Some(description).filter(|description| !description.is_empty()))
+                }
+                SerialPortType::PciPort => ("pci", None),
+                SerialPortType::BluetoothPort => ("bluetooth", None),
+                SerialPortType::Unknown => ("unknown", None),
+            };
+            SerialPortEntry {
+                path: port.port_name,
+                kind: kind.to_string(),
+                description,
+            }
+        })
+        .collect())
+}
+
+/// Sessione collegata a una porta seriale
+pub struct SerialSession {
+    pub id: String,
+    pub path: String,
+    pub stream: Arc<TerminalStream>,
+    port: Mutex<Box<dyn SerialPort>>,
+    local_echo: bool,
+    send_crlf: bool,
+    receive_crlf: bool,
+    /// L'ultimo byte mostrato era `\r`; condiviso da output ed eco locale,
+    /// che finiscono sullo stesso schermo
+    last_cr: Arc<Mutex<bool>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl SerialSession {
+    /// Apre la porta e avvia il thread di lettura
+    pub fn open(id: String, options: &SerialOptions) -> Result<Self> {
+        let port = options.open()?;
+        let mut reader = port
+            .try_clone()
+            .with_context(|| format!("Failed to clone serial port {}", options.path))?;
+        let stream = Arc::new(TerminalStream::new(
+            id.clone(),
+            options.cols.unwrap_or(80),
+            options.rows.unwrap_or(24),
+            options.scrollback.unwrap_or(DEFAULT_SCROLLBACK_LINES),
+        ));
+        let session = Self {
+            id: id.clone(),
+            path: options.path.clone(),
+            stream: Arc::clone(&stream),
+            port: Mutex::new(port),
+            local_echo: options.local_echo,
+            send_crlf: options.send_crlf,
+            receive_crlf: options.receive_crlf,
+            last_cr: Arc::new(Mutex::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
+        };
+
+        let closed = Arc::clone(&session.closed);
+        let last_cr = Arc::clone(&session.last_cr);
+        let receive_crlf = options.receive_crlf;
+        let path = options.path.clone();
+        thread::Builder::new()
+            .name(format!("serial-{}", id))
+            .spawn(move || {
+                let mut buffer = [0u8; 4096];
+                let reason = loop {
+                    if closed.load(Ordering::SeqCst) {
+                        break "SIGHUP";
+                    }
+                    match reader.read(&mut buffer) {
+                        Ok(0) => break "device disconnected",
+                        Ok(read) => {
+                            let data = &buffer[..read];
+                            if receive_crlf {
+                                let mut last_cr = last_cr.lock().unwrap();
+                                stream.process(&lf_to_crlf(data, &mut last_cr));
+                            } else {
+                                stream.process(data);
+                            }
+                        }
+                        Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted) => {}
+                        Err(e) => {
+                            warn!("Failed to read from serial port {}: {}", path, e);
+                            break "device disconnected";
+                        }
+                    }
+                };
+                info!("Serial session {} on {} ended: {}", stream.id, path, reason);
+                stream.finish(ProcessExit::closed(reason, unix_timestamp()));
+            })?;
+
+        info!(
+            "Opened serial session {} on {} at {} baud",
+            id, options.path, options.baud_rate
+        );
+        Ok(session)
+    }
+}
+
+impl SessionBackend for SerialSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn write(&self, data: &[u8]) -> Result<()> {
+        if !self.stream.is_active() {
+            return Err(anyhow!("Serial port {} is closed", self.path));
+        }
+        let data = if self.send_crlf {
+            cr_to_crlf(data)
+        } else {
+            data.to_vec()
+        };
+        {
+            let mut port = self.port.lock().unwrap();
+            port.write_all(&data)
+                .with_context(|| format!("Failed to write to serial port {}", self.path))?;
+            port.flush()?;
+        }
+        if self.local_echo {
+            let mut last_cr = self.last_cr.lock().unwrap();
+            let echo = if self.receive_crlf {
+                lf_to_crlf(&data, &mut last_cr)
+            } else {
+                data
+            };
+            self.stream.process(&echo);
+        } else {
+            self.stream.touch();
+        }
+        Ok(())
+    }
+
+    /// Una porta seriale non ha dimensioni: cambia solo lo schermo locale
+    fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        self.stream.resize_screen(cols, rows);
+        Ok(())
+    }
+
+    fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead> {
+        Ok(self.stream.read_output(from_offset))
+    }
+
+    fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        Ok(self.stream.subscribe_output(subscriber))
+    }
+
+    fn unsubscribe_output(&self, subscription_id: u64) -> bool {
+        self.stream.unsubscribe_output(subscription_id)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.stream.clear();
+        Ok(())
+    }
+
+    fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+        Ok(self.stream.get_screen(include_history))
+    }
+
+    fn status(&self) -> Result<SessionStatus> {
+        Ok(self.stream.status())
+    }
+
+    fn set_event_listener(&self, listener: SessionEventListener) {
+        self.stream.set_event_listener(listener)
+    }
+
+    fn close(&self) -> Result<ShutdownReport> {
+        self.closed.store(true, Ordering::SeqCst);
+        Ok(ShutdownReport {
+            session_id: self.id.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn kill(&self) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn list_commands(&self) -> Result<Vec<CommandRecord>> {
+        Ok(self.stream.list_commands())
+    }
+
+    fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        self.stream.get_command_output(command_id)
+    }
+
+    fn activity(&self) -> Option<SessionActivity> {
+        Some(self.stream.activity())
+    }
+}
+
+impl Drop for SerialSession {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Traduce ogni `\r` in `\r\n`; un `\n` che segue già un `\r` resta com'è
+fn cr_to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut translated = Vec::with_capacity(data.len());
+    for (index, &byte) in data.iter().enumerate() {
+        if byte == b'\n' && index > 0 && data[index - 1] == b'\r' {
+            continue;
+        }
+        translated.push(byte);
+        if byte == b'\r' {
+            translated.push(b'\n');
+        }
+    }
+    translated
+}
+
+/// Traduce ogni `\n` non preceduto da `\r` in `\r\n`; `after_cr` ricorda
+/// l'ultimo byte del blocco precedente
+fn lf_to_crlf(data: &[u8], after_cr: &mut bool) -> Vec<u8> {
+    let mut translated = Vec::with_capacity(data.len());
+    for &byte in data {
+        if byte == b'\n' && !*after_cr {
+            translated.push(b'\r');
+        }
+        translated.push(byte);
+        *after_cr = byte == b'\r';
+    }
+    translated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::os::fd::FromRawFd;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::pty::output::OutputChunk;
+
+    #[test]
+    fn test_line_ending_translation() {
+        assert_eq!(cr_to_crlf(b"ls\r"), b"ls\r\n");
+        assert_eq!(cr_to_crlf(b"a\r\nb\n"), b"a\r\nb\n");
+
+        let mut after_cr = false;
+        assert_eq!(lf_to_crlf(b"one\ntwo\r", &mut after_cr), b"one\r\ntwo\r");
+        assert_eq!(lf_to_crlf(b"\nthree\n", &mut after_cr), b"\nthree\r\n");
+    }
+
+    /// Coppia di pseudo-terminali al posto di un dispositivo (come `socat -d
+    /// -d pty,raw,echo=0 pty,raw,echo=0`): la sessione apre il lato slave, il
+    /// test fa da dispositivo sul lato master
+    fn open_device() -> (File, String) {
+        unsafe {
+            let (mut master, mut slave) = (0, 0);
+            let opened = libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            );
+            assert_eq!(opened, 0);
+            let name = CStr::from_ptr(libc::ptsname(master)).to_string_lossy().to_string();
+            // Modalità raw, come un dispositivo seriale
+            let mut termios = std::mem::zeroed();
+            libc::tcgetattr(slave, &mut termios);
+            libc::cfmakeraw(&mut termios);
+            libc::tcsetattr(slave, libc::TCSANOW, &termios);
+            libc::close(slave);
+            (File::from_raw_fd(master), name)
+        }
+    }
+
+    #[test]
+    fn test_serial_session_over_pty_pair() {
+        let (mut master, slave) = open_device();
+        let options = SerialOptions {
+            path: slave,
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: SerialParity::Even,
+            stop_bits: 1,
+            flow_control: SerialFlowControl::None,
+            local_echo: true,
+            send_crlf: true,
+            receive_crlf: true,
+            cols: None,
+            rows: None,
+            scrollback: None,
+        };
+        let session = SerialSession::open("serial-test".to_string(), &options).unwrap();
+
+        session.write(b"help\r").unwrap();
+        let mut received = [0u8; 6];
+        master.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"help\r\n");
+
+        master.write_all(b"ok\n> ").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let expected = b"help\r\nok\r\n> ";
+        while session.read_output(0).unwrap().data != expected && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(session.read_output(0).unwrap().data, expected);
+
+        session.close().unwrap();
+        while session.stream.is_active() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!session.stream.is_active());
+        assert!(session.write(b"x").is_err());
+    }
+
+    /// L'eco locale e il thread di lettura elaborano blocchi insieme: i
+    /// sottoscrittori li ricevono nell'ordine dello scrollback
+    #[test]
+    fn test_local_echo_while_device_writes() {
+        let (mut master, slave) = open_device();
+        let options = SerialOptions {
+            path: slave,
+            baud_rate: 115200,
+            data_bits: 8,
+            parity: SerialParity::None,
+            stop_bits: 1,
+            flow_control: SerialFlowControl::None,
+            local_echo: true,
+            send_crlf: false,
+            receive_crlf: false,
+            cols: None,
+            rows: None,
+            scrollback: None,
+        };
+        let session = SerialSession::open("serial-echo".to_string(), &options).unwrap();
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&chunks);
+        session
+            .subscribe_output(Arc::new(move |chunk: &OutputChunk| {
+                received.lock().unwrap().push((chunk.offset, chunk.bytes.clone()));
+                true
+            }))
+            .unwrap();
+
+        // Quello che la sessione scrive sulla porta va consumato
+        let mut input = master.try_clone().unwrap();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            while input.read(&mut buffer).is_ok_and(|read| read > 0) {}
+        });
+        let device = thread::spawn(move || {
+            for _ in 0..500 {
+                master.write_all(b"device output\r\n").unwrap();
+            }
+            master
+        });
+        for _ in 0..500 {
+            session.write(b"typed").unwrap();
+        }
+        let _master = device.join().unwrap();
+
+        let total = 500 * (b"device output\r\n".len() + b"typed".len());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let delivered = || chunks.lock().unwrap().iter().map(|(_, bytes)| bytes.len()).sum::<usize>();
+        while delivered() < total && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut offset = 0;
+        let mut data = Vec::new();
+        for (start, bytes) in chunks.lock().unwrap().iter() {
+            assert_eq!(*start, offset);
+            offset += bytes.len() as u64;
+            data.extend_from_slice(bytes);
+        }
+        assert_eq!(data.len(), total);
+        assert_eq!(data, session.read_output(0).unwrap().data);
+        session.close().unwrap();
+    }
+}
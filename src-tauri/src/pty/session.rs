@@ -10,6 +10,8 @@ use anyhow::{Result, anyhow};
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 
+use super::process::ProcessInfo;
+
 /// Stato di una sessione terminale
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionStatus {
@@ -21,6 +23,60 @@ pub struct SessionStatus {
     pub buffer_size: usize,
     pub cwd: String,
     pub pid: Option<u32>,
+    /// Esito del processo, presente solo dopo la terminazione
+    #[serde(default)]
+    pub exit_status: Option<ProcessExit>,
+    /// Programma in primo piano sul terminale
+    #[serde(default)]
+    pub foreground_process: Option<ProcessInfo>,
+    /// Istante del salvataggio, se la sessione è stata ripristinata
+    #[serde(default)]
+    pub restored_at: Option<u64>,
+    /// File `.cast` su cui la sessione viene registrata
+    #[serde(default)]
+    pub recording: Option<String>,
+    /// Sessione di riproduzione: non accetta input
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// Esito del processo principale di una sessione
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessExit {
+    /// Codice di uscita; assente se il processo è stato terminato da un segnale
+    pub exit_code: Option<i32>,
+    /// Descrizione del segnale che ha terminato il processo
+    pub signal: Option<String>,
+    pub success: bool,
+    pub exited_at: u64,
+}
+
+impl ProcessExit {
+    /// Converte lo stato restituito da `portable_pty`
+    pub fn from_status(status: &portable_pty::ExitStatus, exited_at: u64) -> Self {
+        let signal = status.signal().map(str::to_string);
+        Self {
+            exit_code: if signal.is_none() {
+                Some(status.exit_code() as i32)
+            } else {
+                None
+            },
+            signal,
+            success: status.success(),
+            exited_at,
+        }
+    }
+
+    /// Fine di una connessione senza un processo locale né uno stato di
+    /// uscita dall'altro capo; `reason` finisce al posto del segnale
+    pub fn closed(reason: &str, exited_at: u64) -> Self {
+        Self {
+            exit_code: None,
+            signal: Some(reason.to_string()),
+            success: false,
+            exited_at,
+        }
+    }
 }
 
 /// Sessione terminale (wrapper per compatibilità)
@@ -112,6 +168,11 @@ impl TerminalSession {
             buffer_size: self.output_buffer.lock().unwrap().len(),
             cwd: self.cwd.clone(),
             pid: *self.pid.lock().unwrap(),
+            exit_status: None,
+            foreground_process: None,
+            restored_at: None,
+            recording: None,
+            read_only: false,
         }
     }
 
@@ -152,5 +213,17 @@ mod tests {
         assert_eq!(status.id, "test");
         assert_eq!(status.cwd, "/tmp");
         assert!(!status.is_active);
+        assert!(status.exit_status.is_none());
+    }
+
+    #[test]
+    fn test_process_exit_from_status() {
+        let exit = ProcessExit::from_status(&portable_pty::ExitStatus::with_exit_code(2), 10);
+        assert_eq!(exit.exit_code, Some(2));
+        assert!(!exit.success);
+
+        let killed = ProcessExit::from_status(&portable_pty::ExitStatus::with_signal("Killed"), 10);
+        assert_eq!(killed.exit_code, None);
+        assert_eq!(killed.signal.as_deref(), Some("Killed"));
     }
 }
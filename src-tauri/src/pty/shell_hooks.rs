@@ -0,0 +1,271 @@
+//! Iniezione automatica dell'integrazione con la shell
+//!
+//! Quando una sessione avvia bash, zsh o fish, gli script in `shell_hooks/`
+//! vengono scritti nella directory dati dell'applicazione e la shell viene
+//! avviata in modo da caricarli senza modificare i file dell'utente:
+//! - bash: `--rcfile` con uno script che carica prima `~/.bashrc`;
+//! - zsh: `ZDOTDIR` punta a una sovrapposizione che carica i file originali;
+//! - fish: `XDG_DATA_DIRS` include una directory con `vendor_conf.d`.
+//!
+//! Gli script emettono i marcatori OSC 133/633 letti da `shell_integration`
+//! e OSC 7 con la directory corrente.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use log::debug;
+use portable_pty::CommandBuilder;
+
+const BASH_RC: &str = include_str!("shell_hooks/termina.bash");
+const ZSH_INTEGRATION: &str = include_str!("shell_hooks/termina-integration.zsh");
+const ZSH_ENV: &str = include_str!("shell_hooks/zshenv");
+const ZSH_PROFILE: &str = include_str!("shell_hooks/zprofile");
+const ZSH_RC: &str = include_str!("shell_hooks/zshrc");
+const ZSH_LOGIN: &str = include_str!("shell_hooks/zlogin");
+const FISH_CONF: &str = include_str!("shell_hooks/termina.fish");
+
+/// Directory dati predefinite secondo la specifica XDG
+const DEFAULT_XDG_DATA_DIRS: &str = "/usr/local/share:/usr/share";
+
+/// Shell per cui è disponibile l'integrazione
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellKind {
+    /// Riconosce la shell dal percorso o dal nome del programma
+    pub fn detect(shell: &str) -> Option<Self> {
+        let name = Path::new(shell).file_name()?.to_str()?;
+        // Le shell di login compaiono come `-bash`
+        match name.trim_start_matches('-') {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// Directory in cui vengono installati gli script di integrazione
+pub fn integration_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("TermInA")
+        .join("shell-integration")
+}
+
+/// Scrive gli script nella directory indicata, solo se cambiati
+pub fn install_scripts(dir: &Path) -> Result<()> {
+    let files: [(&str, &str); 7] = [
+        ("bash/termina.bash", BASH_RC),
+        ("zsh/termina-integration.zsh", ZSH_INTEGRATION),
+        ("zsh/.zshenv", ZSH_ENV),
+        ("zsh/.zprofile", ZSH_PROFILE),
+        ("zsh/.zshrc", ZSH_RC),
+        ("zsh/.zlogin", ZSH_LOGIN),
+        ("fish/vendor_conf.d/termina.fish", FISH_CONF),
+    ];
+
+    for (relative, contents) in files {
+        write_if_changed(&dir.join(relative), contents)?;
+    }
+    Ok(())
+}
+
+/// Distingue i file temporanei delle sessioni avviate dallo stesso processo
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Scrittura atomica: più istanze possono avviare sessioni insieme
+fn write_if_changed(path: &Path, contents: &str) -> Result<()> {
+    if fs::read(path).is_ok_and(|current| current == contents.as_bytes()) {
+        return Ok(());
+    }
+    let parent = path.parent().context("Invalid shell integration path")?;
+    fs::create_dir_all(parent).with_context(|| format!(
+        "Failed to create shell integration directory: {}",
+        parent.display()
+    ))?;
+
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("script");
+    let temp = parent.join(format!(
+        ".{}.{}.{}",
+        file_name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp, contents)
+        .with_context(|| format!("Failed to write shell integration script: {}", temp.display()))?;
+    fs::rename(&temp, path)
+        .with_context(|| format!("Failed to install shell integration script: {}", path.display()))?;
+    Ok(())
+}
+
+/// Installa gli script e prepara il comando perché la shell li carichi
+pub fn apply(cmd: &mut CommandBuilder, kind: ShellKind) -> Result<()> {
+    let dir = integration_dir();
+    install_scripts(&dir)?;
+    configure_command(cmd, kind, &dir);
+    debug!("Shell integration enabled for {:?} from {}", kind, dir.display());
+    Ok(())
+}
+
+/// Aggiunge argomenti e variabili d'ambiente per la shell indicata
+fn configure_command(cmd: &mut CommandBuilder, kind: ShellKind, dir: &Path) {
+    cmd.env("TERMINA_SHELL_INTEGRATION_DIR", dir);
+
+    match kind {
+        ShellKind::Bash => {
+            // `--rcfile` deve precedere eventuali altri argomenti
+            let argv = cmd.get_argv_mut();
+            argv.insert(1, OsString::from("--rcfile"));
+            argv.insert(2, dir.join("bash").join("termina.bash").into_os_string());
+        }
+        ShellKind::Zsh => {
+            let user_zdotdir = cmd
+                .get_env("ZDOTDIR")
+                .or_else(|| cmd.get_env("HOME"))
+                .map(OsString::from)
+                .or_else(|| dirs::home_dir().map(PathBuf::into_os_string))
+                .unwrap_or_default();
+            cmd.env("TERMINA_USER_ZDOTDIR", user_zdotdir);
+            cmd.env("ZDOTDIR", dir.join("zsh"));
+        }
+        ShellKind::Fish => {
+            let original = cmd.get_env("XDG_DATA_DIRS").map(OsString::from);
+            let mut data_dirs = dir.as_os_str().to_os_string();
+            data_dirs.push(":");
+            match &original {
+                Some(original) => {
+                    data_dirs.push(original);
+                    cmd.env("TERMINA_ORIGINAL_XDG_DATA_DIRS", original);
+                }
+                None => data_dirs.push(DEFAULT_XDG_DATA_DIRS),
+            }
+            cmd.env("XDG_DATA_DIRS", data_dirs);
+        }
+    }
+}
+
+/// Riporta ai valori dell'utente le variabili modificate dall'integrazione
+/// in un ambiente letto da una shell già avviata, togliendo quelle aggiunte
+pub fn restore_user_env(env: &mut HashMap<String, String>) {
+    if let Some(zdotdir) = env.get("TERMINA_USER_ZDOTDIR").cloned() {
+        env.insert("ZDOTDIR".to_string(), zdotdir);
+    }
+    match env.get("TERMINA_ORIGINAL_XDG_DATA_DIRS").cloned() {
+        Some(original) => {
+            env.insert("XDG_DATA_DIRS".to_string(), original);
+        }
+        None => {
+            let injected = env.get("TERMINA_SHELL_INTEGRATION_DIR").is_some_and(|dir| {
+                env.get("XDG_DATA_DIRS")
+                    .is_some_and(|data_dirs| data_dirs.starts_with(&format!("{}:", dir)))
+            });
+            if injected {
+                env.remove("XDG_DATA_DIRS");
+            }
+        }
+    }
+    env.retain(|key, _| !key.starts_with("TERMINA_"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_shell() {
+        assert_eq!(ShellKind::detect("/bin/bash"), Some(ShellKind::Bash));
+        assert_eq!(ShellKind::detect("-zsh"), Some(ShellKind::Zsh));
+        assert_eq!(ShellKind::detect("/usr/local/bin/fish"), Some(ShellKind::Fish));
+        assert_eq!(ShellKind::detect("/bin/sh"), None);
+    }
+
+    #[test]
+    fn test_configure_command() {
+        let dir = Path::new("/data/shell-integration");
+
+        let mut bash = CommandBuilder::new("/bin/bash");
+        configure_command(&mut bash, ShellKind::Bash, dir);
+        assert_eq!(bash.get_argv()[1], "--rcfile");
+        assert_eq!(bash.get_argv()[2], dir.join("bash/termina.bash").as_os_str());
+
+        let mut zsh = CommandBuilder::new("zsh");
+        zsh.env("ZDOTDIR", "/home/user/.config/zsh");
+        configure_command(&mut zsh, ShellKind::Zsh, dir);
+        assert_eq!(zsh.get_env("ZDOTDIR").unwrap(), dir.join("zsh").as_os_str());
+        assert_eq!(zsh.get_env("TERMINA_USER_ZDOTDIR").unwrap(), "/home/user/.config/zsh");
+
+        let mut fish = CommandBuilder::new("fish");
+        fish.env("XDG_DATA_DIRS", "/usr/share");
+        configure_command(&mut fish, ShellKind::Fish, dir);
+        assert_eq!(fish.get_env("XDG_DATA_DIRS").unwrap(), "/data/shell-integration:/usr/share");
+        assert_eq!(fish.get_env("TERMINA_ORIGINAL_XDG_DATA_DIRS").unwrap(), "/usr/share");
+    }
+
+    #[test]
+    fn test_install_scripts() {
+        let dir = std::env::temp_dir().join(format!("termina-hooks-{}", std::process::id()));
+        install_scripts(&dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("zsh/.zshrc")).unwrap(), ZSH_RC);
+        assert!(dir.join("fish/vendor_conf.d/termina.fish").exists());
+        // Una seconda installazione non deve fallire
+        install_scripts(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_install() {
+        let dir = std::env::temp_dir().join(format!("termina-hooks-race-{}", std::process::id()));
+        let path = dir.join("bash/termina.bash");
+        // Sessioni avviate insieme riscrivono lo stesso script in parallelo
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for j in 0..50 {
+                        write_if_changed(&path, &format!("{}-{}", i, j)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // Nessun file temporaneo deve restare nella cartella
+        assert_eq!(fs::read_dir(dir.join("bash")).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_user_env() {
+        let dir = Path::new("/data/shell-integration");
+        let mut zsh = CommandBuilder::new("zsh");
+        zsh.env("ZDOTDIR", "/home/user/.config/zsh");
+        let mut fish = CommandBuilder::new("fish");
+        fish.env_remove("XDG_DATA_DIRS");
+        configure_command(&mut zsh, ShellKind::Zsh, dir);
+        configure_command(&mut fish, ShellKind::Fish, dir);
+
+        let read_env = |cmd: &CommandBuilder| -> HashMap<String, String> {
+            cmd.iter_full_env_as_str()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let mut env = read_env(&zsh);
+        restore_user_env(&mut env);
+        assert_eq!(env.get("ZDOTDIR").map(String::as_str), Some("/home/user/.config/zsh"));
+        assert!(!env.keys().any(|key| key.starts_with("TERMINA_")));
+
+        let mut env = read_env(&fish);
+        restore_user_env(&mut env);
+        assert!(!env.contains_key("XDG_DATA_DIRS"));
+    }
+}
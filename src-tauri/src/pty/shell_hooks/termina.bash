@@ -0,0 +1,83 @@
+# Integrazione di TermInA per bash
+#
+# Caricato con `bash --rcfile`: sostituisce ~/.bashrc, quindi prima carica i
+# file di avvio dell'utente e poi installa gli hook che emettono i marcatori
+# OSC 133 (prompt, input, comando, esito) e OSC 7 (directory corrente).
+
+if [[ -n "$TERMINA_BASH_LOGIN" ]]; then
+    unset TERMINA_BASH_LOGIN
+    [[ -r /etc/profile ]] && builtin source /etc/profile
+    for __termina_file in ~/.bash_profile ~/.bash_login ~/.profile; do
+        if [[ -r "$__termina_file" ]]; then
+            builtin source "$__termina_file"
+            break
+        fi
+    done
+    unset __termina_file
+else
+    [[ -r ~/.bashrc ]] && builtin source ~/.bashrc
+fi
+
+if [[ $- == *i* && -z "$__termina_integration_loaded" ]]; then
+    __termina_integration_loaded=1
+    __termina_at_prompt=0
+    __termina_in_command=0
+
+    __termina_wrap_ps1() {
+        if [[ "$PS1" != *'133;A'* ]]; then
+            PS1='\[\e]133;A\a\]'"$PS1"'\[\e]133;B\a\]'
+        fi
+    }
+
+    __termina_preexec() {
+        [[ "$__termina_at_prompt" == 1 ]] || return
+        [[ -n "$COMP_LINE" ]] && return
+        [[ "$BASH_COMMAND" == __termina_prompt_start* ]] && return
+        __termina_at_prompt=0
+        __termina_in_command=1
+        # Il testo del comando viene ricavato dallo schermo tra B e C
+        builtin printf '\e]133;C\a'
+    }
+
+    # Percent-encoding del percorso per l'URL di OSC 7, byte per byte come
+    # `string escape --style=url` di fish; il risultato va in __termina_encoded
+    __termina_urlencode() {
+        local LC_ALL=C path=$1 i c
+        __termina_encoded=
+        for (( i = 0; i < ${#path}; i++ )); do
+            c=${path:i:1}
+            case "$c" in
+                [a-zA-Z0-9/._~-]) __termina_encoded+=$c ;;
+                *) builtin printf -v c '%%%02X' "'$c"; __termina_encoded+=$c ;;
+            esac
+        done
+    }
+
+    __termina_prompt_start() {
+        local ret=$?
+        __termina_at_prompt=0
+        if [[ "$__termina_in_command" == 1 ]]; then
+            builtin printf '\e]133;D;%s\a' "$ret"
+        fi
+        __termina_in_command=0
+        __termina_urlencode "$PWD"
+        builtin printf '\e]7;file://%s%s\a' "$HOSTNAME" "$__termina_encoded"
+        return $ret
+    }
+
+    __termina_prompt_end() {
+        local ret=$?
+        __termina_wrap_ps1
+        __termina_at_prompt=1
+        return $ret
+    }
+
+    if [[ "$(declare -p PROMPT_COMMAND 2>/dev/null)" == "declare -a"* ]]; then
+        PROMPT_COMMAND=(__termina_prompt_start "${PROMPT_COMMAND[@]}" __termina_prompt_end)
+    else
+        PROMPT_COMMAND="__termina_prompt_start${PROMPT_COMMAND:+
+$PROMPT_COMMAND}
+__termina_prompt_end"
+    fi
+    trap '__termina_preexec' DEBUG
+fi
@@ -0,0 +1,42 @@
+# Integrazione di TermInA per fish
+#
+# Caricato da vendor_conf.d tramite XDG_DATA_DIRS, prima di config.fish.
+# Emette i marcatori OSC 133 (prompt, input, comando, esito), OSC 633;E con
+# il testo del comando e OSC 7 con la directory corrente.
+
+# XDG_DATA_DIRS torna quello originale per i processi figli
+if set -q TERMINA_ORIGINAL_XDG_DATA_DIRS
+    set -gx XDG_DATA_DIRS $TERMINA_ORIGINAL_XDG_DATA_DIRS
+    set -e TERMINA_ORIGINAL_XDG_DATA_DIRS
+else if set -q TERMINA_SHELL_INTEGRATION_DIR
+    set -e XDG_DATA_DIRS
+end
+
+if status is-interactive; and not set -q __termina_integration_loaded
+    set -g __termina_integration_loaded 1
+
+    function __termina_prompt_start --on-event fish_prompt
+        printf '\e]7;file://%s%s\a' (prompt_hostname) (string escape --style=url -- $PWD)
+        # fish_prompt può essere definito in config.fish: si avvolge al primo prompt
+        if not functions -q __termina_original_fish_prompt; and functions -q fish_prompt
+            functions -c fish_prompt __termina_original_fish_prompt
+            function fish_prompt
+                printf '\e]133;A\a'
+                __termina_original_fish_prompt
+                printf '\e]133;B\a'
+            end
+        end
+    end
+
+    function __termina_preexec --on-event fish_preexec
+        set -l cmd (string join \n -- $argv)
+        set cmd (string replace -a -- '\\' '\\\\' $cmd)
+        set cmd (string replace -a -- ';' '\\x3b' $cmd)
+        printf '\e]633;E;%s\a' (string join '\\x0a' -- $cmd)
+        printf '\e]133;C\a'
+    end
+
+    function __termina_postexec --on-event fish_postexec
+        printf '\e]133;D;%s\a' $status
+    end
+end
@@ -0,0 +1,294 @@
+//! Integrazione con la shell tramite marcatori OSC 133
+//!
+//! Le shell configurate con l'integrazione FinalTerm emettono sequenze
+//! `OSC 133 ; A|B|C|D` all'inizio del prompt, all'inizio dell'input, all'avvio
+//! del comando e alla sua terminazione (con il codice di uscita). Da questi
+//! marcatori si ricostruisce l'elenco dei comandi eseguiti, con il testo, il
+//! range di output nello scrollback, l'esito e i tempi.
+//! Sono accettate anche le varianti di VS Code (`OSC 633`) e di kitty
+//! (`cmdline=`/`cmdline_url=`) per il testo del comando.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::screen::{PositionedEvent, ScreenEvent, TerminalScreen};
+
+/// Numero massimo di comandi conservati per sessione
+const MAX_COMMAND_RECORDS: usize = 500;
+
+/// Fase corrente della shell secondo i marcatori ricevuti
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellPhase {
+    /// Nessun marcatore ricevuto: la shell non ha l'integrazione attiva
+    #[default]
+    Unknown,
+    Prompt,
+    Input,
+    Running,
+}
+
+/// Comando eseguito in una sessione
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub id: u64,
+    pub command: String,
+    /// Offset dell'inizio del prompt che ha preceduto il comando
+    pub prompt_offset: Option<u64>,
+    /// Offset del primo byte di output del comando
+    pub output_start: u64,
+    /// Offset successivo all'ultimo byte di output (assente se in esecuzione)
+    pub output_end: Option<u64>,
+    pub exit_code: Option<i32>,
+    /// Millisecondi dall'epoch
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+/// Cambiamento di stato di un comando
+#[derive(Debug, Clone)]
+pub enum CommandEvent {
+    Started(CommandRecord),
+    Finished(CommandRecord),
+}
+
+/// Ricostruisce i comandi di una sessione a partire dai marcatori
+#[derive(Debug, Default)]
+pub struct CommandTracker {
+    records: VecDeque<CommandRecord>,
+    next_id: u64,
+    phase: ShellPhase,
+    running: bool,
+    prompt_offset: Option<u64>,
+    input_start: Option<(u64, usize)>,
+    explicit_command: Option<String>,
+}
+
+impl CommandTracker {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Elabora un evento dello schermo; `screen` è nello stato successivo
+    /// alla sequenza, quindi fornisce la posizione del cursore al marcatore
+    pub fn handle_event(&mut self, screen: &TerminalScreen, event: &PositionedEvent) -> Option<CommandEvent> {
+        let ScreenEvent::Osc(params) = &event.event else {
+            return None;
+        };
+        let (Some(code), Some(kind)) = (params.first(), params.get(1)) else {
+            return None;
+        };
+        if code.as_slice() != b"133" && code.as_slice() != b"633" {
+            return None;
+        }
+        let args = &params[2..];
+
+        match kind.as_slice() {
+            b"A" => {
+                // Un prompt senza `D` chiude comunque il comando precedente
+                let finished = self.finish_running(event.start, None);
+                self.phase = ShellPhase::Prompt;
+                self.prompt_offset = Some(event.start);
+                self.input_start = None;
+                self.explicit_command = None;
+                finished
+            }
+            b"B" => {
+                self.phase = ShellPhase::Input;
+                self.input_start = Some(screen.cursor_position());
+                None
+            }
+            b"C" => {
+                let finished = self.finish_running(event.start, None);
+                let command = Self::kitty_cmdline(args)
+                    .or_else(|| self.explicit_command.take())
+                    .or_else(|| {
+                        self.input_start
+                            .map(|start| screen.text_between(start, screen.cursor_position()))
+                    })
+                    .unwrap_or_default();
+
+                let record = CommandRecord {
+                    id: self.next_id,
+                    command: command.trim().to_string(),
+                    prompt_offset: self.prompt_offset.take(),
+                    output_start: event.end,
+                    output_end: None,
+                    exit_code: None,
+                    started_at: current_timestamp_millis(),
+                    finished_at: None,
+                };
+                self.next_id += 1;
+                self.phase = ShellPhase::Running;
+                self.running = true;
+                self.input_start = None;
+                self.push_record(record.clone());
+                // Il comando precedente non chiuso ha la precedenza come evento
+                finished.or(Some(CommandEvent::Started(record)))
+            }
+            b"D" => {
+                let exit_code = args
+                    .first()
+                    .and_then(|code| std::str::from_utf8(code).ok())
+                    .and_then(|code| code.trim().parse::<i32>().ok());
+                self.phase = ShellPhase::Prompt;
+                self.finish_running(event.start, exit_code)
+            }
+            b"E" if code.as_slice() == b"633" => {
+                self.explicit_command = args.first().map(|cmd| unescape_vscode(cmd));
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn finish_running(&mut self, end: u64, exit_code: Option<i32>) -> Option<CommandEvent> {
+        if !self.running {
+            return None;
+        }
+        self.running = false;
+        let record = self.records.back_mut()?;
+        record.output_end = Some(end.max(record.output_start));
+        record.exit_code = exit_code;
+        record.finished_at = Some(current_timestamp_millis());
+        Some(CommandEvent::Finished(record.clone()))
+    }
+
+    fn push_record(&mut self, record: CommandRecord) {
+        if self.records.len() >= MAX_COMMAND_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Testo del comando fornito da kitty (`cmdline=` o `cmdline_url=`)
+    fn kitty_cmdline(args: &[Vec<u8>]) -> Option<String> {
+        args.iter().find_map(|arg| {
+            let arg = String::from_utf8_lossy(arg);
+            if let Some(url) = arg.strip_prefix("cmdline_url=") {
+                urlencoding::decode(url).ok().map(|cmd| cmd.into_owned())
+            } else {
+                arg.strip_prefix("cmdline=").map(str::to_string)
+            }
+        })
+    }
+
+    /// Fase corrente della shell
+    pub fn phase(&self) -> ShellPhase {
+        self.phase
+    }
+
+    /// Comando in esecuzione, se presente
+    pub fn current_command(&self) -> Option<&CommandRecord> {
+        if self.running {
+            self.records.back()
+        } else {
+            None
+        }
+    }
+
+    /// Comandi registrati, dal più vecchio al più recente
+    pub fn records(&self) -> Vec<CommandRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    /// Comando con l'id indicato
+    pub fn record(&self, command_id: u64) -> Option<CommandRecord> {
+        self.records.iter().find(|record| record.id == command_id).cloned()
+    }
+}
+
+/// Decodifica l'escape di VS Code (`\\` e `\xNN`) nel testo del comando
+fn unescape_vscode(raw: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut index = 0;
+    while index < raw.len() {
+        if raw[index] == b'\\' {
+            if raw.get(index + 1) == Some(&b'\\') {
+                bytes.push(b'\\');
+                index += 2;
+                continue;
+            }
+            if raw.get(index + 1) == Some(&b'x') && index + 4 <= raw.len() {
+                let hex = std::str::from_utf8(&raw[index + 2..index + 4]).ok();
+                if let Some(value) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    bytes.push(value);
+                    index += 4;
+                    continue;
+                }
+            }
+        }
+        bytes.push(raw[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Timestamp corrente in millisecondi
+fn current_timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(tracker: &mut CommandTracker, screen: &mut TerminalScreen, data: &str) -> Vec<CommandEvent> {
+        let mut events = Vec::new();
+        screen.process_with(data.as_bytes(), |screen, event| {
+            if let Some(update) = tracker.handle_event(screen, event) {
+                events.push(update);
+            }
+        });
+        events
+    }
+
+    #[test]
+    fn test_command_lifecycle_from_screen_text() {
+        let mut tracker = CommandTracker::new();
+        let mut screen = TerminalScreen::new(40, 10, 100);
+
+        feed(&mut tracker, &mut screen, "\x1b]133;A\x07$ \x1b]133;B\x07");
+        assert_eq!(tracker.phase(), ShellPhase::Input);
+
+        let events = feed(&mut tracker, &mut screen, "ls -la\r\n\x1b]133;C\x07");
+        assert!(matches!(&events[0], CommandEvent::Started(record) if record.command == "ls -la"));
+        assert_eq!(tracker.current_command().unwrap().command, "ls -la");
+
+        let start = screen.offset();
+        let events = feed(&mut tracker, &mut screen, "file\r\n\x1b]133;D;2\x07");
+        let CommandEvent::Finished(record) = &events[0] else {
+            panic!("expected finished event");
+        };
+        assert_eq!(record.exit_code, Some(2));
+        assert_eq!(record.output_start, start);
+        assert_eq!(record.output_end, Some(start + 6));
+        assert!(tracker.current_command().is_none());
+        assert_eq!(tracker.records().len(), 1);
+    }
+
+    #[test]
+    fn test_explicit_command_text() {
+        let mut tracker = CommandTracker::new();
+        let mut screen = TerminalScreen::new(40, 10, 100);
+
+        feed(&mut tracker, &mut screen, "\x1b]633;A\x07\x1b]633;B\x07\x1b]633;E;echo a\\x3bb\x07");
+        feed(&mut tracker, &mut screen, "\x1b]633;C\x07");
+        assert_eq!(tracker.current_command().unwrap().command, "echo a;b");
+
+        feed(&mut tracker, &mut screen, "\x1b]133;A\x07\x1b]133;C;cmdline_url=git%20status\x07");
+        let records = tracker.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].exit_code, None);
+        assert!(records[0].output_end.is_some());
+        assert_eq!(records[1].command, "git status");
+    }
+}
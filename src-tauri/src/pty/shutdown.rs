@@ -0,0 +1,237 @@
+//! Chiusura delle sessioni con pulizia dell'albero dei processi
+//!
+//! Terminare solo la shell lascerebbe orfani i job in background e i
+//! processi avviati dai comandi (server di sviluppo, `docker compose up`).
+//! La chiusura avviene per gradi, come alla chiusura di un terminale:
+//! SIGHUP ai gruppi di processi della sessione, un periodo di grazia per
+//! uscire in modo pulito, poi SIGKILL a tutto ciò che è ancora vivo.
+
+use std::collections::BTreeSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::process::{self, ProcessInfo, ProcessStat};
+use super::signals::{self, SessionSignal};
+
+/// Periodo di grazia predefinito tra SIGHUP e SIGKILL, in millisecondi
+pub const DEFAULT_CLOSE_GRACE_PERIOD_MS: u64 = 2000;
+
+/// Attesa massima dopo SIGKILL prima di considerare i processi sopravvissuti
+const KILL_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Intervallo tra due controlli dei processi ancora vivi
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Esito della chiusura di una sessione
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub session_id: String,
+    /// Gruppi di processi che hanno ricevuto SIGHUP
+    pub hangup_groups: Vec<i32>,
+    /// Processi ancora vivi alla fine del periodo di grazia, terminati con SIGKILL
+    pub killed: Vec<ProcessInfo>,
+    /// Processi ancora vivi al termine della chiusura
+    pub survivors: Vec<ProcessInfo>,
+    pub duration_ms: u64,
+}
+
+/// Processi da chiudere: con la shell ancora viva la shell e la sua
+/// sessione, dopo la sua uscita solo quello che ne resta
+#[derive(Debug, Clone, Copy)]
+struct Members {
+    shell_pid: i32,
+    shell_exited: bool,
+}
+
+impl Members {
+    fn list(&self) -> Vec<ProcessStat> {
+        if self.shell_exited {
+            process::leftover_session_processes(self.shell_pid)
+        } else {
+            process::session_processes(self.shell_pid)
+        }
+    }
+}
+
+/// Chiude i processi della sessione guidata da `shell_pid`. Con un periodo
+/// di grazia nullo si passa direttamente a SIGKILL.
+pub fn shutdown(session_id: &str, shell_pid: i32, grace_period: Duration) -> ShutdownReport {
+    run(session_id, Members { shell_pid, shell_exited: false }, grace_period)
+}
+
+/// Chiude i processi rimasti nella sessione di una shell già uscita (es.
+/// `docker compose up &`), senza mai segnalare il pid della shell, che
+/// potrebbe essere stato riassegnato
+pub fn shutdown_leftovers(session_id: &str, shell_pid: i32, grace_period: Duration) -> ShutdownReport {
+    run(session_id, Members { shell_pid, shell_exited: true }, grace_period)
+}
+
+fn run(session_id: &str, members: Members, grace_period: Duration) -> ShutdownReport {
+    let started = Instant::now();
+    let mut report = ShutdownReport {
+        session_id: session_id.to_string(),
+        ..Default::default()
+    };
+    let initial = members.list();
+    let mut known: BTreeSet<i32> = initial.iter().map(|stat| stat.pid).collect();
+    if !members.shell_exited {
+        known.insert(members.shell_pid);
+    }
+    if known.is_empty() {
+        return report;
+    }
+
+    if !grace_period.is_zero() {
+        report.hangup_groups = process_groups(&initial, members);
+        for &pgid in &report.hangup_groups {
+            // I job sospesi devono ripartire per poter gestire SIGHUP
+            for signal in [SessionSignal::Hangup, SessionSignal::Continue] {
+                if let Err(e) = signals::send_to_group(pgid, signal) {
+                    debug!("Session {}: {}", session_id, e);
+                }
+            }
+        }
+        wait_for_exit(members, &mut known, started + grace_period);
+    }
+
+    let remaining = alive(members, &mut known);
+    if !remaining.is_empty() {
+        report.killed = describe(&remaining, members.shell_pid);
+        for &pid in &remaining {
+            if let Err(e) = signals::send_to_process(pid, SessionSignal::Kill) {
+                debug!("Session {}: {}", session_id, e);
+            }
+        }
+        wait_for_exit(members, &mut known, Instant::now() + KILL_TIMEOUT);
+        report.survivors = describe(&alive(members, &mut known), members.shell_pid);
+    }
+
+    report.duration_ms = started.elapsed().as_millis() as u64;
+    if !report.killed.is_empty() {
+        info!(
+            "Session {}: killed {} process(es) still running after {:?}: {}",
+            session_id,
+            report.killed.len(),
+            grace_period,
+            titles(&report.killed)
+        );
+    }
+    if !report.survivors.is_empty() {
+        warn!(
+            "Session {}: {} process(es) survived shutdown: {}",
+            session_id,
+            report.survivors.len(),
+            titles(&report.survivors)
+        );
+    }
+    report
+}
+
+/// Gruppi di processi distinti della sessione, quello della shell per primo
+/// se è ancora viva
+fn process_groups(processes: &[ProcessStat], members: Members) -> Vec<i32> {
+    let mut groups = if members.shell_exited {
+        Vec::new()
+    } else {
+        vec![members.shell_pid]
+    };
+    for stat in processes {
+        if stat.pgid > 0 && !groups.contains(&stat.pgid) {
+            groups.push(stat.pgid);
+        }
+    }
+    groups
+}
+
+/// Processi ancora vivi tra quelli noti e quelli comparsi nel frattempo
+fn alive(members: Members, known: &mut BTreeSet<i32>) -> Vec<i32> {
+    known.extend(members.list().iter().map(|stat| stat.pid));
+    known.iter().copied().filter(|&pid| process::is_alive(pid)).collect()
+}
+
+fn wait_for_exit(members: Members, known: &mut BTreeSet<i32>, deadline: Instant) {
+    while !alive(members, known).is_empty() && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+}
+
+fn describe(pids: &[i32], shell_pid: i32) -> Vec<ProcessInfo> {
+    pids.iter()
+        .filter_map(|&pid| process::process_info(pid, Some(shell_pid)))
+        .collect()
+}
+
+fn titles(processes: &[ProcessInfo]) -> String {
+    processes
+        .iter()
+        .map(|process| format!("{} ({})", process.title(), process.pid))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn test_shutdown_kills_processes_ignoring_hangup() {
+        // Shell in una nuova sessione con un figlio che ignora SIGHUP
+        let mut shell = Command::new("sh");
+        shell.args(["-c", "trap '' HUP; sleep 30 & wait"]);
+        unsafe {
+            shell.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        let mut shell = shell.spawn().unwrap();
+        let shell_pid = shell.id() as i32;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process::session_processes(shell_pid).len() < 2 {
+            assert!(Instant::now() < deadline, "sleep did not start");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let reaper = thread::spawn(move || shell.wait());
+
+        let report = shutdown("t", shell_pid, Duration::from_millis(200));
+        reaper.join().unwrap().unwrap();
+        assert_eq!(report.hangup_groups, vec![shell_pid]);
+        assert!(report.killed.iter().any(|process| process.name == "sleep"));
+        assert!(report.survivors.is_empty());
+        assert!(process::session_processes(shell_pid).is_empty());
+    }
+
+    #[test]
+    fn test_shutdown_leftovers_after_shell_exit() {
+        // La shell esce lasciando nella sua sessione un job che ignora SIGHUP
+        let mut shell = Command::new("sh");
+        shell.args(["-c", "trap '' HUP; sleep 30 & exit 0"]);
+        unsafe {
+            shell.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        let mut shell = shell.spawn().unwrap();
+        let shell_pid = shell.id() as i32;
+        shell.wait().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process::leftover_session_processes(shell_pid).is_empty() {
+            assert!(Instant::now() < deadline, "background job not found");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let report = shutdown_leftovers("t", shell_pid, Duration::from_millis(200));
+        // Senza job control il job resta nel gruppo della shell, ancora valido
+        assert_eq!(report.hangup_groups, vec![shell_pid]);
+        assert_eq!(report.killed.len(), 1);
+        assert!(report.survivors.is_empty());
+        assert!(process::leftover_session_processes(shell_pid).is_empty());
+    }
+}
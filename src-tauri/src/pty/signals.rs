@@ -0,0 +1,115 @@
+//! Invio di segnali ai processi di una sessione
+//!
+//! Il segnale può raggiungere la sola shell o l'intero gruppo di processi in
+//! primo piano sul terminale (come farebbe il driver del TTY con Ctrl+C),
+//! così si può fermare un comando senza dipendere da cosa legge il suo input.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Segnali inviabili a una sessione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionSignal {
+    #[serde(rename = "SIGINT", alias = "INT")]
+    Interrupt,
+    #[serde(rename = "SIGTERM", alias = "TERM")]
+    Terminate,
+    #[serde(rename = "SIGHUP", alias = "HUP")]
+    Hangup,
+    #[serde(rename = "SIGQUIT", alias = "QUIT")]
+    Quit,
+    #[serde(rename = "SIGTSTP", alias = "TSTP")]
+    Stop,
+    #[serde(rename = "SIGCONT", alias = "CONT")]
+    Continue,
+    #[serde(rename = "SIGKILL", alias = "KILL")]
+    Kill,
+}
+
+/// Destinatario del segnale
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalTarget {
+    /// Il processo della shell
+    Shell,
+    /// Il gruppo di processi in primo piano sul terminale
+    #[default]
+    Foreground,
+}
+
+#[cfg(unix)]
+impl SessionSignal {
+    fn raw(self) -> libc::c_int {
+        match self {
+            SessionSignal::Interrupt => libc::SIGINT,
+            SessionSignal::Terminate => libc::SIGTERM,
+            SessionSignal::Hangup => libc::SIGHUP,
+            SessionSignal::Quit => libc::SIGQUIT,
+            SessionSignal::Stop => libc::SIGTSTP,
+            SessionSignal::Continue => libc::SIGCONT,
+            SessionSignal::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+/// Invia il segnale al processo `pid`
+#[cfg(unix)]
+pub fn send_to_process(pid: i32, signal: SessionSignal) -> Result<()> {
+    check(unsafe { libc::kill(pid, signal.raw()) }, "process", pid)
+}
+
+/// Invia il segnale a tutti i processi del gruppo `pgid`
+#[cfg(unix)]
+pub fn send_to_group(pgid: i32, signal: SessionSignal) -> Result<()> {
+    check(unsafe { libc::killpg(pgid, signal.raw()) }, "process group", pgid)
+}
+
+#[cfg(unix)]
+fn check(result: libc::c_int, kind: &str, id: i32) -> Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Failed to signal {} {}: {}",
+            kind,
+            id,
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send_to_process(_pid: i32, _signal: SessionSignal) -> Result<()> {
+    Err(anyhow!("Signals are not supported on this platform"))
+}
+
+#[cfg(not(unix))]
+pub fn send_to_group(_pgid: i32, _signal: SessionSignal) -> Result<()> {
+    Err(anyhow!("Signals are not supported on this platform"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_names() {
+        let signal: SessionSignal = serde_json::from_str("\"SIGTSTP\"").unwrap();
+        assert_eq!(signal, SessionSignal::Stop);
+        let signal: SessionSignal = serde_json::from_str("\"INT\"").unwrap();
+        assert_eq!(signal, SessionSignal::Interrupt);
+        assert!(serde_json::from_str::<SessionSignal>("\"SIGUSR1\"").is_err());
+        assert_eq!(serde_json::to_string(&SessionSignal::Kill).unwrap(), "\"SIGKILL\"");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_to_process() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        send_to_process(child.id() as i32, SessionSignal::Terminate).unwrap();
+        let status = child.wait().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+    }
+}
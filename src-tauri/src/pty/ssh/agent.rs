@@ -0,0 +1,232 @@
+//! Inoltro dell'agente SSH
+//!
+//! Con l'inoltro attivo il server apre un canale `auth-agent@openssh.com`
+//! per ogni richiesta all'agente fatta dall'host remoto. `ssh2` non espone
+//! questi canali: la callback `LIBSSH2_CALLBACK_AUTHAGENT` di libssh2 li
+//! consegna qui, e il thread della sessione li collega uno per uno al socket
+//! dell'agente locale (`SSH_AUTH_SOCK`), copiando i byte nei due sensi.
+//! L'agente è un socket Unix: altrove l'inoltro non è disponibile.
+
+#[cfg(unix)]
+use std::ffi::c_void;
+#[cfg(unix)]
+use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::raw::{c_char, c_int};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::sync::Mutex;
+
+#[cfg(unix)]
+use log::debug;
+use log::warn;
+use ssh2::Session;
+
+#[cfg(unix)]
+/// Costante `LIBSSH2_CALLBACK_AUTHAGENT` di `libssh2.h`
+const LIBSSH2_CALLBACK_AUTHAGENT: c_int = 7;
+
+#[cfg(unix)]
+extern "C" {
+    // Non dichiarata da `libssh2-sys`, ma presente nella libreria collegata
+    fn libssh2_session_callback_set(
+        session: *mut libssh2_sys::LIBSSH2_SESSION,
+        cbtype: c_int,
+        callback: *mut c_void,
+    ) -> *mut c_void;
+}
+
+#[cfg(unix)]
+/// Canali aperti dal server in attesa di essere collegati, come coppie
+/// (sessione, canale); la callback non può accedere ad altro stato
+static OPENED: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+
+#[cfg(unix)]
+extern "C" fn on_agent_channel(
+    session: *mut libssh2_sys::LIBSSH2_SESSION,
+    channel: *mut libssh2_sys::LIBSSH2_CHANNEL,
+    _abstract: *mut *mut c_void,
+) {
+    OPENED.lock().unwrap().push((session as usize, channel as usize));
+}
+
+#[cfg(unix)]
+/// Canale dell'agente collegato al socket locale
+struct AgentChannel {
+    channel: *mut libssh2_sys::LIBSSH2_CHANNEL,
+    agent: UnixStream,
+    /// Byte del server non ancora scritti sul socket
+    to_agent: Vec<u8>,
+    /// Byte dell'agente non ancora scritti sul canale
+    to_server: Vec<u8>,
+    agent_closed: bool,
+}
+
+#[cfg(unix)]
+/// Canali dell'agente di una sessione; va usato solo dal thread che possiede
+/// la sessione
+pub struct AgentForwarder {
+    socket: PathBuf,
+    session: usize,
+    channels: Vec<AgentChannel>,
+}
+
+#[cfg(unix)]
+// I puntatori ai canali sono usati solo sotto il lock della sessione
+unsafe impl Send for AgentForwarder {}
+
+#[cfg(unix)]
+impl AgentForwarder {
+    /// Installa la callback sulla sessione; `None` senza un agente locale
+    pub fn install(session: &Session) -> Option<Self> {
+        let socket = PathBuf::from(std::env::var_os("SSH_AUTH_SOCK")?);
+        let raw = &mut *session.raw() as *mut libssh2_sys::LIBSSH2_SESSION;
+        unsafe {
+            libssh2_session_callback_set(raw, LIBSSH2_CALLBACK_AUTHAGENT, on_agent_channel as *mut c_void);
+        }
+        Some(Self {
+            socket,
+            session: raw as usize,
+            channels: Vec::new(),
+        })
+    }
+
+    /// Collega i canali appena aperti e copia i dati in attesa; restituisce
+    /// `true` se qualche byte è stato trasferito
+    pub fn pump(&mut self, session: &Session) -> bool {
+        let opened: Vec<usize> = {
+            let mut opened = OPENED.lock().unwrap();
+            let (mine, others) = opened.drain(..).partition(|(session, _)| *session == self.session);
+            *opened = others;
+            mine.into_iter().map(|(_, channel)| channel).collect()
+        };
+
+        let _session = session.raw();
+        for channel in opened {
+            let channel = channel as *mut libssh2_sys::LIBSSH2_CHANNEL;
+            match UnixStream::connect(&self.socket).and_then(|agent| agent.set_nonblocking(true).map(|_| agent)) {
+                Ok(agent) => {
+                    debug!("Forwarding SSH agent channel to {}", self.socket.display());
+                    self.channels.push(AgentChannel {
+                        channel,
+                        agent,
+                        to_agent: Vec::new(),
+                        to_server: Vec::new(),
+                        agent_closed: false,
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to connect to SSH agent {}: {}", self.socket.display(), e);
+                    unsafe { close_channel(channel) };
+                }
+            }
+        }
+
+        let mut progressed = false;
+        self.channels.retain_mut(|channel| {
+            let (moved, open) = unsafe { channel.pump() };
+            progressed |= moved;
+            if !open {
+                unsafe { close_channel(channel.channel) };
+            }
+            open
+        });
+        progressed
+    }
+}
+
+#[cfg(unix)]
+impl AgentChannel {
+    /// Copia i dati nei due sensi; restituisce (byte trasferiti, canale ancora aperto)
+    unsafe fn pump(&mut self) -> (bool, bool) {
+        let mut moved = false;
+        let mut buffer = [0u8; 8192];
+
+        loop {
+            let read = libssh2_sys::libssh2_channel_read_ex(
+                self.channel,
+                0,
+                buffer.as_mut_ptr() as *mut c_char,
+                buffer.len(),
+            );
+            if read > 0 {
+                self.to_agent.extend_from_slice(&buffer[..read as usize]);
+                moved = true;
+            } else if read == 0 || read as c_int == libssh2_sys::LIBSSH2_ERROR_EAGAIN {
+                break;
+            } else {
+                return (moved, false);
+            }
+        }
+        while !self.to_agent.is_empty() {
+            match self.agent.write(&self.to_agent) {
+                Ok(written) => {
+                    self.to_agent.drain(..written);
+                    moved = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return (moved, false),
+            }
+        }
+
+        while !self.agent_closed {
+            match self.agent.read(&mut buffer) {
+                Ok(0) => self.agent_closed = true,
+                Ok(read) => {
+                    self.to_server.extend_from_slice(&buffer[..read]);
+                    moved = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.agent_closed = true,
+            }
+        }
+        while !self.to_server.is_empty() {
+            let written = libssh2_sys::libssh2_channel_write_ex(
+                self.channel,
+                0,
+                self.to_server.as_ptr() as *const c_char,
+                self.to_server.len(),
+            );
+            if written > 0 {
+                self.to_server.drain(..written as usize);
+                moved = true;
+            } else if written as c_int == libssh2_sys::LIBSSH2_ERROR_EAGAIN {
+                break;
+            } else {
+                return (moved, false);
+            }
+        }
+
+        let server_closed = libssh2_sys::libssh2_channel_eof(self.channel) == 1 && self.to_agent.is_empty();
+        let agent_done = self.agent_closed && self.to_server.is_empty();
+        (moved, !(server_closed || agent_done))
+    }
+}
+
+#[cfg(unix)]
+/// Chiude e libera un canale dell'agente; gli errori (es. `EAGAIN`) si
+/// ignorano perché libssh2 libera comunque le risorse del canale
+unsafe fn close_channel(channel: *mut libssh2_sys::LIBSSH2_CHANNEL) {
+    libssh2_sys::libssh2_channel_send_eof(channel);
+    libssh2_sys::libssh2_channel_close(channel);
+    libssh2_sys::libssh2_channel_free(channel);
+}
+
+/// Senza socket Unix non c'è un agente da inoltrare
+#[cfg(not(unix))]
+pub struct AgentForwarder;
+
+#[cfg(not(unix))]
+impl AgentForwarder {
+    pub fn install(_session: &Session) -> Option<Self> {
+        warn!("SSH agent forwarding is only supported on Unix");
+        None
+    }
+
+    pub fn pump(&mut self, _session: &Session) -> bool {
+        false
+    }
+}
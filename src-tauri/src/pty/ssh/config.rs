@@ -0,0 +1,342 @@
+//! Lettura di `~/.ssh/config`
+//!
+//! Si interpretano solo le opzioni che servono a una sessione interattiva
+//! (`HostName`, `User`, `Port`, `IdentityFile`, `ForwardAgent`,
+//! `UserKnownHostsFile`) e le direttive `Include`; le altre sono ignorate.
+//! Come in OpenSSH, per ogni opzione vale il primo valore trovato tra i
+//! blocchi `Host` che corrispondono all'host richiesto. I blocchi `Match`
+//! non sono supportati e non corrispondono mai.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Profondità massima delle direttive `Include` annidate
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Opzioni risolte per un host
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostConfig {
+    /// Nome con cui è stato richiesto l'host (alias o nome reale)
+    pub host: String,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<PathBuf>,
+    pub forward_agent: Option<bool>,
+    pub user_known_hosts_file: Option<PathBuf>,
+}
+
+impl HostConfig {
+    /// Nome o indirizzo a cui collegarsi
+    pub fn hostname(&self) -> &str {
+        self.hostname.as_deref().unwrap_or(&self.host)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct HostBlock {
+    patterns: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl HostBlock {
+    fn matches(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        let mut matched = false;
+        for pattern in &self.patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) if wildcard_match(&negated.to_lowercase(), &host) => return false,
+                Some(_) => {}
+                None => matched |= wildcard_match(&pattern.to_lowercase(), &host),
+            }
+        }
+        matched
+    }
+}
+
+/// Contenuto di un file di configurazione SSH
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+impl SshConfig {
+    /// Legge il file indicato; un file assente equivale a una configurazione vuota
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config = Self::default();
+        if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read SSH config {}", path.display()))?;
+            config.parse_into(&content, path.parent(), 0, None);
+        }
+        Ok(config)
+    }
+
+    /// Interpreta il contenuto di un file; gli `Include` relativi partono da `base_dir`
+    pub fn parse(content: &str, base_dir: Option<&Path>) -> Self {
+        let mut config = Self::default();
+        config.parse_into(content, base_dir, 0, None);
+        config
+    }
+
+    /// `block` è l'indice del blocco che riceve le opzioni: come in OpenSSH
+    /// un file incluso parte dal blocco che contiene l'`Include`, e dopo
+    /// l'`Include` le opzioni tornano a quel blocco
+    fn parse_into(&mut self, content: &str, base_dir: Option<&Path>, depth: usize, mut block: Option<usize>) {
+        for line in content.lines() {
+            let Some((key, value)) = split_option(line) else {
+                continue;
+            };
+            match key.as_str() {
+                "host" => {
+                    self.blocks.push(HostBlock {
+                        patterns: value.split_whitespace().map(str::to_string).collect(),
+                        options: Vec::new(),
+                    });
+                    block = Some(self.blocks.len() - 1);
+                }
+                // Senza supporto ai criteri di `Match` il blocco viene saltato
+                "match" => {
+                    self.blocks.push(HostBlock {
+                        patterns: Vec::new(),
+                        options: Vec::new(),
+                    });
+                    block = Some(self.blocks.len() - 1);
+                }
+                "include" if depth < MAX_INCLUDE_DEPTH => {
+                    for pattern in value.split_whitespace() {
+                        for path in include_paths(pattern, base_dir) {
+                            if let Ok(included) = fs::read_to_string(&path) {
+                                self.parse_into(&included, base_dir, depth + 1, block);
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    // Le opzioni prima del primo `Host` valgono per tutti
+                    let index = *block.get_or_insert_with(|| {
+                        self.blocks.push(HostBlock {
+                            patterns: vec!["*".to_string()],
+                            options: Vec::new(),
+                        });
+                        self.blocks.len() - 1
+                    });
+                    self.blocks[index].options.push((key, value));
+                }
+            }
+        }
+    }
+
+    /// Opzioni valide per un host
+    pub fn resolve(&self, host: &str) -> HostConfig {
+        let mut config = HostConfig {
+            host: host.to_string(),
+            ..HostConfig::default()
+        };
+        for block in self.blocks.iter().filter(|block| block.matches(host)) {
+            for (key, value) in &block.options {
+                match key.as_str() {
+                    "hostname" if config.hostname.is_none() => {
+                        config.hostname = Some(value.replace("%h", host));
+                    }
+                    "user" if config.user.is_none() => config.user = Some(value.clone()),
+                    "port" if config.port.is_none() => config.port = value.parse().ok(),
+                    // `IdentityFile` si accumula invece di fermarsi al primo valore
+                    "identityfile" => config.identity_files.push(expand_path(value, host)),
+                    "forwardagent" if config.forward_agent.is_none() => {
+                        config.forward_agent = Some(value.eq_ignore_ascii_case("yes"));
+                    }
+                    "userknownhostsfile" if config.user_known_hosts_file.is_none() => {
+                        config.user_known_hosts_file = value
+                            .split_whitespace()
+                            .next()
+                            .map(|path| expand_path(path, host));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        config
+    }
+
+    /// Host con un nome esplicito, senza caratteri jolly, nell'ordine del file
+    pub fn hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = Vec::new();
+        let patterns = self.blocks.iter().flat_map(|block| block.patterns.iter());
+        for pattern in patterns {
+            let explicit = !pattern.contains(['*', '?', '!']);
+            if explicit && !hosts.contains(pattern) {
+                hosts.push(pattern.clone());
+            }
+        }
+        hosts
+    }
+}
+
+/// Percorso predefinito della configurazione dell'utente
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh").join("config"))
+}
+
+/// Divide una riga in opzione (minuscola) e valore; `None` per righe vuote e commenti
+fn split_option(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let key = line[..end].to_lowercase();
+    let value = line[end..].trim_start();
+    let value = value.strip_prefix('=').unwrap_or(value).trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    Some((key, value.to_string()))
+}
+
+/// File indicati da un `Include`; i caratteri jolly valgono solo nel nome del file
+fn include_paths(pattern: &str, base_dir: Option<&Path>) -> Vec<PathBuf> {
+    let path = expand_path(pattern, "");
+    let path = match base_dir {
+        Some(base_dir) if path.is_relative() => base_dir.join(path),
+        _ => path,
+    };
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string());
+    match (name, path.parent()) {
+        (Some(name), Some(dir)) if name.contains(['*', '?']) => {
+            let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| wildcard_match(&name, &entry.file_name().to_string_lossy()))
+                        .map(|entry| entry.path())
+                        .collect()
+                })
+                .unwrap_or_default();
+            paths.sort();
+            paths
+        }
+        _ => vec![path],
+    }
+}
+
+/// Espande `~`, `%d` (home) e `%h` (host richiesto)
+fn expand_path(value: &str, host: &str) -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_default();
+    let home = home.to_string_lossy();
+    let value = match value.strip_prefix('~') {
+        Some(rest) => format!("{}{}", home, rest),
+        None => value.to_string(),
+    };
+    PathBuf::from(value.replace("%d", &home).replace("%h", host))
+}
+
+/// Confronto con i caratteri jolly `*` e `?` di `ssh_config`
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Posizione dell'ultimo `*` e del testo da cui riprovare
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, from)) => {
+                    p = star + 1;
+                    t = from + 1;
+                    backtrack = Some((star, from + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+ForwardAgent no
+
+# Macchine di sviluppo
+Host dev dev-*
+    HostName %h.internal.example.com
+    User deploy
+    Port=2222
+    IdentityFile ~/.ssh/dev_ed25519
+
+Host dev-db !dev-legacy
+    User postgres
+    ForwardAgent yes
+
+Match exec \"true\"
+    User nobody
+
+Host *
+    User fallback
+    IdentityFile \"~/.ssh/id_ed25519\"
+";
+
+    #[test]
+    fn test_resolve_host() {
+        let config = SshConfig::parse(CONFIG, None);
+        let home = dirs::home_dir().unwrap_or_default();
+
+        let dev_db = config.resolve("dev-db");
+        assert_eq!(dev_db.hostname(), "dev-db.internal.example.com");
+        // Vale il primo valore trovato
+        assert_eq!(dev_db.user.as_deref(), Some("deploy"));
+        assert_eq!(dev_db.port, Some(2222));
+        assert_eq!(dev_db.forward_agent, Some(false));
+        assert_eq!(
+            dev_db.identity_files,
+            vec![home.join(".ssh/dev_ed25519"), home.join(".ssh/id_ed25519")]
+        );
+
+        let other = config.resolve("example.org");
+        assert_eq!(other.hostname(), "example.org");
+        assert_eq!(other.user.as_deref(), Some("fallback"));
+        assert_eq!(other.port, None);
+
+        assert_eq!(config.hosts(), vec!["dev".to_string(), "dev-db".to_string()]);
+    }
+
+    #[test]
+    fn test_include_keeps_enclosing_block() {
+        let dir = std::env::temp_dir().join(format!("termina-ssh-include-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("extra.conf"), "Port 2200\nHost other\n    User other\n").unwrap();
+
+        let config = SshConfig::parse("Host web\n    Include extra.conf\n    User webuser\n", Some(&dir));
+        let _ = fs::remove_dir_all(&dir);
+
+        let web = config.resolve("web");
+        assert_eq!(web.user.as_deref(), Some("webuser"));
+        assert_eq!(web.port, Some(2200));
+        assert_eq!(config.resolve("other").user.as_deref(), Some("other"));
+        assert_eq!(config.resolve("other").port, None);
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.example.com", "a.b.example.com"));
+        assert!(wildcard_match("web?", "web1"));
+        assert!(!wildcard_match("web?", "web10"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("dev-*", "dev"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Verifica delle chiavi degli host
+//!
+//! La chiave presentata dal server viene confrontata con `known_hosts`. Una
+//! chiave sconosciuta o cambiata non viene mai accettata in silenzio: la
+//! connessione si interrompe e l'interfaccia mostra l'impronta all'utente,
+//! che può riprovare confermandola. Una chiave nuova confermata viene
+//! aggiunta in coda al file; una chiave cambiata vale solo per la
+//! connessione in corso e il file resta da correggere a mano.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD};
+use base64::Engine;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, Session};
+
+/// Porta SSH predefinita, omessa nei nomi di `known_hosts`
+const DEFAULT_PORT: u16 = 22;
+
+/// Motivo per cui la chiave va confermata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostKeyStatus {
+    /// L'host non compare in `known_hosts`
+    Unknown,
+    /// L'host compare con una chiave diversa: possibile attacco
+    Changed,
+}
+
+/// Richiesta di conferma della chiave di un host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostKeyPrompt {
+    pub host: String,
+    pub port: u16,
+    pub key_type: String,
+    /// Impronta SHA256 nel formato di OpenSSH (`SHA256:...`)
+    pub fingerprint: String,
+    pub status: HostKeyStatus,
+    pub known_hosts: PathBuf,
+}
+
+/// Percorso predefinito di `known_hosts`
+pub fn default_known_hosts_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh").join("known_hosts"))
+}
+
+/// Controlla la chiave dell'host dopo l'handshake.
+///
+/// Restituisce la richiesta da mostrare all'utente se la chiave non è nota,
+/// a meno che `accepted` non sia proprio la sua impronta.
+pub fn verify(
+    session: &Session,
+    host: &str,
+    port: u16,
+    path: &Path,
+    accepted: Option<&str>,
+) -> Result<Option<HostKeyPrompt>> {
+    let (key, key_type) = session
+        .host_key()
+        .ok_or_else(|| anyhow!("Host {} did not present a host key", host))?;
+    let fingerprint = fingerprint(session).ok_or_else(|| anyhow!("Failed to hash host key of {}", host))?;
+
+    let mut known_hosts = session.known_hosts()?;
+    if path.exists() {
+        known_hosts
+            .read_file(path, KnownHostFileKind::OpenSSH)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+    }
+    let status = match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => return Ok(None),
+        CheckResult::NotFound => HostKeyStatus::Unknown,
+        CheckResult::Mismatch => HostKeyStatus::Changed,
+        CheckResult::Failure => return Err(anyhow!("Failed to check host key of {}", host)),
+    };
+
+    if accepted == Some(fingerprint.as_str()) {
+        match status {
+            HostKeyStatus::Unknown => {
+                append(path, host, port, key_type, key)?;
+                info!("Added host key of {} ({}) to {}", host, fingerprint, path.display());
+            }
+            HostKeyStatus::Changed => {
+                warn!("Accepted changed host key of {} ({}) for this connection only", host, fingerprint);
+            }
+        }
+        return Ok(None);
+    }
+
+    Ok(Some(HostKeyPrompt {
+        host: host.to_string(),
+        port,
+        key_type: key_type_name(key_type).to_string(),
+        fingerprint,
+        status,
+        known_hosts: path.to_path_buf(),
+    }))
+}
+
+/// Impronta SHA256 della chiave dell'host
+pub fn fingerprint(session: &Session) -> Option<String> {
+    let hash = session.host_key_hash(HashType::Sha256)?;
+    Some(format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+}
+
+/// Aggiunge una chiave in coda al file senza riscrivere le voci esistenti
+fn append(path: &Path, host: &str, port: u16, key_type: HostKeyType, key: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    // Il file potrebbe non terminare con un a capo
+    let needs_newline = fs::read(path).map(|content| content.last().is_some_and(|&last| last != b'\n'))?;
+    if needs_newline {
+        writeln!(file)?;
+    }
+    writeln!(
+        file,
+        "{} {} {}",
+        host_entry(host, port),
+        key_type_name(key_type),
+        BASE64.encode(key)
+    )?;
+    Ok(())
+}
+
+/// Nome dell'host come compare in `known_hosts`
+fn host_entry(host: &str, port: u16) -> String {
+    if port == DEFAULT_PORT {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+/// Host leggibili di `known_hosts` con la porta, se diversa da 22; le voci
+/// con nome cifrato (`HashKnownHosts`) non si possono elencare
+pub fn hosts(content: &str) -> Vec<(String, u16)> {
+    let mut hosts = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('@') || line.starts_with('|') {
+            continue;
+        }
+        let Some(names) = line.split_whitespace().next() else {
+            continue;
+        };
+        for name in names.split(',') {
+            if name.starts_with('!') || name.contains(['*', '?']) {
+                continue;
+            }
+            let entry = match name.strip_prefix('[').and_then(|rest| rest.split_once("]:")) {
+                Some((host, port)) => match port.parse() {
+                    Ok(port) => (host.to_string(), port),
+                    Err(_) => continue,
+                },
+                None => (name.to_string(), DEFAULT_PORT),
+            };
+            if !hosts.contains(&entry) {
+                hosts.push(entry);
+            }
+        }
+    }
+    hosts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_hosts_entries() {
+        let content = "\
+# commento
+github.com,140.82.121.4 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl
+[build.local]:2222 ssh-rsa AAAAB3NzaC1yc2E
+|1|c2FsdA==|aGFzaA== ssh-ed25519 AAAA
+@cert-authority *.example.com ssh-rsa AAAA
+*.corp,!bad.corp ssh-rsa AAAA
+github.com ssh-rsa AAAA
+";
+        assert_eq!(
+            hosts(content),
+            vec![
+                ("github.com".to_string(), 22),
+                ("140.82.121.4".to_string(), 22),
+                ("build.local".to_string(), 2222),
+            ]
+        );
+        assert_eq!(host_entry("build.local", 2222), "[build.local]:2222");
+        assert_eq!(host_entry("github.com", 22), "github.com");
+    }
+}
@@ -0,0 +1,693 @@
+//! Sessioni SSH native
+//!
+//! La connessione viene aperta da Rust con `ssh2` invece di digitare `ssh`
+//! in una shell locale: la sessione ha un PTY remoto che segue il resize
+//! (`window-change`), può inoltrare l'agente locale e passa per
+//! `TerminalStream`, quindi scrollback, comandi rilevati e directory
+//! comunicata con OSC 7 funzionano come per i PTY locali.
+//!
+//! La connessione avviene in due fasi: se la chiave dell'host non è in
+//! `known_hosts` o l'autenticazione richiede una password, `connect`
+//! restituisce una `SshPrompt` da mostrare all'utente e l'interfaccia
+//! riprova con la risposta (`accept_host_key`, `password`, ...).
+//!
+//! Il test contro un `sshd` reale si abilita con `TERMINA_TEST_SSHD`
+//! (`host:porta`, es. `127.0.0.1:2222`), con l'utente corrente autenticato
+//! dall'agente o dalle chiavi predefinite.
+
+pub mod agent;
+pub mod config;
+pub mod known_hosts;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ssh2::{Channel, ErrorCode, KeyboardInteractivePrompt, Prompt, Session};
+
+use self::agent::AgentForwarder;
+use self::config::{HostConfig, SshConfig};
+use self::known_hosts::HostKeyPrompt;
+use super::backend::SessionBackend;
+use super::events::SessionEventListener;
+use super::output::OutputSubscriber;
+use super::profiles::ShellProfile;
+use super::reaper::SessionActivity;
+use super::recording::unix_timestamp;
+use super::screen::ScreenSnapshot;
+use super::scrollback::{ScrollbackRead, DEFAULT_SCROLLBACK_LINES};
+use super::session::{ProcessExit, SessionStatus};
+use super::shell_integration::CommandRecord;
+use super::shutdown::ShutdownReport;
+use super::stream::TerminalStream;
+
+/// Timeout della connessione TCP e dell'autenticazione
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attesa massima di un comando quando la connessione è inattiva
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Intervallo dei keepalive inviati al server, in secondi
+const KEEPALIVE_INTERVAL: u32 = 30;
+/// Attesa dello stato di uscita dopo la fine dell'output
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Chiavi provate, nell'ordine, se la configurazione non ne indica
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Opzioni di connessione; i campi assenti vengono da `~/.ssh/config`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SshOptions {
+    /// Alias di `~/.ssh/config` o nome dell'host
+    pub host: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Passphrase delle chiavi private
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+    #[serde(default)]
+    pub forward_agent: Option<bool>,
+    /// Impronta della chiave dell'host confermata dall'utente
+    #[serde(default)]
+    pub accept_host_key: Option<String>,
+    #[serde(default)]
+    pub cols: Option<u16>,
+    #[serde(default)]
+    pub rows: Option<u16>,
+    #[serde(default)]
+    pub scrollback: Option<usize>,
+    #[serde(default)]
+    pub term: Option<String>,
+    /// Variabili richieste al server (soggette ad `AcceptEnv`)
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Comando inviato alla shell remota appena avviata
+    #[serde(default)]
+    pub startup_command: Option<String>,
+    #[serde(default)]
+    pub config_path: Option<PathBuf>,
+    #[serde(default)]
+    pub known_hosts_path: Option<PathBuf>,
+}
+
+impl SshOptions {
+    /// Applica un profilo di shell; della shell remota si possono usare solo
+    /// l'ambiente e il comando iniziale
+    pub fn apply_profile(&mut self, profile: &ShellProfile) {
+        for (key, value) in &profile.env {
+            self.env.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        if self.startup_command.is_none() {
+            self.startup_command = profile.startup_command.clone();
+        }
+    }
+}
+
+/// Profilo associato a un host in `terminal.ssh_profiles`, che associa alias
+/// o modelli con caratteri jolly (`"dev-*": "nix"`) a nomi di profilo; un
+/// alias esatto prevale sui modelli
+pub fn host_profile(ssh_profiles: Option<&Value>, host: &str) -> Option<String> {
+    let profiles = ssh_profiles?.as_object()?;
+    let name = profiles.get(host).or_else(|| {
+        profiles
+            .iter()
+            .find(|(pattern, _)| config::wildcard_match(pattern, host))
+            .map(|(_, name)| name)
+    })?;
+    name.as_str().filter(|name| !name.is_empty()).map(str::to_string)
+}
+
+/// Informazione da chiedere all'utente prima di riprovare la connessione
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SshPrompt {
+    /// Chiave dell'host sconosciuta o cambiata
+    HostKey(HostKeyPrompt),
+    /// Nessun metodo di autenticazione automatico è riuscito
+    Credentials {
+        user: String,
+        host: String,
+        /// Metodi accettati dal server (es. `publickey,password`)
+        methods: Vec<String>,
+    },
+}
+
+/// Esito di `connect`
+pub enum SshConnect {
+    Connected(SshSession),
+    Prompt(SshPrompt),
+}
+
+/// Host proposto all'utente
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SshHost {
+    /// Alias da passare come `host` a `connect`
+    pub name: String,
+    pub hostname: String,
+    pub user: Option<String>,
+    pub port: u16,
+    /// `config` o `known_hosts`
+    pub source: String,
+}
+
+/// Host di `~/.ssh/config` seguiti da quelli di `known_hosts` non già elencati
+pub fn hosts(config_path: Option<&Path>, known_hosts_path: Option<&Path>) -> Result<Vec<SshHost>> {
+    let config = match config_path.map(Path::to_path_buf).or_else(config::default_config_path) {
+        Some(path) => SshConfig::load(&path)?,
+        None => SshConfig::default(),
+    };
+
+    let mut hosts: Vec<SshHost> = config
+        .hosts()
+        .into_iter()
+        .map(|name| {
+            let resolved = config.resolve(&name);
+            SshHost {
+                hostname: resolved.hostname().to_string(),
+                user: resolved.user.clone(),
+                port: resolved.port.unwrap_or(22),
+                source: "config".to_string(),
+                name,
+            }
+        })
+        .collect();
+
+    let known_hosts_path = known_hosts_path
+        .map(Path::to_path_buf)
+        .or_else(known_hosts::default_known_hosts_path);
+    let content = match known_hosts_path {
+        Some(path) => fs::read_to_string(path).unwrap_or_default(),
+        None => String::new(),
+    };
+    for (host, port) in known_hosts::hosts(&content) {
+        let listed = hosts
+            .iter()
+            .any(|entry| (entry.name == host || entry.hostname == host) && entry.port == port);
+        if !listed {
+            hosts.push(SshHost {
+                name: if port == 22 { host.clone() } else { format!("{}:{}", host, port) },
+                hostname: host,
+                user: None,
+                port,
+                source: "known_hosts".to_string(),
+            });
+        }
+    }
+    Ok(hosts)
+}
+
+/// Apre una sessione SSH interattiva
+pub fn connect(session_id: String, options: &SshOptions) -> Result<SshConnect> {
+    let config_path = options.config_path.clone().or_else(config::default_config_path);
+    let config = match &config_path {
+        Some(path) => SshConfig::load(path)?,
+        None => SshConfig::default(),
+    };
+    // Un host `nome:porta` viene dall'elenco di `known_hosts`
+    let (alias, alias_port) = match options.host.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host, port.parse().ok()),
+        _ => (options.host.as_str(), None),
+    };
+    let host_config = config.resolve(alias);
+    let hostname = host_config.hostname().to_string();
+    let port = options.port.or(alias_port).or(host_config.port).unwrap_or(22);
+    let user = options
+        .user
+        .clone()
+        .or_else(|| host_config.user.clone())
+        .or_else(|| std::env::var("USER").ok())
+        .ok_or_else(|| anyhow!("No user specified for {}", options.host))?;
+
+    let address = (hostname.as_str(), port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", hostname))?
+        .next()
+        .ok_or_else(|| anyhow!("No address found for {}", hostname))?;
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}:{}", hostname, port))?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+    session
+        .handshake()
+        .with_context(|| format!("SSH handshake with {}:{} failed", hostname, port))?;
+
+    let known_hosts_path = options
+        .known_hosts_path
+        .clone()
+        .or_else(|| host_config.user_known_hosts_file.clone())
+        .or_else(known_hosts::default_known_hosts_path)
+        .ok_or_else(|| anyhow!("Cannot locate known_hosts"))?;
+    if let Some(prompt) = known_hosts::verify(
+        &session,
+        &hostname,
+        port,
+        &known_hosts_path,
+        options.accept_host_key.as_deref(),
+    )? {
+        info!("Host key of {}:{} needs confirmation", hostname, port);
+        return Ok(SshConnect::Prompt(SshPrompt::HostKey(prompt)));
+    }
+
+    if !authenticate(&session, &user, options, &host_config) {
+        let methods = session
+            .auth_methods(&user)
+            .map(|methods| methods.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        info!("Authentication of {}@{} needs credentials", user, hostname);
+        return Ok(SshConnect::Prompt(SshPrompt::Credentials {
+            user,
+            host: options.host.clone(),
+            methods,
+        }));
+    }
+
+    let forward_agent = options.forward_agent.or(host_config.forward_agent).unwrap_or(false);
+    let cols = options.cols.unwrap_or(80);
+    let rows = options.rows.unwrap_or(24);
+    let mut channel = session.channel_session()?;
+    let agent = if forward_agent {
+        let forwarder = AgentForwarder::install(&session);
+        match &forwarder {
+            Some(_) => channel.request_auth_agent_forwarding()?,
+            None => warn!("Agent forwarding requested for {} but SSH_AUTH_SOCK is not set", hostname),
+        }
+        forwarder
+    } else {
+        None
+    };
+    for (key, value) in &options.env {
+        // I server accettano solo le variabili elencate in `AcceptEnv`
+        if let Err(e) = channel.setenv(key, value) {
+            debug!("Server refused environment variable {}: {}", key, e);
+        }
+    }
+    let term = options.term.as_deref().unwrap_or("xterm-256color");
+    channel.request_pty(term, None, Some((cols as u32, rows as u32, 0, 0)))?;
+    channel.shell()?;
+    session.set_keepalive(true, KEEPALIVE_INTERVAL);
+    session.set_timeout(0);
+    session.set_blocking(false);
+
+    let target = format!("{}@{}:{}", user, hostname, port);
+    info!("Opened SSH session {} to {}", session_id, target);
+    let stream = Arc::new(TerminalStream::new(
+        session_id.clone(),
+        cols,
+        rows,
+        options.scrollback.unwrap_or(DEFAULT_SCROLLBACK_LINES),
+    ));
+    let (control, receiver) = mpsc::channel();
+    if let Some(command) = &options.startup_command {
+        let _ = control.send(Control::Input(format!("{}\n", command).into_bytes()));
+    }
+
+    let connection = Connection {
+        session,
+        channel,
+        agent,
+        stream: Arc::clone(&stream),
+        receiver,
+    };
+    thread::Builder::new()
+        .name(format!("ssh-{}", session_id))
+        .spawn(move || connection.run())?;
+
+    Ok(SshConnect::Connected(SshSession {
+        id: session_id,
+        target,
+        stream,
+        control,
+    }))
+}
+
+/// Prova, nell'ordine, agente, chiavi, password e keyboard-interactive
+fn authenticate(session: &Session, user: &str, options: &SshOptions, host_config: &HostConfig) -> bool {
+    if std::env::var_os("SSH_AUTH_SOCK").is_some() && session.userauth_agent(user).is_ok() {
+        debug!("Authenticated {} with the SSH agent", user);
+        return true;
+    }
+
+    let mut identities: Vec<PathBuf> = options.identity_file.iter().cloned().collect();
+    identities.extend(host_config.identity_files.iter().cloned());
+    if identities.is_empty() {
+        if let Some(home) = dirs::home_dir() {
+            identities.extend(DEFAULT_IDENTITIES.iter().map(|name| home.join(".ssh").join(name)));
+        }
+    }
+    for identity in identities.iter().filter(|path| path.exists()) {
+        match session.userauth_pubkey_file(user, None, identity, options.passphrase.as_deref()) {
+            Ok(()) => {
+                debug!("Authenticated {} with {}", user, identity.display());
+                return true;
+            }
+            Err(e) => debug!("Key {} rejected: {}", identity.display(), e),
+        }
+    }
+
+    if let Some(password) = &options.password {
+        if session.userauth_password(user, password).is_ok() {
+            return true;
+        }
+        let mut prompter = PasswordPrompter { password };
+        if session.userauth_keyboard_interactive(user, &mut prompter).is_ok() {
+            return true;
+        }
+    }
+    session.authenticated()
+}
+
+/// Risponde con la password alle richieste keyboard-interactive
+struct PasswordPrompter<'a> {
+    password: &'a str,
+}
+
+impl KeyboardInteractivePrompt for PasswordPrompter<'_> {
+    fn prompt<'a>(&mut self, _username: &str, _instructions: &str, prompts: &[Prompt<'a>]) -> Vec<String> {
+        prompts.iter().map(|_| self.password.to_string()).collect()
+    }
+}
+
+/// Richieste al thread della connessione
+enum Control {
+    Input(Vec<u8>),
+    Resize(u16, u16),
+    Close,
+}
+
+/// Stato posseduto dal thread della connessione: `ssh2` non permette
+/// di usare la stessa sessione da più thread in modo concorrente
+struct Connection {
+    session: Session,
+    channel: Channel,
+    agent: Option<AgentForwarder>,
+    stream: Arc<TerminalStream>,
+    receiver: Receiver<Control>,
+}
+
+impl Connection {
+    fn run(mut self) {
+        let exit_status = match self.pump() {
+            Ok(true) => self.exit_status(),
+            Ok(false) => {
+                let _ = self.session.disconnect(None, "Session closed", None);
+                ProcessExit::closed("SIGHUP", unix_timestamp())
+            }
+            Err(e) => {
+                warn!("SSH session {} failed: {}", self.stream.id, e);
+                ProcessExit::closed("connection lost", unix_timestamp())
+            }
+        };
+        info!("SSH session {} ended: {:?}", self.stream.id, exit_status);
+        self.stream.finish(exit_status);
+    }
+
+    /// Copia i dati fino alla fine dell'output (`true`) o a una chiusura
+    /// richiesta (`false`)
+    fn pump(&mut self) -> Result<bool> {
+        let mut pending: Vec<u8> = Vec::new();
+        let mut resize: Option<(u16, u16)> = None;
+        let mut buffer = [0u8; 8192];
+        let mut next_keepalive = Instant::now();
+
+        loop {
+            let mut progressed = false;
+
+            loop {
+                match self.receiver.try_recv() {
+                    Ok(Control::Input(data)) => pending.extend_from_slice(&data),
+                    Ok(Control::Resize(cols, rows)) => resize = Some((cols, rows)),
+                    Ok(Control::Close) | Err(mpsc::TryRecvError::Disconnected) => return Ok(false),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                }
+            }
+
+            if let Some((cols, rows)) = resize {
+                match self.channel.request_pty_size(cols as u32, rows as u32, None, None) {
+                    Ok(()) => resize = None,
+                    Err(e) if is_would_block(&e) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            while !pending.is_empty() {
+                match self.channel.write(&pending) {
+                    Ok(written) => {
+                        pending.drain(..written);
+                        progressed = true;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            loop {
+                match self.channel.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => {
+                        self.stream.process(&buffer[..read]);
+                        progressed = true;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if self.channel.eof() {
+                return Ok(true);
+            }
+
+            if let Some(agent) = &mut self.agent {
+                progressed |= agent.pump(&self.session);
+            }
+            if Instant::now() >= next_keepalive {
+                let wait = self.session.keepalive_send().unwrap_or(KEEPALIVE_INTERVAL);
+                next_keepalive = Instant::now() + Duration::from_secs(wait.max(1) as u64);
+            }
+
+            if !progressed {
+                match self.receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(Control::Input(data)) => pending.extend_from_slice(&data),
+                    Ok(Control::Resize(cols, rows)) => resize = Some((cols, rows)),
+                    Ok(Control::Close) | Err(RecvTimeoutError::Disconnected) => return Ok(false),
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+            }
+        }
+    }
+
+    /// Stato di uscita della shell remota, inviato dal server prima di
+    /// chiudere il canale
+    fn exit_status(&mut self) -> ProcessExit {
+        self.session.set_blocking(true);
+        self.session.set_timeout(CLOSE_TIMEOUT.as_millis() as u32);
+        let _ = self.channel.wait_close();
+        let signal = self.channel.exit_signal().ok().and_then(|signal| signal.exit_signal);
+        let exit_code = match signal {
+            Some(_) => None,
+            None => self.channel.exit_status().ok(),
+        };
+        let _ = self.session.disconnect(None, "Session closed", None);
+        ProcessExit {
+            success: exit_code == Some(0),
+            exit_code,
+            signal: signal.map(|signal| format!("SIG{}", signal)),
+            exited_at: unix_timestamp(),
+        }
+    }
+}
+
+fn is_would_block(error: &ssh2::Error) -> bool {
+    error.code() == ErrorCode::Session(libssh2_sys::LIBSSH2_ERROR_EAGAIN)
+}
+
+/// Sessione collegata a una shell remota
+pub struct SshSession {
+    pub id: String,
+    /// `utente@host:porta`
+    pub target: String,
+    pub stream: Arc<TerminalStream>,
+    control: Sender<Control>,
+}
+
+impl SshSession {
+    fn send(&self, control: Control) -> Result<()> {
+        self.control
+            .send(control)
+            .map_err(|_| anyhow!("SSH session {} is closed", self.id))
+    }
+}
+
+impl SessionBackend for SshSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn write(&self, data: &[u8]) -> Result<()> {
+        self.send(Control::Input(data.to_vec()))?;
+        self.stream.touch();
+        Ok(())
+    }
+
+    fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        self.stream.resize_screen(cols, rows);
+        self.send(Control::Resize(cols, rows))
+    }
+
+    fn read_output(&self, from_offset: u64) -> Result<ScrollbackRead> {
+        Ok(self.stream.read_output(from_offset))
+    }
+
+    fn subscribe_output(&self, subscriber: OutputSubscriber) -> Result<(u64, u64)> {
+        Ok(self.stream.subscribe_output(subscriber))
+    }
+
+    fn unsubscribe_output(&self, subscription_id: u64) -> bool {
+        self.stream.unsubscribe_output(subscription_id)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.stream.clear();
+        Ok(())
+    }
+
+    fn get_screen(&self, include_history: bool) -> Result<ScreenSnapshot> {
+        Ok(self.stream.get_screen(include_history))
+    }
+
+    fn status(&self) -> Result<SessionStatus> {
+        Ok(self.stream.status())
+    }
+
+    fn set_event_listener(&self, listener: SessionEventListener) {
+        self.stream.set_event_listener(listener)
+    }
+
+    fn close(&self) -> Result<ShutdownReport> {
+        let _ = self.control.send(Control::Close);
+        Ok(ShutdownReport {
+            session_id: self.id.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn kill(&self) -> Result<()> {
+        let _ = self.control.send(Control::Close);
+        Ok(())
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    /// Directory comunicata dalla shell remota con OSC 7
+    fn cwd(&self) -> Result<String> {
+        Ok(self.stream.cwd())
+    }
+
+    fn list_commands(&self) -> Result<Vec<CommandRecord>> {
+        Ok(self.stream.list_commands())
+    }
+
+    fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        self.stream.get_command_output(command_id)
+    }
+
+    fn activity(&self) -> Option<SessionActivity> {
+        Some(self.stream.activity())
+    }
+}
+
+impl Drop for SshSession {
+    fn drop(&mut self) {
+        let _ = self.control.send(Control::Close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_hosts_merge_config_and_known_hosts() {
+        let dir = std::env::temp_dir().join(format!("termina-ssh-hosts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config");
+        let known_hosts_path = dir.join("known_hosts");
+        fs::write(&config_path, "Host build\n    HostName build.local\n    Port 2222\n").unwrap();
+        fs::write(
+            &known_hosts_path,
+            "[build.local]:2222 ssh-ed25519 AAAA\ngithub.com ssh-ed25519 AAAA\n",
+        )
+        .unwrap();
+
+        let hosts = hosts(Some(&config_path), Some(&known_hosts_path)).unwrap();
+        let names: Vec<_> = hosts.iter().map(|host| (host.name.as_str(), host.source.as_str())).collect();
+        assert_eq!(names, vec![("build", "config"), ("github.com", "known_hosts")]);
+        assert_eq!(hosts[0].port, 2222);
+
+        let _ = fs::remove_dir_all(dir);
+
+        let profiles = json!({ "dev-*": "nix", "dev-db": "psql", "prod": "" });
+        assert_eq!(host_profile(Some(&profiles), "dev-db").as_deref(), Some("psql"));
+        assert_eq!(host_profile(Some(&profiles), "dev-web").as_deref(), Some("nix"));
+        assert_eq!(host_profile(Some(&profiles), "prod"), None);
+    }
+
+    /// Richiede un `sshd` in ascolto su `TERMINA_TEST_SSHD`
+    #[test]
+    #[ignore = "needs TERMINA_TEST_SSHD"]
+    fn test_loopback_sshd_session() {
+        let address = std::env::var("TERMINA_TEST_SSHD").expect("TERMINA_TEST_SSHD must be host:port");
+        let (host, port) = address.rsplit_once(':').expect("TERMINA_TEST_SSHD must be host:port");
+        let dir = std::env::temp_dir().join(format!("termina-ssh-sshd-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut options = SshOptions {
+            host: host.to_string(),
+            port: Some(port.parse().unwrap()),
+            known_hosts_path: Some(dir.join("known_hosts")),
+            config_path: Some(dir.join("config")),
+            cols: Some(100),
+            rows: Some(30),
+            ..Default::default()
+        };
+
+        // Prima connessione: la chiave va confermata
+        let fingerprint = match connect("ssh-test".to_string(), &options).unwrap() {
+            SshConnect::Prompt(SshPrompt::HostKey(prompt)) => prompt.fingerprint,
+            _ => panic!("unknown host key was accepted without confirmation"),
+        };
+        options.accept_host_key = Some(fingerprint);
+        let session = match connect("ssh-test".to_string(), &options).unwrap() {
+            SshConnect::Connected(session) => session,
+            SshConnect::Prompt(prompt) => panic!("unexpected prompt: {:?}", prompt),
+        };
+
+        session.write(b"stty size; exit 3\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while session.stream.is_active() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        let output = String::from_utf8_lossy(&session.read_output(0).unwrap().data).to_string();
+        assert!(output.contains("30 100"), "unexpected output: {}", output);
+        assert_eq!(session.stream.exit_status().and_then(|status| status.exit_code), Some(3));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,243 @@
+//! Terminale alimentato da un flusso di byte
+//!
+//! Le sessioni senza un PTY locale (SSH, porte seriali) ricevono l'output da
+//! una connessione invece che da un processo figlio. `TerminalStream` fa per
+//! loro quello che il reader fa per `RealPtySession`: conserva i byte nello
+//! scrollback, aggiorna lo schermo e i comandi rilevati, segue la directory
+//! comunicata con OSC 7 e pubblica l'output ai sottoscrittori.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+
+use super::cwd;
+use super::events::{SessionEvent, SessionEventListener};
+use super::output::{OutputBroadcaster, OutputSubscriber};
+use super::reaper::SessionActivity;
+use super::recording::unix_timestamp;
+use super::screen::{ScreenEvent, ScreenSnapshot, TerminalScreen};
+use super::scrollback::{ScrollbackBuffer, ScrollbackRead};
+use super::session::{ProcessExit, SessionStatus};
+use super::shell_integration::{CommandRecord, CommandTracker};
+use super::SessionState;
+
+/// Canale verso il batcher dell'output: (offset, byte)
+type BatchSender = Sender<(u64, Vec<u8>)>;
+
+/// Scrollback, schermo e sottoscrittori di una sessione senza PTY locale
+pub struct TerminalStream {
+    pub id: String,
+    pub buffer: Mutex<ScrollbackBuffer>,
+    pub screen: Mutex<TerminalScreen>,
+    pub commands: Mutex<CommandTracker>,
+    pub output: Arc<OutputBroadcaster>,
+    /// Rilasciato alla fine della connessione, così il batcher termina.
+    /// Il lock resta preso per tutto `process`, che può essere chiamato da
+    /// più thread (es. lettura ed eco locale)
+    batcher: Mutex<Option<BatchSender>>,
+    state: Mutex<SessionState>,
+    last_activity: Mutex<u64>,
+    active: AtomicBool,
+}
+
+impl TerminalStream {
+    pub fn new(id: String, cols: u16, rows: u16, scrollback: usize) -> Self {
+        let output = Arc::new(OutputBroadcaster::new(id.clone()));
+        let batcher = output.start_batcher();
+        Self {
+            id,
+            buffer: Mutex::new(ScrollbackBuffer::new(scrollback)),
+            screen: Mutex::new(TerminalScreen::new(cols, rows, scrollback)),
+            commands: Mutex::new(CommandTracker::new()),
+            output,
+            batcher: Mutex::new(Some(batcher)),
+            state: Mutex::new(SessionState::default()),
+            last_activity: Mutex::new(unix_timestamp()),
+            active: AtomicBool::new(true),
+        }
+    }
+
+    /// Elabora un blocco ricevuto dalla connessione. I blocchi raggiungono
+    /// scrollback, schermo e batcher nello stesso ordine: il batcher li
+    /// unisce sotto il primo offset
+    pub fn process(&self, data: &[u8]) {
+        let batcher = self.batcher.lock().unwrap();
+        let offset = self.buffer.lock().unwrap().append(data);
+        {
+            let mut screen = self.screen.lock().unwrap();
+            let mut commands = self.commands.lock().unwrap();
+            screen.process_with(data, |screen, event| {
+                commands.handle_event(screen, event);
+                if let ScreenEvent::Osc(params) = &event.event {
+                    if let Some(cwd) = cwd::parse_osc7(params) {
+                        let mut state = self.state.lock().unwrap();
+                        state.cwd_reported = true;
+                        state.update_cwd(&self.id, cwd);
+                    }
+                }
+            });
+        }
+        self.touch();
+        if let Some(batcher) = batcher.as_ref() {
+            let _ = batcher.send((offset, data.to_vec()));
+        }
+    }
+
+    /// Registra un'attività (es. input inviato)
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = unix_timestamp();
+    }
+
+    /// Segna la connessione come chiusa e notifica l'uscita al listener
+    pub fn finish(&self, exit_status: ProcessExit) {
+        self.active.store(false, Ordering::SeqCst);
+        self.batcher.lock().unwrap().take();
+        let mut state = self.state.lock().unwrap();
+        if state.exit_status.is_some() {
+            return;
+        }
+        state.exit_status = Some(exit_status.clone());
+        state.emit(SessionEvent::Exited {
+            session_id: self.id.clone(),
+            exit_status,
+        });
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    pub fn exit_status(&self) -> Option<ProcessExit> {
+        self.state.lock().unwrap().exit_status.clone()
+    }
+
+    /// Ultima directory comunicata con OSC 7
+    pub fn cwd(&self) -> String {
+        self.state.lock().unwrap().cwd.clone()
+    }
+
+    pub fn read_output(&self, from_offset: u64) -> ScrollbackRead {
+        self.buffer.lock().unwrap().read_from(from_offset)
+    }
+
+    pub fn get_screen(&self, include_history: bool) -> ScreenSnapshot {
+        self.screen.lock().unwrap().snapshot(include_history)
+    }
+
+    pub fn resize_screen(&self, cols: u16, rows: u16) {
+        self.screen.lock().unwrap().resize(cols, rows);
+    }
+
+    pub fn subscribe_output(&self, subscriber: OutputSubscriber) -> (u64, u64) {
+        self.output.subscribe_at(subscriber)
+    }
+
+    pub fn unsubscribe_output(&self, subscriber_id: u64) -> bool {
+        self.output.unsubscribe(subscriber_id)
+    }
+
+    pub fn clear(&self) {
+        self.buffer.lock().unwrap().clear();
+    }
+
+    /// Registra il listener; se la connessione è già chiusa l'evento di
+    /// uscita viene emesso subito
+    pub fn set_event_listener(&self, listener: SessionEventListener) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(status) = &state.exit_status {
+            listener(&SessionEvent::Exited {
+                session_id: self.id.clone(),
+                exit_status: status.clone(),
+            });
+        }
+        state.listener = Some(listener);
+    }
+
+    pub fn list_commands(&self) -> Vec<CommandRecord> {
+        self.commands.lock().unwrap().records()
+    }
+
+    pub fn get_command_output(&self, command_id: u64) -> Result<(CommandRecord, ScrollbackRead)> {
+        let record = self
+            .commands
+            .lock()
+            .unwrap()
+            .record(command_id)
+            .ok_or_else(|| anyhow!("Command {} not found in session {}", command_id, self.id))?;
+
+        let mut read = self.read_output(record.output_start);
+        if let Some(end) = record.output_end {
+            read.data.truncate(end.saturating_sub(read.start) as usize);
+            read.end = read.start + read.data.len() as u64;
+        }
+        Ok((record, read))
+    }
+
+    /// Stato della sessione; i comandi in corso sono noti solo se la shell
+    /// remota emette i marcatori dell'integrazione
+    pub fn status(&self) -> SessionStatus {
+        let exit_status = self.exit_status();
+        let current_command = self
+            .commands
+            .lock()
+            .unwrap()
+            .current_command()
+            .map(|record| record.command.clone());
+        SessionStatus {
+            id: self.id.clone(),
+            is_active: self.is_active(),
+            is_executing: exit_status.is_none() && current_command.is_some(),
+            current_command: current_command.unwrap_or_default(),
+            last_activity: *self.last_activity.lock().unwrap(),
+            buffer_size: self.buffer.lock().unwrap().len(),
+            cwd: self.cwd(),
+            pid: None,
+            exit_status,
+            foreground_process: None,
+            restored_at: None,
+            recording: None,
+            read_only: false,
+        }
+    }
+
+    pub fn activity(&self) -> SessionActivity {
+        SessionActivity {
+            session_id: self.id.clone(),
+            exited_at: self.exit_status().map(|status| status.exited_at),
+            reader_active: self.is_active(),
+            last_activity: *self.last_activity.lock().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_tracks_output_and_exit() {
+        let stream = TerminalStream::new("s".to_string(), 40, 10, 100);
+        stream.process(b"\x1b]7;file://host/srv/app\x07hello\r\n");
+        assert_eq!(stream.cwd(), "/srv/app");
+        assert!(stream.get_screen(false).plain_text().contains("hello"));
+        assert_eq!(stream.read_output(0).end, stream.buffer.lock().unwrap().end_offset());
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&events);
+        stream.set_event_listener(Arc::new(move |event: &SessionEvent| {
+            received.lock().unwrap().push(event.name());
+        }));
+        let exit = ProcessExit {
+            exit_code: Some(0),
+            signal: None,
+            success: true,
+            exited_at: 1,
+        };
+        stream.finish(exit.clone());
+        stream.finish(exit);
+        assert!(!stream.is_active());
+        assert_eq!(*events.lock().unwrap(), vec!["pty-exited"]);
+    }
+}
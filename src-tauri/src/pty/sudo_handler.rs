@@ -43,7 +43,7 @@ impl SudoHandler {
         let result = self.run_sudo_with_password(actual_command, password)?;
         
         // Invia il risultato alla sessione
-        if let Some(_session) = pty_manager.get_session(session_id) {
+        if pty_manager.has_session(session_id) {
             let output = if result.success {
                 result.output
             } else {
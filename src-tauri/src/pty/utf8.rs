@@ -0,0 +1,100 @@
+//! Decodifica UTF-8 a cavallo delle letture
+//!
+//! Il PTY restituisce blocchi di byte arbitrari: un carattere multibyte può
+//! essere spezzato tra due letture. Queste funzioni trattengono la sequenza
+//! incompleta finale invece di trasformarla in caratteri di sostituzione.
+
+/// Lunghezza del prefisso di `bytes` che non termina con una sequenza
+/// UTF-8 incompleta
+pub fn complete_prefix_len(bytes: &[u8]) -> usize {
+    let len = bytes.len();
+    for back in 1..=len.min(3) {
+        let byte = bytes[len - back];
+        if byte & 0xC0 == 0x80 {
+            // Byte di continuazione: cerca il byte iniziale
+            continue;
+        }
+        let needed = match byte {
+            0xF0..=0xF7 => 4,
+            0xE0..=0xEF => 3,
+            0xC0..=0xDF => 2,
+            _ => return len,
+        };
+        return if needed > back { len - back } else { len };
+    }
+    len
+}
+
+/// Numero di byte di continuazione all'inizio di `bytes` (resti di un
+/// carattere il cui inizio non è più disponibile)
+pub fn leading_continuation_len(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .take(3)
+        .take_while(|&&byte| byte & 0xC0 == 0x80)
+        .count()
+}
+
+/// Decoder incrementale che trattiene le sequenze incomplete tra un blocco
+/// e il successivo
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    carry: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodifica un blocco, rimandando al prossimo l'eventuale coda incompleta
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut data = std::mem::take(&mut self.carry);
+        data.extend_from_slice(bytes);
+
+        let complete = complete_prefix_len(&data);
+        self.carry = data.split_off(complete);
+        String::from_utf8_lossy(&data).into_owned()
+    }
+
+    /// Restituisce quanto trattenuto, anche se incompleto
+    pub fn flush(&mut self) -> String {
+        let carry = std::mem::take(&mut self.carry);
+        String::from_utf8_lossy(&carry).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_prefix_len() {
+        let euro = "€".as_bytes();
+        assert_eq!(complete_prefix_len(b"abc"), 3);
+        assert_eq!(complete_prefix_len(euro), 3);
+        assert_eq!(complete_prefix_len(&euro[..2]), 0);
+        assert_eq!(complete_prefix_len(&[b'a', euro[0]]), 1);
+    }
+
+    #[test]
+    fn test_decoder_carries_split_characters() {
+        let text = "caffè ☕ fatto";
+        let bytes = text.as_bytes();
+        let mut decoder = Utf8Decoder::new();
+
+        let mut decoded = String::new();
+        for chunk in bytes.chunks(1) {
+            decoded.push_str(&decoder.decode(chunk));
+        }
+        decoded.push_str(&decoder.flush());
+        assert_eq!(decoded, text);
+    }
+
+    #[test]
+    fn test_leading_continuation_len() {
+        let euro = "€".as_bytes();
+        assert_eq!(leading_continuation_len(&euro[1..]), 2);
+        assert_eq!(leading_continuation_len(b"abc"), 0);
+    }
+}